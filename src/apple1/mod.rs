@@ -0,0 +1,232 @@
+extern crate crossterm;
+extern crate emulator;
+
+use std::collections::VecDeque;
+use std::env;
+use std::fs;
+use std::io::{stdout, Stdout, Write};
+use std::process;
+
+use crossterm::event::{KeyCode, KeyModifiers};
+
+use cli::{fail, parse_address, read_file};
+use emulator::machines::apple1::{Apple1, Config, Ram, CLOCK};
+use terminal::{Input, Terminal};
+
+#[path = "../cli/mod.rs"]
+mod cli;
+#[path = "../cli/terminal.rs"]
+mod terminal;
+
+const USAGE: &str = "\
+Usage: apple1 [options] wozmon
+
+Options:
+  --ram 4|8             Kilobytes of RAM, 8 by default.
+  --load address:file   Load a file into RAM, such as Apple-1 BASIC at e000. Can be repeated.
+  --cassette file       Add the cassette interface, with this file as its ROM.
+  --tape file           Play this tape on the cassette interface.
+  --record file         Save what the cassette interface records to this file when done.
+  --slow                Show 60 characters per second, like the real display.
+
+The wozmon file holds the 256 bytes of ROM at ff00. Addresses are hexadecimal.
+Ctrl-R presses the reset button, and Ctrl-C quits.";
+
+/// Cycles to run between looking at the keyboard and the clock.
+const BATCH: u64 = 4000;
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    wozmon: String,
+    ram: Ram,
+    load: Vec<(u16, String)>,
+    cassette: Option<String>,
+    tape: Option<String>,
+    record: Option<String>,
+    slow: bool,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        wozmon: String::new(),
+        ram: Ram::EightK,
+        load: Vec::new(),
+        cassette: None,
+        tape: None,
+        record: None,
+        slow: false,
+    };
+    let mut wozmon = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}.", arg))
+        };
+
+        match arg.as_str() {
+            "--ram" => {
+                options.ram = match value()?.as_str() {
+                    "4" => Ram::FourK,
+                    "8" => Ram::EightK,
+                    ram => return Err(format!("Invalid amount of RAM '{}'.", ram)),
+                }
+            }
+            "--load" => {
+                let load = value()?;
+                let (address, file) = load
+                    .split_once(':')
+                    .ok_or_else(|| format!("Invalid load '{}'.", load))?;
+                options
+                    .load
+                    .push((parse_address(address)?, file.to_string()));
+            }
+            "--cassette" => options.cassette = Some(value()?.clone()),
+            "--tape" => options.tape = Some(value()?.clone()),
+            "--record" => options.record = Some(value()?.clone()),
+            "--slow" => options.slow = true,
+            _ if arg.starts_with("--") => return Err(format!("Unknown option '{}'.", arg)),
+            _ if wozmon.is_none() => wozmon = Some(arg.clone()),
+            _ => return Err(format!("Unexpected argument '{}'.", arg)),
+        }
+    }
+
+    if options.cassette.is_none() && (options.tape.is_some() || options.record.is_some()) {
+        return Err("A tape needs the cassette interface.".to_string());
+    }
+
+    options.wozmon = wozmon.ok_or_else(|| "No monitor ROM given.".to_string())?;
+    Ok(options)
+}
+
+/// A tape is the number of cycles between each change of level, separated by whitespace.
+fn parse_tape(text: &str) -> Result<Vec<u64>, String> {
+    text.split_whitespace()
+        .map(|s| s.parse().map_err(|_| format!("Invalid tape '{}'.", s)))
+        .collect()
+}
+
+fn format_tape(tape: &[u64]) -> String {
+    let lines: Vec<String> = tape.iter().map(|cycles| cycles.to_string()).collect();
+    lines.join("\n") + "\n"
+}
+
+/// The character a key types.
+fn character(code: KeyCode, _modifiers: KeyModifiers) -> Option<u8> {
+    match code {
+        KeyCode::Char(c) if c.is_ascii() => Some(c as u8),
+        KeyCode::Enter => Some(b'\n'),
+        KeyCode::Backspace => Some(0x08),
+        KeyCode::Esc => Some(0x1b),
+        _ => None,
+    }
+}
+
+/// Stdout in raw mode, which needs a carriage return to go with every newline.
+struct RawStdout(Stdout);
+
+impl Write for RawStdout {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for byte in buf {
+            match byte {
+                b'\n' => self.0.write_all(b"\r\n")?,
+                _ => self.0.write_all(&[*byte])?,
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = parse_options(&args).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(1)
+    });
+
+    let config = Config {
+        ram: options.ram,
+        wozmon: read_file(&options.wozmon),
+        cassette: options.cassette.as_ref().map(|f| read_file(f)),
+        slow_display: options.slow,
+    };
+    let mut apple1 =
+        Apple1::new(config, VecDeque::new(), RawStdout(stdout())).unwrap_or_else(|e| fail(e));
+    for (address, file) in options.load.iter() {
+        apple1
+            .load(&read_file(file), *address)
+            .unwrap_or_else(|e| fail(e));
+    }
+    if let (Some(file), Some(cassette)) = (&options.tape, &apple1.cassette) {
+        let text = String::from_utf8_lossy(&read_file(file)).to_string();
+        cassette
+            .borrow_mut()
+            .play(parse_tape(&text).unwrap_or_else(|e| fail(e)));
+    }
+
+    let terminal = Terminal::start(CLOCK, character);
+    'run: loop {
+        while let Some(input) = terminal.poll() {
+            match input {
+                Input::Key(c) => apple1.terminal.borrow_mut().input().push_back(c),
+                Input::Reset => apple1.reset(),
+                Input::Quit => break 'run,
+            }
+        }
+
+        apple1.run_until(apple1.emulator.cycles + BATCH);
+        terminal.wait(apple1.emulator.cycles);
+    }
+
+    drop(terminal);
+    println!();
+
+    if let (Some(file), Some(cassette)) = (&options.record, &apple1.cassette) {
+        let tape = format_tape(cassette.borrow().recording());
+        fs::write(file, tape).unwrap_or_else(|e| fail(format!("Cannot write {}: {}", file, e)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use emulator::machines::apple1::Ram;
+    use {format_tape, parse_options, parse_tape, Options};
+
+    fn options(args: &str) -> Result<Options, String> {
+        let args: Vec<String> = args.split_whitespace().map(|s| s.to_string()).collect();
+        parse_options(&args)
+    }
+
+    #[test]
+    fn test_options() {
+        let o =
+            options("--ram 4 --load e000:basic.bin --cassette aci.bin --slow wozmon.bin").unwrap();
+
+        assert_eq!("wozmon.bin", o.wozmon);
+        assert_eq!(Ram::FourK, o.ram);
+        assert_eq!(vec![(0xe000, "basic.bin".to_string())], o.load);
+        assert_eq!(Some("aci.bin".to_string()), o.cassette);
+        assert!(o.slow);
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        assert!(options("").is_err());
+        assert!(options("--ram 16 wozmon.bin").is_err());
+        assert!(options("--load e000 wozmon.bin").is_err());
+        assert!(options("--tape hello.tape wozmon.bin").is_err());
+        assert!(options("wozmon.bin basic.bin").is_err());
+    }
+
+    #[test]
+    fn test_tape() {
+        let tape = vec![650, 650, 250, 500];
+        assert_eq!(tape, parse_tape(&format_tape(&tape)).unwrap());
+        assert!(parse_tape("650 x").is_err());
+    }
+}
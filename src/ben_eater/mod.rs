@@ -0,0 +1,206 @@
+extern crate crossterm;
+extern crate emulator;
+
+use std::env;
+use std::io::{stdout, Write};
+use std::process;
+
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{KeyCode, KeyModifiers},
+    execute, queue,
+    style::Print,
+    terminal::{Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+
+use cli::{fail, read_file};
+use emulator::machines::ben_eater::{BenEater, CLOCK};
+use terminal::{Input, Terminal};
+
+#[path = "../cli/mod.rs"]
+mod cli;
+#[path = "../cli/terminal.rs"]
+mod terminal;
+
+const USAGE: &str = "\
+Usage: ben_eater [--cycles count] rom
+
+Options:
+  --cycles count   Run this many cycles without a terminal, then print the LCD.
+
+The rom file holds the 32K at 8000. The arrow keys press the buttons on PA0 to PA3
+(up, down, left, right), Enter or Space the one on PA4 and I the one on CA1. Ctrl-R resets,
+Ctrl-C quits.";
+
+/// Instructions to execute between looking at the keyboard and the clock.
+const BATCH: usize = 1000;
+
+/// How long a button stays pressed after a key, as the terminal does not tell when it is let go.
+const PRESS_CYCLES: u64 = CLOCK as u64 / 10;
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    rom: String,
+    cycles: Option<u64>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut rom = None;
+    let mut cycles = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--cycles" => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("Missing value for {}.", arg))?;
+                cycles = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid number of cycles '{}'.", value))?,
+                );
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option '{}'.", arg)),
+            _ if rom.is_none() => rom = Some(arg.clone()),
+            _ => return Err(format!("Unexpected argument '{}'.", arg)),
+        }
+    }
+
+    Ok(Options {
+        rom: rom.ok_or_else(|| "No ROM given.".to_string())?,
+        cycles,
+    })
+}
+
+enum Key {
+    Button(u8),
+    Interrupt,
+}
+
+fn key(code: KeyCode, _modifiers: KeyModifiers) -> Option<Key> {
+    let key = match code {
+        KeyCode::Up => Key::Button(0x01),
+        KeyCode::Down => Key::Button(0x02),
+        KeyCode::Left => Key::Button(0x04),
+        KeyCode::Right => Key::Button(0x08),
+        KeyCode::Enter | KeyCode::Char(' ') => Key::Button(0x10),
+        KeyCode::Char('i') => Key::Interrupt,
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// Draw the LCD with a frame around it, and the cursor when it is shown.
+fn draw<W: Write>(out: &mut W, computer: &BenEater) {
+    let text = computer.text();
+    let width = text.first().map_or(0, |line| line.chars().count());
+    let border = "─".repeat(width);
+
+    queue!(out, MoveTo(0, 0), Print(format!("┌{}┐", border))).unwrap();
+    for (i, line) in text.iter().enumerate() {
+        queue!(out, MoveTo(0, i as u16 + 1), Print(format!("│{}│", line))).unwrap();
+    }
+    queue!(
+        out,
+        MoveTo(0, text.len() as u16 + 1),
+        Print(format!("└{}┘", border))
+    )
+    .unwrap();
+
+    match computer.lcd.cursor() {
+        Some((line, column)) => {
+            queue!(out, MoveTo(column as u16 + 1, line as u16 + 1), Show).unwrap()
+        }
+        None => queue!(out, Hide).unwrap(),
+    }
+    out.flush().unwrap();
+}
+
+fn run_headless(mut computer: BenEater, cycles: u64) {
+    while computer.emulator.cycles < cycles && !computer.emulator.is_trapped() {
+        computer.step();
+    }
+    for line in computer.text() {
+        println!("{}", line);
+    }
+}
+
+fn run_terminal(mut computer: BenEater) {
+    let terminal = Terminal::start(CLOCK, key);
+    let mut out = stdout();
+    execute!(out, EnterAlternateScreen, Clear(ClearType::All)).unwrap();
+
+    let mut release = 0;
+    'run: loop {
+        while let Some(input) = terminal.poll() {
+            match input {
+                Input::Key(Key::Button(button)) => {
+                    computer.set_buttons(button);
+                    release = computer.emulator.cycles + PRESS_CYCLES;
+                }
+                Input::Key(Key::Interrupt) => {
+                    computer.set_interrupt_button(true);
+                    release = computer.emulator.cycles + PRESS_CYCLES;
+                }
+                Input::Reset => computer.emulator.reset(),
+                Input::Quit => break 'run,
+            }
+        }
+
+        for _ in 0..BATCH {
+            computer.step();
+        }
+        if release != 0 && computer.emulator.cycles >= release {
+            computer.set_buttons(0);
+            computer.set_interrupt_button(false);
+            release = 0;
+        }
+        draw(&mut out, &computer);
+
+        terminal.wait(computer.emulator.cycles);
+    }
+
+    execute!(out, Show, LeaveAlternateScreen).unwrap();
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = parse_options(&args).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(1)
+    });
+
+    let computer = BenEater::new(read_file(&options.rom)).unwrap_or_else(|e| fail(e));
+
+    match options.cycles {
+        Some(cycles) => run_headless(computer, cycles),
+        None => run_terminal(computer),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {parse_options, Options};
+
+    fn options(args: &str) -> Result<Options, String> {
+        let args: Vec<String> = args.split_whitespace().map(|s| s.to_string()).collect();
+        parse_options(&args)
+    }
+
+    #[test]
+    fn test_options() {
+        assert_eq!(
+            Options {
+                rom: "rom.bin".to_string(),
+                cycles: Some(100000),
+            },
+            options("--cycles 100000 rom.bin").unwrap()
+        );
+        assert_eq!(None, options("rom.bin").unwrap().cycles);
+
+        assert!(options("").is_err());
+        assert!(options("--cycles x rom.bin").is_err());
+        assert!(options("rom.bin other.bin").is_err());
+    }
+}
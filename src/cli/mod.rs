@@ -0,0 +1,42 @@
+//! Helpers shared by the command line programs. They are not part of the library, as they exit
+//! the process on errors: each binary includes this file with `#[path]`, and the machines that
+//! run in a terminal also include `terminal.rs`.
+
+// Not every binary uses every helper.
+#![allow(dead_code)]
+
+use std::fs;
+use std::process;
+
+/// Parse a hexadecimal address, optionally prefixed with `$`.
+pub fn parse_address(s: &str) -> Result<u16, String> {
+    u16::from_str_radix(s.trim_start_matches('$'), 16)
+        .map_err(|_| format!("Invalid address '{}'.", s))
+}
+
+/// Print an error and exit.
+pub fn fail(error: String) -> ! {
+    eprintln!("{}", error);
+    process::exit(1)
+}
+
+/// Read a whole file, or exit if it cannot be read.
+pub fn read_file(file: &str) -> Vec<u8> {
+    fs::read(file).unwrap_or_else(|e| fail(format!("Cannot read {}: {}", file, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use cli::parse_address;
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(Ok(0xfffc), parse_address("fffc"));
+        assert_eq!(Ok(0x0200), parse_address("$200"));
+        assert_eq!(
+            Err("Invalid address 'xyz'.".to_string()),
+            parse_address("xyz")
+        );
+        assert!(parse_address("10000").is_err());
+    }
+}
@@ -0,0 +1,90 @@
+//! Running a machine in the terminal: raw mode, a thread reading keys, and keeping to the speed
+//! of the real machine.
+
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossterm::event::{read, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+
+/// A key pressed in the terminal. Every machine resets with Ctrl-R and quits with Ctrl-C, and
+/// gives the other keys a meaning of its own.
+pub enum Input<K> {
+    Key(K),
+    Reset,
+    Quit,
+}
+
+/// A machine running in the terminal, which is in raw mode until this is dropped.
+pub struct Terminal<K> {
+    receiver: Receiver<Input<K>>,
+    start: Instant,
+    clock: u32,
+}
+
+impl<K: Send + 'static> Terminal<K> {
+    /// Read keys in a thread, with `key` telling what the ones other than Ctrl-R and Ctrl-C are,
+    /// for a machine running at `clock` cycles per second.
+    pub fn start<F>(clock: u32, key: F) -> Terminal<K>
+    where
+        F: Fn(KeyCode, KeyModifiers) -> Option<K> + Send + 'static,
+    {
+        let (sender, receiver) = channel();
+        thread::spawn(move || keys(sender, key));
+        enable_raw_mode().unwrap();
+
+        Terminal {
+            receiver,
+            start: Instant::now(),
+            clock,
+        }
+    }
+}
+
+impl<K> Terminal<K> {
+    /// The next key that was pressed, if any.
+    pub fn poll(&self) -> Option<Input<K>> {
+        match self.receiver.try_recv() {
+            Ok(input) => Some(input),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Input::Quit),
+        }
+    }
+
+    /// Keep to the speed of the real machine, by sleeping until it would have run `cycles`.
+    pub fn wait(&self, cycles: u64) {
+        let due = Duration::from_secs_f64(cycles as f64 / self.clock as f64);
+        if let Some(ahead) = due.checked_sub(self.start.elapsed()) {
+            thread::sleep(ahead);
+        }
+    }
+}
+
+impl<K> Drop for Terminal<K> {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+    }
+}
+
+fn keys<K, F: Fn(KeyCode, KeyModifiers) -> Option<K>>(sender: Sender<Input<K>>, key: F) {
+    loop {
+        let input = match read() {
+            Ok(Event::Key(KeyEvent { code, modifiers })) => match code {
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => Input::Quit,
+                KeyCode::Char('r') if modifiers.contains(KeyModifiers::CONTROL) => Input::Reset,
+                _ => match key(code, modifiers) {
+                    Some(key) => Input::Key(key),
+                    None => continue,
+                },
+            },
+            Ok(_) => continue,
+            Err(_) => Input::Quit,
+        };
+
+        let quit = matches!(input, Input::Quit);
+        if sender.send(input).is_err() || quit {
+            break;
+        }
+    }
+}
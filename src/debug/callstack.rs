@@ -0,0 +1,344 @@
+//! A shadow call stack, kept by following JSR, RTS, interrupts and RTI.
+//!
+//! 6502 code often uses the stack in ways that do not match up calls and returns: pushing an
+//! address and executing RTS to jump through a table, or pulling a return address to never
+//! return. Returns are therefore matched to frames by the address they return to, and anything
+//! that does not match is recorded as an `Anomaly` rather than trusted.
+
+use std::collections::VecDeque;
+
+use crate::debug::symbols::SymbolTable;
+use crate::emulator::instructions::opcodes::*;
+use crate::emulator::observer::{Execution, Interrupt, Observer};
+use crate::emulator::registers::Registers;
+
+/// The number of anomalies kept, the oldest being forgotten first.
+const MAX_ANOMALIES: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameKind {
+    Subroutine,
+    Interrupt(Interrupt),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub kind: FrameKind,
+    /// The JSR or BRK instruction, or the instruction that was interrupted.
+    pub call_site: u16,
+    /// The subroutine or interrupt handler.
+    pub entry: u16,
+    /// Where the matching RTS or RTI is expected to continue.
+    pub return_address: u16,
+    /// The stack pointer after the return address (and status) were pushed.
+    pub stack_pointer: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Anomaly {
+    /// The RTS or RTI at `address` continued at `target`, which no frame returns to. This is
+    /// what jumping by means of RTS looks like.
+    UnexpectedReturn { address: u16, target: u16 },
+    /// The RTS or RTI at `address` returned past `discarded` frames, for example because their
+    /// return addresses were pulled off the stack.
+    Unwound { address: u16, discarded: usize },
+    /// The TXS at `address` moved the stack pointer past `discarded` frames.
+    StackReset { address: u16, discarded: usize },
+}
+
+pub struct CallStack {
+    frames: Vec<Frame>,
+    anomalies: VecDeque<Anomaly>,
+    /// The address of the last byte pushed, to know the stack pointer when an interrupt is taken.
+    last_push: u16,
+}
+
+impl CallStack {
+    pub fn new() -> CallStack {
+        CallStack {
+            frames: Vec::new(),
+            anomalies: VecDeque::new(),
+            last_push: 0x100,
+        }
+    }
+
+    /// The frames, outermost first.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// The most recent anomalies, oldest first.
+    pub fn anomalies(&self) -> impl Iterator<Item = &Anomaly> {
+        self.anomalies.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.anomalies.clear();
+    }
+
+    /// A backtrace for a crash report or debugger, starting at `program_counter` and followed by
+    /// the call sites, innermost first.
+    pub fn backtrace(&self, program_counter: u16, symbols: &SymbolTable) -> String {
+        let mut backtrace = format!(
+            "#0  {:04x}  {}\n",
+            program_counter,
+            location(program_counter, symbols)
+        );
+
+        for (i, frame) in self.frames.iter().rev().enumerate() {
+            let kind = match frame.kind {
+                FrameKind::Subroutine => "jsr",
+                FrameKind::Interrupt(Interrupt::Brk) => "brk",
+                FrameKind::Interrupt(Interrupt::Irq) => "irq",
+                FrameKind::Interrupt(Interrupt::Nmi) => "nmi",
+            };
+
+            backtrace += &format!(
+                "#{:<2} {:04x}  {} ({} {})\n",
+                i + 1,
+                frame.call_site,
+                location(frame.call_site, symbols),
+                kind,
+                location(frame.entry, symbols)
+            );
+        }
+
+        backtrace
+    }
+
+    fn anomaly(&mut self, anomaly: Anomaly) {
+        if self.anomalies.len() == MAX_ANOMALIES {
+            self.anomalies.pop_front();
+        }
+        self.anomalies.push_back(anomaly);
+    }
+
+    fn call(&mut self, execution: &Execution, registers: &Registers) {
+        let entry = registers.program_counter;
+        self.frames.push(Frame {
+            kind: FrameKind::Subroutine,
+            call_site: execution.address,
+            entry,
+            return_address: execution.address.wrapping_add(3),
+            stack_pointer: registers.stack_pointer,
+        });
+    }
+
+    fn ret(&mut self, address: u16, target: u16) {
+        match self.frames.iter().rposition(|f| f.return_address == target) {
+            Some(index) => {
+                let discarded = self.frames.len() - index - 1;
+                if discarded > 0 {
+                    self.anomaly(Anomaly::Unwound { address, discarded });
+                }
+                self.frames.truncate(index);
+            }
+            None => self.anomaly(Anomaly::UnexpectedReturn { address, target }),
+        }
+    }
+
+    fn reset_stack(&mut self, address: u16, stack_pointer: u8) {
+        let live = self
+            .frames
+            .iter()
+            .rposition(|f| f.stack_pointer >= stack_pointer)
+            .map_or(0, |index| index + 1);
+
+        let discarded = self.frames.len() - live;
+        if discarded > 0 {
+            self.anomaly(Anomaly::StackReset { address, discarded });
+            self.frames.truncate(live);
+        }
+    }
+}
+
+impl Default for CallStack {
+    fn default() -> CallStack {
+        CallStack::new()
+    }
+}
+
+impl Observer for CallStack {
+    fn after_instruction(&mut self, execution: &Execution, registers: &Registers) {
+        match execution.opcode {
+            JSR::ABSOLUTE => self.call(execution, registers),
+            RTS::IMPLIED | RTI::IMPLIED => self.ret(execution.address, registers.program_counter),
+            TXS::IMPLIED => self.reset_stack(execution.address, registers.stack_pointer),
+            _ => {}
+        }
+    }
+
+    fn interrupt(&mut self, interrupt: Interrupt, return_address: u16, handler: u16) {
+        // BRK is followed by a padding byte, and the return address skips it.
+        let call_site = match interrupt {
+            Interrupt::Brk => return_address.wrapping_sub(2),
+            Interrupt::Irq | Interrupt::Nmi => return_address,
+        };
+
+        self.frames.push(Frame {
+            kind: FrameKind::Interrupt(interrupt),
+            call_site,
+            entry: handler,
+            return_address,
+            stack_pointer: self.last_push.wrapping_sub(1) as u8,
+        });
+    }
+
+    fn push(&mut self, address: u16, _value: u8) {
+        self.last_push = address;
+    }
+}
+
+fn location(address: u16, symbols: &SymbolTable) -> String {
+    match symbols.nearest(address) {
+        Some((name, 0)) => name.to_string(),
+        Some((name, offset)) => format!("{}+${:x}", name, offset),
+        None => format!("${:04x}", address),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debug::callstack::{Anomaly, CallStack, FrameKind};
+    use crate::debug::symbols::SymbolTable;
+    use crate::emulator::instructions::opcodes::*;
+    use crate::emulator::observer::Interrupt;
+    use crate::emulator::Emulator;
+    use crate::memory::default::DefaultMemory;
+
+    fn emulator(program: Vec<Vec<u8>>) -> Emulator<DefaultMemory, CallStack> {
+        let mut memory = DefaultMemory::empty();
+        memory.load(program.concat(), 0x600);
+        memory.set_program_counter(0x600);
+
+        Emulator::with_observer(memory, CallStack::new())
+    }
+
+    fn step(e: &mut Emulator<DefaultMemory, CallStack>, count: usize) {
+        for _ in 0..count {
+            e.execute_next();
+        }
+    }
+
+    #[test]
+    fn test_calls() {
+        let mut e = emulator(vec![JSR::absolute(0x700), NOP::implied()]);
+        e.memory
+            .load([JSR::absolute(0x710), RTS::implied()].concat(), 0x700);
+        e.memory.load(RTS::implied(), 0x710);
+
+        step(&mut e, 2);
+        let frames = e.observer.frames();
+        assert_eq!(2, frames.len());
+        assert_eq!(0x600, frames[0].call_site);
+        assert_eq!(0x710, frames[1].entry);
+        assert_eq!(0x703, frames[1].return_address);
+        assert_eq!(0xfb, frames[1].stack_pointer);
+
+        step(&mut e, 2);
+        assert!(e.observer.frames().is_empty());
+        assert_eq!(0, e.observer.anomalies().count());
+    }
+
+    #[test]
+    fn test_interrupts() {
+        let mut e = emulator(vec![BRK::immediate(0), NOP::implied()]);
+        e.memory.load(vec![0x00, 0x07], 0xfffe);
+        e.memory.load(RTI::implied(), 0x700);
+
+        step(&mut e, 1);
+        let frame = e.observer.frames()[0];
+        assert_eq!(FrameKind::Interrupt(Interrupt::Brk), frame.kind);
+        assert_eq!(0x600, frame.call_site);
+        assert_eq!(0x602, frame.return_address);
+        assert_eq!(0xfc, frame.stack_pointer);
+
+        step(&mut e, 1);
+        assert!(e.observer.frames().is_empty());
+    }
+
+    #[test]
+    fn test_rts_as_jump() {
+        // Push $06ff and return to $0700.
+        let mut e = emulator(vec![
+            LDA::immediate(0x06),
+            PHA::implied(),
+            LDA::immediate(0xff),
+            PHA::implied(),
+            RTS::implied(),
+        ]);
+
+        step(&mut e, 5);
+
+        assert_eq!(0x700, e.registers.program_counter);
+        assert_eq!(
+            vec![&Anomaly::UnexpectedReturn {
+                address: 0x606,
+                target: 0x700
+            }],
+            e.observer.anomalies().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_pulled_return_address() {
+        // The inner subroutine drops its return address and returns to the outer caller.
+        let mut e = emulator(vec![JSR::absolute(0x700), NOP::implied()]);
+        e.memory
+            .load([JSR::absolute(0x710), RTS::implied()].concat(), 0x700);
+        e.memory.load(
+            [PLA::implied(), PLA::implied(), RTS::implied()].concat(),
+            0x710,
+        );
+
+        step(&mut e, 5);
+
+        assert_eq!(0x603, e.registers.program_counter);
+        assert!(e.observer.frames().is_empty());
+        assert_eq!(
+            vec![&Anomaly::Unwound {
+                address: 0x712,
+                discarded: 1
+            }],
+            e.observer.anomalies().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_stack_reset() {
+        let mut e = emulator(vec![JSR::absolute(0x700)]);
+        e.memory
+            .load([LDX::immediate(0xff), TXS::implied()].concat(), 0x700);
+
+        step(&mut e, 3);
+
+        assert!(e.observer.frames().is_empty());
+        assert_eq!(
+            vec![&Anomaly::StackReset {
+                address: 0x702,
+                discarded: 1
+            }],
+            e.observer.anomalies().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let mut e = emulator(vec![JSR::absolute(0x700)]);
+        e.memory
+            .load([NOP::implied(), JSR::absolute(0x710)].concat(), 0x700);
+        step(&mut e, 3);
+
+        let mut symbols = SymbolTable::new();
+        symbols.insert("main", 0x600);
+        symbols.insert("outer", 0x700);
+
+        assert_eq!(
+            "#0  0710  outer+$10\n\
+             #1  0701  outer+$1 (jsr outer+$10)\n\
+             #2  0600  main (jsr outer)\n",
+            e.observer.backtrace(e.registers.program_counter, &symbols)
+        );
+    }
+}
@@ -0,0 +1,333 @@
+//! Record which addresses were executed as code, read as data or written.
+//!
+//! `CoverageMemory` wraps the memory of the emulator to see every read and write, and `step` or
+//! `run` execute instructions so that the bytes of each instruction are marked as executed
+//! rather than read.
+
+use std::cell::{Cell, Ref, RefCell};
+use std::io;
+use std::io::Write;
+
+use crate::debug::disassembler::{decode, disassemble};
+use crate::debug::listing::SourceMap;
+use crate::debug::symbols::SymbolTable;
+use crate::emulator::observer::Observer;
+use crate::emulator::run::StopReason;
+use crate::emulator::Emulator;
+use crate::memory::Memory;
+
+pub const EXECUTED: u8 = 0x1;
+pub const READ: u8 = 0x2;
+pub const WRITTEN: u8 = 0x4;
+
+pub struct Coverage {
+    flags: Vec<u8>,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage {
+            flags: vec![0; 0x10000],
+        }
+    }
+
+    /// The `EXECUTED`, `READ` and `WRITTEN` bits of an address.
+    pub fn flags(&self, address: u16) -> u8 {
+        self.flags[address as usize]
+    }
+
+    pub fn is_executed(&self, address: u16) -> bool {
+        self.flags(address) & EXECUTED != 0
+    }
+
+    pub fn is_read(&self, address: u16) -> bool {
+        self.flags(address) & READ != 0
+    }
+
+    pub fn is_written(&self, address: u16) -> bool {
+        self.flags(address) & WRITTEN != 0
+    }
+
+    pub fn mark(&mut self, address: u16, flags: u8) {
+        self.flags[address as usize] |= flags;
+    }
+
+    pub fn clear(&mut self) {
+        self.flags.iter_mut().for_each(|f| *f = 0);
+    }
+
+    /// A listing of the memory from `start` to `end`, where each line starts with the flags
+    /// (`x`, `r` and `w`) of the byte or instruction. Executed code is disassembled, and
+    /// anything else is shown as bytes.
+    pub fn listing<M: Memory>(
+        &self,
+        memory: &M,
+        symbols: &SymbolTable,
+        start: u16,
+        end: u16,
+    ) -> String {
+        let mut listing = String::new();
+        let mut address = start;
+
+        loop {
+            if let Some(name) = symbols.name(address) {
+                listing += &format!("{}:\n", name);
+            }
+
+            let length = if self.is_executed(address) {
+                let instruction = disassemble(memory, address);
+                let bytes: Vec<String> = instruction
+                    .bytes()
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect();
+
+                listing += &format!(
+                    "{}  {:04x}  {:<9} {}\n",
+                    describe(self.flags(address)),
+                    address,
+                    bytes.join(" "),
+                    instruction.format(|a| symbols.label(a))
+                );
+                instruction.length()
+            } else {
+                let byte = memory.peek(address);
+                listing += &format!(
+                    "{}  {:04x}  {:<9} .byte ${:02x}\n",
+                    describe(self.flags(address)),
+                    address,
+                    format!("{:02x}", byte),
+                    byte
+                );
+                1
+            };
+
+            match address.checked_add(length) {
+                Some(next) if next <= end && next > address => address = next,
+                _ => break,
+            }
+        }
+
+        listing
+    }
+
+    /// Write an lcov tracefile with the coverage of every source line in `source_map` that
+    /// produced bytes. A line is hit when any of its bytes was executed, read or written.
+    pub fn write_lcov<W: Write>(&self, out: &mut W, source_map: &SourceMap) -> io::Result<()> {
+        writeln!(out, "TN:")?;
+
+        for (index, file) in source_map.files().iter().enumerate() {
+            let lines: Vec<(u32, bool)> = source_map
+                .lines()
+                .filter(|(source, _)| source.file == index)
+                .map(|(source, addresses)| {
+                    let hit = addresses.iter().any(|a| self.flags(*a) != 0);
+                    (source.line, hit)
+                })
+                .collect();
+
+            if lines.is_empty() {
+                continue;
+            }
+
+            writeln!(out, "SF:{}", file)?;
+            for (line, hit) in &lines {
+                writeln!(out, "DA:{},{}", line, *hit as u8)?;
+            }
+            writeln!(out, "LF:{}", lines.len())?;
+            writeln!(out, "LH:{}", lines.iter().filter(|(_, hit)| *hit).count())?;
+            writeln!(out, "end_of_record")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Coverage {
+    fn default() -> Coverage {
+        Coverage::new()
+    }
+}
+
+fn describe(flags: u8) -> String {
+    [(EXECUTED, 'x'), (READ, 'r'), (WRITTEN, 'w')]
+        .iter()
+        .map(|(flag, c)| if flags & flag != 0 { *c } else { '-' })
+        .collect()
+}
+
+/// Memory that records reads and writes, except for reading the instruction being executed.
+pub struct CoverageMemory<M: Memory> {
+    pub memory: M,
+    coverage: RefCell<Coverage>,
+    /// The address and length of the instruction being executed.
+    instruction: Cell<(u16, u16)>,
+}
+
+impl<M: Memory> CoverageMemory<M> {
+    pub fn new(memory: M) -> CoverageMemory<M> {
+        CoverageMemory {
+            memory,
+            coverage: RefCell::new(Coverage::new()),
+            instruction: Cell::new((0, 0)),
+        }
+    }
+
+    pub fn coverage(&self) -> Ref<'_, Coverage> {
+        self.coverage.borrow()
+    }
+
+    pub fn into_coverage(self) -> Coverage {
+        self.coverage.into_inner()
+    }
+
+    pub fn clear(&mut self) {
+        self.coverage.borrow_mut().clear();
+    }
+}
+
+impl<M: Memory> Memory for CoverageMemory<M> {
+    fn read(&self, address: u16) -> u8 {
+        let (start, length) = self.instruction.get();
+        if address.wrapping_sub(start) >= length {
+            self.coverage.borrow_mut().mark(address, READ);
+        }
+
+        self.memory.read(address)
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.memory.peek(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.coverage.get_mut().mark(address, WRITTEN);
+        self.memory.write(address, value)
+    }
+}
+
+/// Execute the next instruction, marking its bytes as executed.
+pub fn step<M: Memory, O: Observer>(emulator: &mut Emulator<CoverageMemory<M>, O>) {
+    let address = emulator.next_instruction_address();
+    let opcode = emulator.memory.memory.peek(address);
+    let length = 1 + decode(opcode).map_or(0, |(_, operand)| operand.length());
+
+    emulator.memory.instruction.set((address, length));
+    emulator.execute_next();
+    emulator.memory.instruction.set((0, 0));
+
+    let coverage = emulator.memory.coverage.get_mut();
+    for i in 0..length {
+        coverage.mark(address.wrapping_add(i), EXECUTED);
+    }
+}
+
+/// Execute instructions until a trap is detected or `max_instructions` have been executed,
+/// like `Emulator::run`.
+pub fn run<M: Memory, O: Observer>(
+    emulator: &mut Emulator<CoverageMemory<M>, O>,
+    max_instructions: u64,
+) -> StopReason {
+    for _ in 0..max_instructions {
+        step(emulator);
+
+        if emulator.is_trapped() {
+            return StopReason::Trap(emulator.registers.program_counter);
+        }
+    }
+
+    StopReason::InstructionLimit
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debug::coverage::{run, CoverageMemory, EXECUTED, READ, WRITTEN};
+    use crate::debug::listing::SourceMap;
+    use crate::debug::symbols::SymbolTable;
+    use crate::emulator::instructions::opcodes::*;
+    use crate::emulator::Emulator;
+    use crate::memory::default::DefaultMemory;
+
+    fn program() -> Emulator<CoverageMemory<DefaultMemory>> {
+        let mut memory = DefaultMemory::empty();
+        memory.load(
+            [
+                LDA::absolute(0x0700),
+                STA::absolute(0x0701),
+                BEQ::relative(3),
+                JMP::absolute(0x060b),
+                JMP::absolute(0x060b),
+            ]
+            .concat(),
+            0x600,
+        );
+        memory.set_program_counter(0x600);
+
+        Emulator::new(CoverageMemory::new(memory))
+    }
+
+    #[test]
+    fn test_flags() {
+        let mut e = program();
+        run(&mut e, 100);
+
+        let coverage = e.memory.coverage();
+        assert_eq!(EXECUTED, coverage.flags(0x600));
+        assert_eq!(EXECUTED, coverage.flags(0x602));
+        assert_eq!(READ, coverage.flags(0x700));
+        assert_eq!(WRITTEN, coverage.flags(0x701));
+        // Branched over.
+        assert_eq!(0, coverage.flags(0x608));
+        // The reset vector.
+        assert_eq!(READ, coverage.flags(0xfffc));
+    }
+
+    #[test]
+    fn test_listing() {
+        let mut e = program();
+        run(&mut e, 100);
+
+        let mut symbols = SymbolTable::new();
+        symbols.insert("done", 0x60b);
+
+        assert_eq!(
+            "x--  0600  ad 00 07  lda $0700\n\
+             x--  0603  8d 01 07  sta $0701\n\
+             x--  0606  f0 03     beq done\n\
+             ---  0608  4c        .byte $4c\n\
+             ---  0609  0b        .byte $0b\n\
+             ---  060a  06        .byte $06\n\
+             done:\n\
+             x--  060b  4c 0b 06  jmp done\n",
+            e.memory
+                .coverage()
+                .listing(&e.memory.memory, &symbols, 0x600, 0x60b)
+        );
+    }
+
+    #[test]
+    fn test_lcov() {
+        let mut e = program();
+        run(&mut e, 100);
+
+        let source_map = SourceMap::parse(
+            "Source: \"test.asm\"\n\
+             00:0600 AD0007          \t     1:     lda data\n\
+             00:0603 8D0107          \t     2:     sta data+1\n\
+             00:0606 F003            \t     3:     beq done\n\
+             00:0608 4C0B06          \t     4:     jmp done\n\
+             00:060B 4C0B06          \t     5: done: jmp done\n",
+        );
+
+        let mut out = Vec::new();
+        e.memory
+            .coverage()
+            .write_lcov(&mut out, &source_map)
+            .unwrap();
+
+        assert_eq!(
+            "TN:\nSF:test.asm\nDA:1,1\nDA:2,1\nDA:3,1\nDA:4,0\nDA:5,1\nLF:5\nLH:4\nend_of_record\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
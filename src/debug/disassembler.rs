@@ -0,0 +1,398 @@
+//! Turns machine code back into assembly, one instruction at a time.
+
+use std::fmt;
+
+use crate::emulator::instructions::opcodes::*;
+use crate::memory::Memory;
+
+use self::Operand::*;
+
+/// The form of an instruction's operand, which also determines its length.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Operand {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Relative,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndexedIndirect,
+    IndirectIndexed,
+}
+
+impl Operand {
+    /// Number of bytes following the opcode.
+    pub fn length(self) -> u16 {
+        match self {
+            Implied | Accumulator => 0,
+            Absolute | AbsoluteX | AbsoluteY | Indirect => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// The mnemonic and operand for an opcode, if it is a documented instruction.
+pub fn decode(opcode: u8) -> Option<(&'static str, Operand)> {
+    let decoded = match opcode {
+        ADC::IMMEDIATE => ("adc", Immediate),
+        ADC::ZEROPAGE => ("adc", ZeroPage),
+        ADC::ZEROPAGEX => ("adc", ZeroPageX),
+        ADC::ABSOLUTE => ("adc", Absolute),
+        ADC::ABSOLUTEX => ("adc", AbsoluteX),
+        ADC::ABSOLUTEY => ("adc", AbsoluteY),
+        ADC::INDEXEDINDIRECT => ("adc", IndexedIndirect),
+        ADC::INDIRECTINDEXED => ("adc", IndirectIndexed),
+        AND::IMMEDIATE => ("and", Immediate),
+        AND::ZEROPAGE => ("and", ZeroPage),
+        AND::ZEROPAGEX => ("and", ZeroPageX),
+        AND::ABSOLUTE => ("and", Absolute),
+        AND::ABSOLUTEX => ("and", AbsoluteX),
+        AND::ABSOLUTEY => ("and", AbsoluteY),
+        AND::INDEXEDINDIRECT => ("and", IndexedIndirect),
+        AND::INDIRECTINDEXED => ("and", IndirectIndexed),
+        ASL::ACCUMULATOR => ("asl", Accumulator),
+        ASL::ZEROPAGE => ("asl", ZeroPage),
+        ASL::ZEROPAGEX => ("asl", ZeroPageX),
+        ASL::ABSOLUTE => ("asl", Absolute),
+        ASL::ABSOLUTEX => ("asl", AbsoluteX),
+        BCC::RELATIVE => ("bcc", Relative),
+        BCS::RELATIVE => ("bcs", Relative),
+        BEQ::RELATIVE => ("beq", Relative),
+        BIT::ZEROPAGE => ("bit", ZeroPage),
+        BIT::ABSOLUTE => ("bit", Absolute),
+        BMI::RELATIVE => ("bmi", Relative),
+        BNE::RELATIVE => ("bne", Relative),
+        BPL::RELATIVE => ("bpl", Relative),
+        BRK::IMMEDIATE => ("brk", Implied),
+        BVC::RELATIVE => ("bvc", Relative),
+        BVS::RELATIVE => ("bvs", Relative),
+        CLC::IMPLIED => ("clc", Implied),
+        CLD::IMPLIED => ("cld", Implied),
+        CLI::IMPLIED => ("cli", Implied),
+        CLV::IMPLIED => ("clv", Implied),
+        CMP::IMMEDIATE => ("cmp", Immediate),
+        CMP::ZEROPAGE => ("cmp", ZeroPage),
+        CMP::ZEROPAGEX => ("cmp", ZeroPageX),
+        CMP::ABSOLUTE => ("cmp", Absolute),
+        CMP::ABSOLUTEX => ("cmp", AbsoluteX),
+        CMP::ABSOLUTEY => ("cmp", AbsoluteY),
+        CMP::INDEXEDINDIRECT => ("cmp", IndexedIndirect),
+        CMP::INDIRECTINDEXED => ("cmp", IndirectIndexed),
+        CPX::IMMEDIATE => ("cpx", Immediate),
+        CPX::ZEROPAGE => ("cpx", ZeroPage),
+        CPX::ABSOLUTE => ("cpx", Absolute),
+        CPY::IMMEDIATE => ("cpy", Immediate),
+        CPY::ZEROPAGE => ("cpy", ZeroPage),
+        CPY::ABSOLUTE => ("cpy", Absolute),
+        DEC::ZEROPAGE => ("dec", ZeroPage),
+        DEC::ZEROPAGEX => ("dec", ZeroPageX),
+        DEC::ABSOLUTE => ("dec", Absolute),
+        DEC::ABSOLUTEX => ("dec", AbsoluteX),
+        DEX::IMPLIED => ("dex", Implied),
+        DEY::IMPLIED => ("dey", Implied),
+        EOR::IMMEDIATE => ("eor", Immediate),
+        EOR::ZEROPAGE => ("eor", ZeroPage),
+        EOR::ZEROPAGEX => ("eor", ZeroPageX),
+        EOR::ABSOLUTE => ("eor", Absolute),
+        EOR::ABSOLUTEX => ("eor", AbsoluteX),
+        EOR::ABSOLUTEY => ("eor", AbsoluteY),
+        EOR::INDEXEDINDIRECT => ("eor", IndexedIndirect),
+        EOR::INDIRECTINDEXED => ("eor", IndirectIndexed),
+        INC::ZEROPAGE => ("inc", ZeroPage),
+        INC::ZEROPAGEX => ("inc", ZeroPageX),
+        INC::ABSOLUTE => ("inc", Absolute),
+        INC::ABSOLUTEX => ("inc", AbsoluteX),
+        INX::IMPLIED => ("inx", Implied),
+        INY::IMPLIED => ("iny", Implied),
+        JMP::ABSOLUTE => ("jmp", Absolute),
+        JMP::INDIRECT => ("jmp", Indirect),
+        JSR::ABSOLUTE => ("jsr", Absolute),
+        LDA::IMMEDIATE => ("lda", Immediate),
+        LDA::ZEROPAGE => ("lda", ZeroPage),
+        LDA::ZEROPAGEX => ("lda", ZeroPageX),
+        LDA::ABSOLUTE => ("lda", Absolute),
+        LDA::ABSOLUTEX => ("lda", AbsoluteX),
+        LDA::ABSOLUTEY => ("lda", AbsoluteY),
+        LDA::INDEXEDINDIRECT => ("lda", IndexedIndirect),
+        LDA::INDIRECTINDEXED => ("lda", IndirectIndexed),
+        LDX::IMMEDIATE => ("ldx", Immediate),
+        LDX::ZEROPAGE => ("ldx", ZeroPage),
+        LDX::ZEROPAGEY => ("ldx", ZeroPageY),
+        LDX::ABSOLUTE => ("ldx", Absolute),
+        LDX::ABSOLUTEY => ("ldx", AbsoluteY),
+        LDY::IMMEDIATE => ("ldy", Immediate),
+        LDY::ZEROPAGE => ("ldy", ZeroPage),
+        LDY::ZEROPAGEX => ("ldy", ZeroPageX),
+        LDY::ABSOLUTE => ("ldy", Absolute),
+        LDY::ABSOLUTEX => ("ldy", AbsoluteX),
+        LSR::ACCUMULATOR => ("lsr", Accumulator),
+        LSR::ZEROPAGE => ("lsr", ZeroPage),
+        LSR::ZEROPAGEX => ("lsr", ZeroPageX),
+        LSR::ABSOLUTE => ("lsr", Absolute),
+        LSR::ABSOLUTEX => ("lsr", AbsoluteX),
+        NOP::IMPLIED => ("nop", Implied),
+        ORA::IMMEDIATE => ("ora", Immediate),
+        ORA::ZEROPAGE => ("ora", ZeroPage),
+        ORA::ZEROPAGEX => ("ora", ZeroPageX),
+        ORA::ABSOLUTE => ("ora", Absolute),
+        ORA::ABSOLUTEX => ("ora", AbsoluteX),
+        ORA::ABSOLUTEY => ("ora", AbsoluteY),
+        ORA::INDEXEDINDIRECT => ("ora", IndexedIndirect),
+        ORA::INDIRECTINDEXED => ("ora", IndirectIndexed),
+        PHA::IMPLIED => ("pha", Implied),
+        PHP::IMPLIED => ("php", Implied),
+        PLA::IMPLIED => ("pla", Implied),
+        PLP::IMPLIED => ("plp", Implied),
+        ROL::ACCUMULATOR => ("rol", Accumulator),
+        ROL::ZEROPAGE => ("rol", ZeroPage),
+        ROL::ZEROPAGEX => ("rol", ZeroPageX),
+        ROL::ABSOLUTE => ("rol", Absolute),
+        ROL::ABSOLUTEX => ("rol", AbsoluteX),
+        ROR::ACCUMULATOR => ("ror", Accumulator),
+        ROR::ZEROPAGE => ("ror", ZeroPage),
+        ROR::ZEROPAGEX => ("ror", ZeroPageX),
+        ROR::ABSOLUTE => ("ror", Absolute),
+        ROR::ABSOLUTEX => ("ror", AbsoluteX),
+        RTI::IMPLIED => ("rti", Implied),
+        RTS::IMPLIED => ("rts", Implied),
+        SBC::IMMEDIATE => ("sbc", Immediate),
+        SBC::ZEROPAGE => ("sbc", ZeroPage),
+        SBC::ZEROPAGEX => ("sbc", ZeroPageX),
+        SBC::ABSOLUTE => ("sbc", Absolute),
+        SBC::ABSOLUTEX => ("sbc", AbsoluteX),
+        SBC::ABSOLUTEY => ("sbc", AbsoluteY),
+        SBC::INDEXEDINDIRECT => ("sbc", IndexedIndirect),
+        SBC::INDIRECTINDEXED => ("sbc", IndirectIndexed),
+        SEC::IMPLIED => ("sec", Implied),
+        SED::IMPLIED => ("sed", Implied),
+        SEI::IMPLIED => ("sei", Implied),
+        STA::ZEROPAGE => ("sta", ZeroPage),
+        STA::ZEROPAGEX => ("sta", ZeroPageX),
+        STA::ABSOLUTE => ("sta", Absolute),
+        STA::ABSOLUTEX => ("sta", AbsoluteX),
+        STA::ABSOLUTEY => ("sta", AbsoluteY),
+        STA::INDEXEDINDIRECT => ("sta", IndexedIndirect),
+        STA::INDIRECTINDEXED => ("sta", IndirectIndexed),
+        STX::ZEROPAGE => ("stx", ZeroPage),
+        STX::ZEROPAGEY => ("stx", ZeroPageY),
+        STX::ABSOLUTE => ("stx", Absolute),
+        STY::ZEROPAGE => ("sty", ZeroPage),
+        STY::ZEROPAGEX => ("sty", ZeroPageX),
+        STY::ABSOLUTE => ("sty", Absolute),
+        TAX::IMPLIED => ("tax", Implied),
+        TAY::IMPLIED => ("tay", Implied),
+        TSX::IMPLIED => ("tsx", Implied),
+        TXA::IMPLIED => ("txa", Implied),
+        TXS::IMPLIED => ("txs", Implied),
+        TYA::IMPLIED => ("tya", Implied),
+        _ => return None,
+    };
+
+    Some(decoded)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub address: u16,
+    pub opcode: u8,
+    pub mnemonic: Option<&'static str>,
+    pub operand: Operand,
+    /// Operand value, as a single byte or a little endian word.
+    pub value: u16,
+}
+
+impl Instruction {
+    /// Number of bytes taken up by the instruction. Unknown opcodes take up a single byte.
+    pub fn length(&self) -> u16 {
+        1 + self.operand.length()
+    }
+
+    /// The address the instruction refers to, if any. Branch displacements are resolved.
+    pub fn target(&self) -> Option<u16> {
+        match self.operand {
+            Implied | Accumulator | Immediate => None,
+            Relative => Some(
+                self.address
+                    .wrapping_add(2)
+                    .wrapping_add(self.value as u8 as i8 as u16),
+            ),
+            _ => Some(self.value),
+        }
+    }
+
+    /// Raw bytes of the instruction.
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![self.opcode];
+        if self.operand.length() > 0 {
+            bytes.push(self.value as u8);
+        }
+        if self.operand.length() > 1 {
+            bytes.push((self.value >> 8) as u8);
+        }
+
+        bytes
+    }
+
+    /// Format the instruction, naming the address it refers to with `label` where possible.
+    pub fn format<F: Fn(u16) -> Option<String>>(&self, label: F) -> String {
+        let mnemonic = match self.mnemonic {
+            Some(m) => m,
+            None => return format!(".byte ${:02x}", self.opcode),
+        };
+
+        let target = || {
+            let address = self.target().unwrap_or(self.value);
+            label(address).unwrap_or_else(|| match self.operand {
+                ZeroPage | ZeroPageX | ZeroPageY | IndexedIndirect | IndirectIndexed => {
+                    format!("${:02x}", address)
+                }
+                _ => format!("${:04x}", address),
+            })
+        };
+
+        let operand = match self.operand {
+            Implied => return mnemonic.to_string(),
+            Accumulator => "a".to_string(),
+            Immediate => format!("#${:02x}", self.value),
+            ZeroPage | Relative | Absolute => target(),
+            ZeroPageX | AbsoluteX => format!("{},x", target()),
+            ZeroPageY | AbsoluteY => format!("{},y", target()),
+            Indirect => format!("({})", target()),
+            IndexedIndirect => format!("({},x)", target()),
+            IndirectIndexed => format!("({}),y", target()),
+        };
+
+        format!("{} {}", mnemonic, operand)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format(|_| None))
+    }
+}
+
+/// Disassemble the instruction at the given address.
+pub fn disassemble<M: Memory>(memory: &M, address: u16) -> Instruction {
+    let opcode = memory.peek(address);
+    let (mnemonic, operand) = match decode(opcode) {
+        Some((mnemonic, operand)) => (Some(mnemonic), operand),
+        None => (None, Implied),
+    };
+
+    let least = memory.peek(address.wrapping_add(1)) as u16;
+    let most = memory.peek(address.wrapping_add(2)) as u16;
+    let value = match operand.length() {
+        0 => 0,
+        1 => least,
+        _ => most << 8 | least,
+    };
+
+    Instruction {
+        address,
+        opcode,
+        mnemonic,
+        operand,
+        value,
+    }
+}
+
+/// Disassemble `count` consecutive instructions starting at the given address.
+pub fn disassemble_range<M: Memory>(memory: &M, address: u16, count: usize) -> Vec<Instruction> {
+    let mut instructions = Vec::with_capacity(count);
+    let mut address = address;
+
+    for _ in 0..count {
+        let instruction = disassemble(memory, address);
+        address = address.wrapping_add(instruction.length());
+        instructions.push(instruction);
+    }
+
+    instructions
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debug::disassembler::{decode, disassemble, disassemble_range, Operand};
+    use crate::emulator::instructions::opcodes::*;
+    use crate::memory::default::DefaultMemory;
+
+    fn disassemble_bytes(bytes: Vec<u8>) -> String {
+        let mut memory = DefaultMemory::empty();
+        memory.load(bytes, 0x600);
+
+        disassemble(&memory, 0x600).to_string()
+    }
+
+    #[test]
+    fn test_decode_all_documented() {
+        let documented = (0..=255u8).filter(|o| decode(*o).is_some()).count();
+        assert_eq!(151, documented);
+    }
+
+    #[test]
+    fn test_operands() {
+        assert_eq!("nop", disassemble_bytes(NOP::implied()));
+        assert_eq!("asl a", disassemble_bytes(ASL::accumulator()));
+        assert_eq!("lda #$42", disassemble_bytes(LDA::immediate(0x42)));
+        assert_eq!("sta $10", disassemble_bytes(STA::zero_page(0x10)));
+        assert_eq!("sta $10,x", disassemble_bytes(STA::zero_page_x(0x10)));
+        assert_eq!("ldx $10,y", disassemble_bytes(LDX::zero_page_y(0x10)));
+        assert_eq!("jmp $abcd", disassemble_bytes(JMP::absolute(0xabcd)));
+        assert_eq!("lda $abcd,x", disassemble_bytes(LDA::absolute_x(0xabcd)));
+        assert_eq!("lda $abcd,y", disassemble_bytes(LDA::absolute_y(0xabcd)));
+        assert_eq!("jmp ($abcd)", disassemble_bytes(JMP::indirect(0xabcd)));
+        assert_eq!(
+            "lda ($10,x)",
+            disassemble_bytes(LDA::indexed_indirect(0x10))
+        );
+        assert_eq!(
+            "lda ($10),y",
+            disassemble_bytes(LDA::indirect_indexed(0x10))
+        );
+        assert_eq!(".byte $02", disassemble_bytes(vec![0x02]));
+    }
+
+    #[test]
+    fn test_branch_target() {
+        assert_eq!("beq $0600", disassemble_bytes(BEQ::relative(-2)));
+        assert_eq!("bne $0612", disassemble_bytes(BNE::relative(0x10)));
+    }
+
+    #[test]
+    fn test_labels() {
+        let mut memory = DefaultMemory::empty();
+        memory.load(JSR::absolute(0x8010), 0x600);
+
+        let instruction = disassemble(&memory, 0x600);
+        let formatted = instruction.format(|a| match a {
+            0x8010 => Some("seed".to_string()),
+            _ => None,
+        });
+
+        assert_eq!("jsr seed", formatted);
+        assert_eq!(Some(0x8010), instruction.target());
+        assert_eq!(vec![0x20, 0x10, 0x80], instruction.bytes());
+    }
+
+    #[test]
+    fn test_range() {
+        let mut memory = DefaultMemory::empty();
+        let program: Vec<u8> = vec![LDA::immediate(1), STA::absolute(0x200), NOP::implied()]
+            .into_iter()
+            .flatten()
+            .collect();
+        memory.load(program, 0x600);
+
+        let instructions = disassemble_range(&memory, 0x600, 3);
+        let addresses: Vec<u16> = instructions.iter().map(|i| i.address).collect();
+
+        assert_eq!(vec![0x600, 0x602, 0x605], addresses);
+        assert_eq!(Operand::Implied, instructions[2].operand);
+    }
+}
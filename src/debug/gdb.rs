@@ -0,0 +1,636 @@
+//! A stub for the GDB remote serial protocol, which allows a debugger frontend to control the
+//! emulator over a TCP connection.
+//!
+//! Registers are exposed in the order A, X, Y, P (status), S (stack pointer) and PC. All are 8
+//! bits wide except for the 16-bit PC, and the layout is described in a target description.
+//! Stock GDB has no 6502 architecture to apply it to, so the debugger side needs a build or
+//! frontend that knows the 6502. The stub is only tested against the protocol itself.
+
+use std::collections::HashSet;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::emulator::observer::{NoObserver, Observer};
+use crate::emulator::Emulator;
+use crate::memory::Memory;
+
+const TARGET_XML: &str = "<?xml version=\"1.0\"?>\
+<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+<target version=\"1.0\">\
+<feature name=\"org.6502.cpu\">\
+<reg name=\"a\" bitsize=\"8\" regnum=\"0\"/>\
+<reg name=\"x\" bitsize=\"8\"/>\
+<reg name=\"y\" bitsize=\"8\"/>\
+<reg name=\"p\" bitsize=\"8\"/>\
+<reg name=\"s\" bitsize=\"8\"/>\
+<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>\
+</feature>\
+</target>";
+
+/// Stopped by a breakpoint, single step or trap.
+const SIGTRAP: &str = "S05";
+
+/// Stopped at the request of the debugger.
+const SIGINT: &str = "S02";
+
+/// Sent by the debugger to interrupt a running target.
+const INTERRUPT: u8 = 0x03;
+
+/// Number of instructions between checks for an interrupt from the debugger while running.
+const POLL_INTERVAL: u32 = 0x1000;
+
+/// The largest packet the debugger may send, and the largest reply to a memory read.
+const PACKET_SIZE: usize = 0x1000;
+
+enum Action {
+    Reply(String),
+    Detach,
+    Kill,
+}
+
+pub struct GdbStub<'a, C: Memory, O: Observer = NoObserver> {
+    emulator: &'a mut Emulator<C, O>,
+    breakpoints: HashSet<u16>,
+}
+
+impl<'a, C: Memory, O: Observer> GdbStub<'a, C, O> {
+    pub fn new(emulator: &'a mut Emulator<C, O>) -> GdbStub<'a, C, O> {
+        GdbStub {
+            emulator,
+            breakpoints: HashSet::new(),
+        }
+    }
+
+    /// Wait for a debugger to connect on the given address and serve it until it detaches.
+    pub fn serve<A: ToSocketAddrs>(&mut self, address: A) -> io::Result<()> {
+        let listener = TcpListener::bind(address)?;
+        let (stream, _) = listener.accept()?;
+
+        self.attach(stream)
+    }
+
+    /// Serve a debugger on an established connection until it detaches.
+    pub fn attach(&mut self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+
+        while let Some(packet) = read_packet(&mut stream)? {
+            let mut interrupted = || is_interrupted(&stream);
+
+            match self.handle(&packet, &mut interrupted) {
+                Action::Reply(reply) => write_packet(&mut stream, &reply)?,
+                Action::Detach => return write_packet(&mut stream, "OK"),
+                Action::Kill => return Ok(()),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: &str, interrupted: &mut dyn FnMut() -> bool) -> Action {
+        let command = packet.get(..1).unwrap_or("");
+        let arguments = packet.get(1..).unwrap_or("");
+
+        let reply = match command {
+            "?" => Some(SIGTRAP.to_string()),
+            "g" => self.read_registers(),
+            "G" => self.write_registers(arguments),
+            "p" => self.read_register(arguments),
+            "P" => self.write_register(arguments),
+            "m" => self.read_memory(arguments),
+            "M" => self.write_memory(arguments),
+            "Z" => self.update_breakpoint(arguments, true),
+            "z" => self.update_breakpoint(arguments, false),
+            "s" => self.resume(arguments, true, interrupted),
+            "c" => self.resume(arguments, false, interrupted),
+            "H" => Some("OK".to_string()),
+            "q" => self.query(arguments),
+            "D" => return Action::Detach,
+            "k" => return Action::Kill,
+            _ => Some(String::new()),
+        };
+
+        Action::Reply(reply.unwrap_or_else(|| "E01".to_string()))
+    }
+
+    fn registers(&self) -> [u8; 7] {
+        let r = &self.emulator.registers;
+        let pc = r.program_counter;
+
+        [
+            r.accumulator,
+            r.x,
+            r.y,
+            r.status.flags,
+            r.stack_pointer,
+            pc as u8,
+            (pc >> 8) as u8,
+        ]
+    }
+
+    fn set_register(&mut self, register: usize, bytes: &[u8]) -> Option<()> {
+        let r = &mut self.emulator.registers;
+
+        match (register, bytes) {
+            (0, &[v]) => r.accumulator = v,
+            (1, &[v]) => r.x = v,
+            (2, &[v]) => r.y = v,
+            (3, &[v]) => r.status.from(v),
+            (4, &[v]) => r.stack_pointer = v,
+            (5, &[least, most]) => r.program_counter = (most as u16) << 8 | least as u16,
+            _ => return None,
+        }
+
+        Some(())
+    }
+
+    fn read_registers(&self) -> Option<String> {
+        Some(encode_hex(&self.registers()))
+    }
+
+    fn write_registers(&mut self, arguments: &str) -> Option<String> {
+        let bytes = decode_hex(arguments)?;
+        if bytes.len() != 7 {
+            return None;
+        }
+
+        for register in 0..5 {
+            self.set_register(register, &bytes[register..=register])?;
+        }
+        self.set_register(5, &bytes[5..7])?;
+
+        Some("OK".to_string())
+    }
+
+    fn read_register(&self, arguments: &str) -> Option<String> {
+        let registers = self.registers();
+
+        match parse_hex(arguments)? {
+            n @ 0..=4 => Some(encode_hex(&registers[n..=n])),
+            5 => Some(encode_hex(&registers[5..7])),
+            _ => None,
+        }
+    }
+
+    fn write_register(&mut self, arguments: &str) -> Option<String> {
+        let (register, value) = arguments.split_once('=')?;
+        self.set_register(parse_hex(register)?, &decode_hex(value)?)?;
+
+        Some("OK".to_string())
+    }
+
+    fn read_memory(&self, arguments: &str) -> Option<String> {
+        // A shorter reply than asked for is allowed, and the debugger asks for the rest.
+        let (address, length) = parse_range(arguments)?;
+        let length = length.min(PACKET_SIZE / 2);
+
+        let bytes: Vec<u8> = (0..length)
+            .map(|i| self.emulator.memory.peek(address.wrapping_add(i as u16)))
+            .collect();
+
+        Some(encode_hex(&bytes))
+    }
+
+    fn write_memory(&mut self, arguments: &str) -> Option<String> {
+        let (range, data) = arguments.split_once(':')?;
+        let (address, length) = parse_range(range)?;
+        let bytes = decode_hex(data)?;
+        if bytes.len() != length {
+            return None;
+        }
+
+        for (i, b) in bytes.into_iter().enumerate() {
+            self.emulator
+                .memory
+                .write(address.wrapping_add(i as u16), b);
+        }
+
+        Some("OK".to_string())
+    }
+
+    fn update_breakpoint(&mut self, arguments: &str, insert: bool) -> Option<String> {
+        let mut fields = arguments.split(',');
+
+        // Only software breakpoints are supported, and hardware breakpoints are the same thing.
+        match fields.next()? {
+            "0" | "1" => {}
+            _ => return Some(String::new()),
+        }
+
+        let address = parse_hex(fields.next()?)? as u16;
+        if insert {
+            self.breakpoints.insert(address);
+        } else {
+            self.breakpoints.remove(&address);
+        }
+
+        Some("OK".to_string())
+    }
+
+    fn resume(
+        &mut self,
+        arguments: &str,
+        step: bool,
+        interrupted: &mut dyn FnMut() -> bool,
+    ) -> Option<String> {
+        if !arguments.is_empty() {
+            self.emulator.registers.program_counter = parse_hex(arguments)? as u16;
+        }
+
+        // Interrupts are taken on their own, so that execution stops at the start of a handler
+        // like anywhere else.
+        if step {
+            if !self.emulator.service_interrupt() {
+                self.emulator.execute_next();
+            }
+            return Some(SIGTRAP.to_string());
+        }
+
+        let resumed_at = self.emulator.registers.program_counter;
+        let mut first = true;
+        let mut count = 0u32;
+        loop {
+            // Not stopping at the breakpoint it resumed from.
+            let next = self.emulator.next_instruction_address();
+            if self.breakpoints.contains(&next) && !(first && next == resumed_at) {
+                self.emulator.service_interrupt();
+                return Some(SIGTRAP.to_string());
+            }
+            first = false;
+
+            self.emulator.execute_next();
+            if self.emulator.is_trapped() {
+                return Some(SIGTRAP.to_string());
+            }
+
+            count += 1;
+            if count == POLL_INTERVAL {
+                count = 0;
+                if interrupted() {
+                    return Some(SIGINT.to_string());
+                }
+            }
+        }
+    }
+
+    fn query(&self, arguments: &str) -> Option<String> {
+        let name = arguments.split(':').next()?;
+
+        let reply = match name {
+            "Supported" => format!("PacketSize={:x};qXfer:features:read+", PACKET_SIZE),
+            "Attached" => "1".to_string(),
+            "Xfer" => {
+                let annex = arguments.trim_start_matches("Xfer:features:read:target.xml:");
+                let (offset, length) = parse_range(annex)?;
+                let offset = (offset as usize).min(TARGET_XML.len());
+                let end = (offset + length).min(TARGET_XML.len());
+
+                let more = if end < TARGET_XML.len() { "m" } else { "l" };
+                format!("{}{}", more, &TARGET_XML[offset..end])
+            }
+            _ => String::new(),
+        };
+
+        Some(reply)
+    }
+}
+
+fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b))
+}
+
+fn read_byte<R: Read>(reader: &mut R) -> io::Result<Option<u8>> {
+    let mut buffer = [0u8; 1];
+    match reader.read(&mut buffer)? {
+        0 => Ok(None),
+        _ => Ok(Some(buffer[0])),
+    }
+}
+
+/// Read the next packet, acknowledging it. Returns `None` once the connection is closed.
+fn read_packet<S: Read + Write>(stream: &mut S) -> io::Result<Option<String>> {
+    loop {
+        // Skip acknowledgements and stray interrupts until the start of a packet.
+        match read_byte(stream)? {
+            None => return Ok(None),
+            Some(b'$') => {}
+            Some(_) => continue,
+        }
+
+        let mut data = Vec::new();
+        loop {
+            match read_byte(stream)? {
+                None => return Ok(None),
+                Some(b'#') => break,
+                Some(b) => data.push(b),
+            }
+        }
+
+        let mut expected = [0u8; 2];
+        stream.read_exact(&mut expected)?;
+
+        let data = String::from_utf8_lossy(&data).to_string();
+        let expected = String::from_utf8_lossy(&expected);
+
+        if u8::from_str_radix(&expected, 16).ok() == Some(checksum(&data)) {
+            stream.write_all(b"+")?;
+            return Ok(Some(data));
+        }
+
+        stream.write_all(b"-")?;
+    }
+}
+
+fn write_packet<W: Write>(writer: &mut W, data: &str) -> io::Result<()> {
+    write!(writer, "${}#{:02x}", data, checksum(data))?;
+    writer.flush()
+}
+
+/// Check for an interrupt from the debugger without blocking.
+fn is_interrupted(stream: &TcpStream) -> bool {
+    let mut buffer = [0u8; 1];
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+
+    let interrupted = match stream.peek(&mut buffer) {
+        Ok(1) if buffer[0] == INTERRUPT => {
+            let mut s = stream;
+            s.read_exact(&mut buffer).is_ok()
+        }
+        _ => false,
+    };
+
+    stream.set_nonblocking(false).is_ok() && interrupted
+}
+
+fn parse_hex(s: &str) -> Option<usize> {
+    usize::from_str_radix(s, 16).ok()
+}
+
+/// Parse an `address,length` pair, with at most the 64K there are.
+fn parse_range(s: &str) -> Option<(u16, usize)> {
+    let (address, length) = s.split_once(',')?;
+    let length = parse_hex(length).filter(|&length| length <= 0x10000)?;
+    Some((parse_hex(address)? as u16, length))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 == 1 || !s.is_ascii() {
+        return None;
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    use crate::debug::gdb::{checksum, read_packet, write_packet, Action, GdbStub};
+    use crate::emulator::instructions::opcodes::*;
+    use crate::emulator::tests::setup;
+    use crate::emulator::Emulator;
+    use crate::memory::default::DefaultMemory;
+
+    fn reply<C: crate::memory::Memory>(stub: &mut GdbStub<C>, packet: &str) -> String {
+        match stub.handle(packet, &mut || false) {
+            Action::Reply(r) => r,
+            _ => panic!("Expected a reply to {}", packet),
+        }
+    }
+
+    fn setup_program(program: Vec<Vec<u8>>) -> Emulator<DefaultMemory> {
+        let mut e = setup(vec![]);
+        e.memory
+            .load(program.into_iter().flatten().collect(), 0x600);
+        e
+    }
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(0x00, checksum(""));
+        assert_eq!(0x37, checksum("qSupported"));
+    }
+
+    #[test]
+    fn test_read_registers() {
+        let mut e = setup(vec![]);
+        e.registers.accumulator = 0x12;
+        e.registers.x = 0x34;
+        e.registers.y = 0x56;
+        e.registers.stack_pointer = 0xfd;
+        e.registers.program_counter = 0xabcd;
+
+        let mut stub = GdbStub::new(&mut e);
+
+        assert_eq!("12345620fdcdab", reply(&mut stub, "g"));
+        assert_eq!("cdab", reply(&mut stub, "p5"));
+        assert_eq!("fd", reply(&mut stub, "p4"));
+        assert_eq!("E01", reply(&mut stub, "p6"));
+    }
+
+    #[test]
+    fn test_write_registers() {
+        let mut e = setup(vec![]);
+
+        {
+            let mut stub = GdbStub::new(&mut e);
+            assert_eq!("OK", reply(&mut stub, "G01020381fe0080"));
+            assert_eq!("OK", reply(&mut stub, "P0=ff"));
+            assert_eq!("E01", reply(&mut stub, "P5=ff"));
+        }
+
+        assert_eq!(0xff, e.registers.accumulator);
+        assert_eq!(0x02, e.registers.x);
+        assert_eq!(0x03, e.registers.y);
+        assert_eq!(0xa1, e.registers.status.flags); // Reserved is always on.
+        assert_eq!(0xfe, e.registers.stack_pointer);
+        assert_eq!(0x8000, e.registers.program_counter);
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut e = setup(vec![]);
+        e.memory.memory[0x1234] = 0xaa;
+
+        {
+            let mut stub = GdbStub::new(&mut e);
+            assert_eq!("aa00", reply(&mut stub, "m1234,2"));
+            assert_eq!("OK", reply(&mut stub, "M2000,3:010203"));
+            assert_eq!("E01", reply(&mut stub, "M2000,3:01"));
+
+            // Reads are cut short to fit in a packet, and lengths beyond memory are refused.
+            assert_eq!(0x1000, reply(&mut stub, "m0,10000").len());
+            assert_eq!("E01", reply(&mut stub, "m0,10001"));
+        }
+
+        assert_eq!(vec![1, 2, 3], e.memory.memory[0x2000..0x2003].to_vec());
+    }
+
+    #[test]
+    fn test_step() {
+        let mut e = setup_program(vec![LDA::immediate(0x42), NOP::implied()]);
+
+        {
+            let mut stub = GdbStub::new(&mut e);
+            assert_eq!("S05", reply(&mut stub, "s"));
+        }
+
+        assert_eq!(0x42, e.registers.accumulator);
+        assert_eq!(0x602, e.registers.program_counter);
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let mut e = setup_program(vec![
+            NOP::implied(),
+            NOP::implied(),
+            INX::implied(),
+            JMP::absolute(0x600),
+        ]);
+
+        {
+            let mut stub = GdbStub::new(&mut e);
+            assert_eq!("OK", reply(&mut stub, "Z0,602,1"));
+            assert_eq!("S05", reply(&mut stub, "c"));
+            assert_eq!("S05", reply(&mut stub, "c"));
+            assert_eq!("OK", reply(&mut stub, "z0,602,1"));
+            assert_eq!("", reply(&mut stub, "Z2,602,1")); // Watchpoints are not supported.
+        }
+
+        assert_eq!(0x602, e.registers.program_counter);
+        assert_eq!(1, e.registers.x);
+    }
+
+    #[test]
+    fn test_interrupt_breakpoint() {
+        let mut e = setup_program(vec![NOP::implied(), JMP::absolute(0x600)]);
+        e.memory.load(INX::implied(), 0x700);
+        e.memory.load(vec![0x00, 0x07], 0xfffe);
+        e.registers
+            .status
+            .clear(crate::emulator::registers::Flag::Interrupt);
+        e.irq = true;
+
+        {
+            // Stopped at the start of the handler, before its first instruction.
+            let mut stub = GdbStub::new(&mut e);
+            assert_eq!("OK", reply(&mut stub, "Z0,700,1"));
+            assert_eq!("S05", reply(&mut stub, "c"));
+        }
+        assert_eq!(0x700, e.registers.program_counter);
+        assert_eq!(0, e.registers.x);
+
+        // Stepping takes the interrupt on its own as well.
+        e.registers.program_counter = 0x600;
+        e.registers
+            .status
+            .clear(crate::emulator::registers::Flag::Interrupt);
+        {
+            let mut stub = GdbStub::new(&mut e);
+            assert_eq!("S05", reply(&mut stub, "s"));
+        }
+        assert_eq!(0x700, e.registers.program_counter);
+        assert_eq!(0, e.registers.x);
+    }
+
+    #[test]
+    fn test_continue_until_trap() {
+        let mut e = setup_program(vec![NOP::implied(), JMP::absolute(0x601)]);
+        e.registers
+            .status
+            .set(crate::emulator::registers::Flag::Interrupt);
+
+        let mut stub = GdbStub::new(&mut e);
+        assert_eq!("S05", reply(&mut stub, "c"));
+        assert_eq!("0106", &reply(&mut stub, "g")[10..]);
+    }
+
+    #[test]
+    fn test_continue_until_interrupted() {
+        let mut e = setup_program(vec![INX::implied(), JMP::absolute(0x600)]);
+
+        let mut stub = GdbStub::new(&mut e);
+        match stub.handle("c", &mut || true) {
+            Action::Reply(r) => assert_eq!("S02", r),
+            _ => panic!("Expected a reply"),
+        }
+    }
+
+    #[test]
+    fn test_target_description() {
+        let mut e = setup(vec![]);
+        let mut stub = GdbStub::new(&mut e);
+
+        assert!(reply(&mut stub, "qSupported:multiprocess+").contains("qXfer:features:read+"));
+
+        let first = reply(&mut stub, "qXfer:features:read:target.xml:0,a");
+        assert_eq!("m<?xml vers", first);
+
+        let rest = reply(&mut stub, "qXfer:features:read:target.xml:a,1000");
+        assert!(rest.starts_with('l'));
+        assert!(rest.ends_with("</target>"));
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let mut e = setup(vec![]);
+        let mut stub = GdbStub::new(&mut e);
+
+        assert_eq!("", reply(&mut stub, "vMustReplyEmpty"));
+        assert_eq!("", reply(&mut stub, "qTStatus"));
+    }
+
+    #[test]
+    fn test_packet_framing() {
+        let mut output = Vec::new();
+        write_packet(&mut output, "OK").unwrap();
+        assert_eq!(b"$OK#9a".to_vec(), output);
+    }
+
+    #[test]
+    fn test_session() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+
+            let mut exchange = |packet: &str| {
+                write_packet(&mut stream, packet).unwrap();
+
+                let mut ack = [0u8; 1];
+                stream.read_exact(&mut ack).unwrap();
+                assert_eq!(b'+', ack[0]);
+
+                read_packet(&mut stream).unwrap().unwrap()
+            };
+
+            assert_eq!("S05", exchange("?"));
+            assert_eq!("OK", exchange("M0600,1:e8"));
+            assert_eq!("S05", exchange("s"));
+            assert_eq!("OK", exchange("D"));
+        });
+
+        let mut e = setup(vec![]);
+        {
+            let (stream, _) = listener.accept().unwrap();
+            let mut stub = GdbStub::new(&mut e);
+            stub.attach(stream).unwrap();
+        }
+        client.join().unwrap();
+
+        assert_eq!(1, e.registers.x);
+        assert_eq!(0x601, e.registers.program_counter);
+    }
+}
@@ -0,0 +1,124 @@
+//! Map addresses back to the assembler source lines that produced them, using a vasm listing
+//! written with `-L`:
+//!
+//! ```text
+//! Source: "demo.asm"
+//!                              1: ; Comment
+//! 00:8000 A2FF                 2:     ldx #$ff
+//! ```
+//!
+//! The address and bytes are separated from the line number by a tab.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A line in a source file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SourceLine {
+    /// Index into `SourceMap::files`.
+    pub file: usize,
+    pub line: u32,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SourceMap {
+    files: Vec<String>,
+    addresses: BTreeMap<u16, SourceLine>,
+    /// Every line that produced bytes, with the addresses of those bytes.
+    lines: BTreeMap<SourceLine, Vec<u16>>,
+}
+
+impl SourceMap {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<SourceMap> {
+        let contents = fs::read_to_string(path)?;
+        Ok(SourceMap::parse(&contents))
+    }
+
+    /// Parse a listing. Lines that do not belong to a source file, such as the sections and
+    /// symbols, are skipped.
+    pub fn parse(contents: &str) -> SourceMap {
+        let mut map = SourceMap::default();
+        let mut file = None;
+
+        for line in contents.lines() {
+            if let Some(name) = line.strip_prefix("Source: ") {
+                map.files.push(name.trim_matches('"').to_string());
+                file = Some(map.files.len() - 1);
+                continue;
+            }
+
+            if let (Some(file), Some((address, length, line))) = (file, parse_line(line)) {
+                let source = SourceLine { file, line };
+                for i in 0..length {
+                    let address = address.wrapping_add(i);
+                    map.addresses.insert(address, source);
+                    map.lines.entry(source).or_default().push(address);
+                }
+            }
+        }
+
+        map
+    }
+
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// The source line that produced the byte at an address.
+    pub fn line(&self, address: u16) -> Option<SourceLine> {
+        self.addresses.get(&address).cloned()
+    }
+
+    /// The lines that produced bytes, in order, with the addresses of those bytes.
+    pub fn lines(&self) -> impl Iterator<Item = (&SourceLine, &Vec<u16>)> {
+        self.lines.iter()
+    }
+}
+
+/// `00:8000 A2FF            \t     2:     ldx #$ff`, where the part before the tab is blank for
+/// lines that produce no bytes.
+fn parse_line(line: &str) -> Option<(u16, u16, u32)> {
+    let (location, source) = line.split_once('\t')?;
+
+    let number = source.split(':').next()?.trim().parse().ok()?;
+
+    let mut words = location.split_whitespace();
+    let address = words.next()?.rsplit(':').next()?;
+    let address = u16::from_str_radix(address, 16).ok()?;
+    let bytes = words.next()?;
+    if bytes.len() % 2 != 0 || !bytes.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    Some((address, (bytes.len() / 2) as u16, number))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debug::listing::{SourceLine, SourceMap};
+
+    const LISTING: &str = "Sections:\n\
+        00: \"seg8000\" (8000-8005)\n\
+        \n\
+        \n\
+        Source: \"demo.asm\"\n                        \t     1: ; Comment\n\
+        00:8000 A2FF            \t     2:     ldx #$ff\n\
+        00:8002 4C0080          \t     3:     jmp $8000\n\
+        \n\
+        \n\
+        Symbols by name:\n\
+        start                            A:8000\n";
+
+    #[test]
+    fn test_parse() {
+        let map = SourceMap::parse(LISTING);
+
+        assert_eq!(vec!["demo.asm"], map.files());
+        assert_eq!(Some(SourceLine { file: 0, line: 2 }), map.line(0x8001));
+        assert_eq!(Some(SourceLine { file: 0, line: 3 }), map.line(0x8004));
+        assert_eq!(None, map.line(0x8005));
+        assert_eq!(2, map.lines().count());
+    }
+}
@@ -0,0 +1,11 @@
+pub mod callstack;
+pub mod coverage;
+pub mod disassembler;
+pub mod gdb;
+pub mod listing;
+pub mod profiler;
+pub mod rewind;
+pub mod stack;
+pub mod symbols;
+pub mod trace;
+pub mod uninitialized;
@@ -0,0 +1,396 @@
+//! Count where the cycles go, per address and per subroutine.
+//!
+//! Subroutines are tracked by following JSR and RTS, and interrupt handlers by following
+//! interrupts, BRK and RTI. Code that returns by other means (for example by pulling the return
+//! address and jumping) leaves the call stack as it was, until a later return unwinds it.
+
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+
+use crate::debug::disassembler::disassemble;
+use crate::debug::symbols::SymbolTable;
+use crate::emulator::instructions::opcodes::*;
+use crate::emulator::observer::Observer;
+use crate::emulator::run::StopReason;
+use crate::emulator::Emulator;
+use crate::memory::Memory;
+
+/// Executions and cycles for a single address.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct AddressProfile {
+    pub executions: u64,
+    pub cycles: u64,
+}
+
+/// Calls and cycles for a subroutine, identified by its entry address.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SubroutineProfile {
+    pub calls: u64,
+    /// Cycles spent in the subroutine itself.
+    pub self_cycles: u64,
+    /// Cycles spent in the subroutine and everything it called. Recursive calls are counted
+    /// once for every level of recursion.
+    pub total_cycles: u64,
+}
+
+struct Frame {
+    entry: u16,
+    entered_at: u64,
+}
+
+pub struct Profiler {
+    addresses: HashMap<u16, AddressProfile>,
+    subroutines: HashMap<u16, SubroutineProfile>,
+    stack: Vec<Frame>,
+    /// Self cycles per call stack, from the outermost entry address inwards.
+    folded: HashMap<Vec<u16>, u64>,
+    /// Cycles spent in the current call stack that are not in `folded` yet.
+    unfolded: u64,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            addresses: HashMap::new(),
+            subroutines: HashMap::new(),
+            stack: Vec::new(),
+            folded: HashMap::new(),
+            unfolded: 0,
+        }
+    }
+
+    /// Execute the next instruction and account for it. The cycles of an interrupt are counted
+    /// towards the first instruction of its handler, while those of BRK are the caller's.
+    pub fn step<C: Memory, O: Observer>(&mut self, emulator: &mut Emulator<C, O>) {
+        let cycles = emulator.cycles;
+        let address = emulator.next_instruction_address();
+
+        if emulator.is_interrupt_pending() {
+            self.enter(address, cycles);
+        }
+
+        let instruction = emulator.memory.peek(address);
+        emulator.execute_next();
+        let spent = emulator.cycles - cycles;
+
+        let profile = self.addresses.entry(address).or_default();
+        profile.executions += 1;
+        profile.cycles += spent;
+        self.account(spent);
+
+        match instruction {
+            JSR::ABSOLUTE | BRK::IMMEDIATE => {
+                self.enter(emulator.registers.program_counter, emulator.cycles)
+            }
+            RTS::IMPLIED | RTI::IMPLIED => self.leave(emulator.cycles),
+            _ => {}
+        }
+    }
+
+    /// Execute instructions until a trap is detected or `max_instructions` have been executed,
+    /// like `Emulator::run`.
+    pub fn run<C: Memory, O: Observer>(
+        &mut self,
+        emulator: &mut Emulator<C, O>,
+        max_instructions: u64,
+    ) -> StopReason {
+        for _ in 0..max_instructions {
+            self.step(emulator);
+
+            if emulator.is_trapped() {
+                return StopReason::Trap(emulator.registers.program_counter);
+            }
+        }
+
+        StopReason::InstructionLimit
+    }
+
+    pub fn address(&self, address: u16) -> AddressProfile {
+        self.addresses.get(&address).cloned().unwrap_or_default()
+    }
+
+    pub fn subroutine(&self, entry: u16) -> SubroutineProfile {
+        self.subroutines.get(&entry).cloned().unwrap_or_default()
+    }
+
+    /// The addresses that took the most cycles, most expensive first.
+    pub fn hotspots(&self) -> Vec<(u16, AddressProfile)> {
+        let mut hotspots: Vec<(u16, AddressProfile)> =
+            self.addresses.iter().map(|(a, p)| (*a, *p)).collect();
+        hotspots.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(&b.0)));
+        hotspots
+    }
+
+    /// A report of the subroutines and instructions that took the most cycles, at most `limit`
+    /// of each.
+    pub fn report<M: Memory>(&self, memory: &M, symbols: &SymbolTable, limit: usize) -> String {
+        let total: u64 = self.addresses.values().map(|p| p.cycles).sum();
+        let percentage = |cycles: u64| match total {
+            0 => 0.0,
+            _ => cycles as f64 * 100.0 / total as f64,
+        };
+
+        let mut subroutines: Vec<(&u16, &SubroutineProfile)> = self.subroutines.iter().collect();
+        subroutines.sort_by(|a, b| b.1.total_cycles.cmp(&a.1.total_cycles).then(a.0.cmp(b.0)));
+
+        let mut report = format!(
+            "{:>12} {:>6} {:>12} {:>10}  subroutine\n",
+            "total", "%", "self", "calls"
+        );
+        for (entry, profile) in subroutines.into_iter().take(limit) {
+            report += &format!(
+                "{:>12} {:>6.2} {:>12} {:>10}  {}\n",
+                profile.total_cycles,
+                percentage(profile.total_cycles),
+                profile.self_cycles,
+                profile.calls,
+                name(symbols, *entry)
+            );
+        }
+
+        report += &format!(
+            "\n{:>12} {:>6} {:>12}  address\n",
+            "cycles", "%", "executions"
+        );
+        for (address, profile) in self.hotspots().into_iter().take(limit) {
+            let instruction = disassemble(memory, address);
+            report += &format!(
+                "{:>12} {:>6.2} {:>12}  {:04x}  {}\n",
+                profile.cycles,
+                percentage(profile.cycles),
+                profile.executions,
+                address,
+                instruction.format(|a| symbols.label(a))
+            );
+        }
+
+        report
+    }
+
+    /// Write the cycles per call stack in the folded format read by flame graph tools, one
+    /// stack per line: `main;draw;plot 1234`. Code outside any known subroutine is `root`.
+    pub fn write_folded<W: Write>(&self, out: &mut W, symbols: &SymbolTable) -> io::Result<()> {
+        let mut folded = self.folded.clone();
+        if self.unfolded > 0 {
+            *folded.entry(self.current_stack()).or_default() += self.unfolded;
+        }
+
+        let mut stacks: Vec<(String, u64)> = folded
+            .into_iter()
+            .map(|(stack, cycles)| {
+                let mut names = vec!["root".to_string()];
+                names.extend(stack.iter().map(|e| name(symbols, *e)));
+                (names.join(";"), cycles)
+            })
+            .collect();
+        stacks.sort();
+
+        for (stack, cycles) in stacks {
+            writeln!(out, "{} {}", stack, cycles)?;
+        }
+
+        Ok(())
+    }
+
+    fn account(&mut self, cycles: u64) {
+        if let Some(frame) = self.stack.last() {
+            self.subroutines.entry(frame.entry).or_default().self_cycles += cycles;
+        }
+        self.unfolded += cycles;
+    }
+
+    fn current_stack(&self) -> Vec<u16> {
+        self.stack.iter().map(|f| f.entry).collect()
+    }
+
+    /// Add the cycles spent since the call stack last changed to the folded stacks.
+    fn fold(&mut self) {
+        if self.unfolded > 0 {
+            *self.folded.entry(self.current_stack()).or_default() += self.unfolded;
+            self.unfolded = 0;
+        }
+    }
+
+    fn enter(&mut self, entry: u16, cycles: u64) {
+        self.fold();
+        self.subroutines.entry(entry).or_default().calls += 1;
+        self.stack.push(Frame {
+            entry,
+            entered_at: cycles,
+        });
+    }
+
+    fn leave(&mut self, cycles: u64) {
+        self.fold();
+        if let Some(frame) = self.stack.pop() {
+            self.subroutines
+                .entry(frame.entry)
+                .or_default()
+                .total_cycles += cycles - frame.entered_at;
+        }
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Profiler {
+        Profiler::new()
+    }
+}
+
+fn name(symbols: &SymbolTable, address: u16) -> String {
+    symbols
+        .label(address)
+        .unwrap_or_else(|| format!("${:04x}", address))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debug::profiler::Profiler;
+    use crate::debug::symbols::SymbolTable;
+    use crate::emulator::instructions::opcodes::*;
+    use crate::emulator::run::StopReason;
+    use crate::emulator::tests::setup;
+    use crate::emulator::Emulator;
+    use crate::memory::default::DefaultMemory;
+
+    /// Calls a subroutine at $0700 twice, which calls another at $0710 once.
+    fn program() -> Emulator<DefaultMemory> {
+        let mut e = setup(vec![]);
+        e.memory.load(
+            [
+                JSR::absolute(0x700),
+                JSR::absolute(0x700),
+                JMP::absolute(0x606),
+            ]
+            .concat(),
+            0x600,
+        );
+        e.memory.load(
+            [INX::implied(), JSR::absolute(0x710), RTS::implied()].concat(),
+            0x700,
+        );
+        e.memory
+            .load([INY::implied(), RTS::implied()].concat(), 0x710);
+        e
+    }
+
+    fn symbols() -> SymbolTable {
+        let mut symbols = SymbolTable::new();
+        symbols.insert("outer", 0x700);
+        symbols.insert("inner", 0x710);
+        symbols
+    }
+
+    #[test]
+    fn test_addresses() {
+        let mut e = program();
+        let mut profiler = Profiler::new();
+
+        assert_eq!(StopReason::Trap(0x606), profiler.run(&mut e, 100));
+
+        let inx = profiler.address(0x700);
+        assert_eq!(2, inx.executions);
+        assert_eq!(4, inx.cycles);
+        assert_eq!(0x701, profiler.hotspots()[0].0);
+    }
+
+    #[test]
+    fn test_subroutines() {
+        let mut e = program();
+        let mut profiler = Profiler::new();
+        profiler.run(&mut e, 100);
+
+        let outer = profiler.subroutine(0x700);
+        assert_eq!(2, outer.calls);
+        // INX, JSR and RTS.
+        assert_eq!(2 * (2 + 6 + 6), outer.self_cycles);
+        // INY and RTS.
+        assert_eq!(2 * (2 + 6), profiler.subroutine(0x710).self_cycles);
+        assert_eq!(2 * (14 + 8), outer.total_cycles);
+    }
+
+    #[test]
+    fn test_interrupts() {
+        let mut e = setup(vec![]);
+        e.memory.load(JMP::absolute(0x600), 0x600);
+        e.memory
+            .load([INX::implied(), RTI::implied()].concat(), 0x700);
+        e.memory.load(vec![0x00, 0x07], 0xFFFA);
+        e.nmi = true;
+
+        let mut profiler = Profiler::new();
+        for _ in 0..3 {
+            profiler.step(&mut e);
+        }
+
+        let handler = profiler.subroutine(0x700);
+        assert_eq!(1, handler.calls);
+        // The interrupt, INX and RTI.
+        assert_eq!(7 + 2 + 6, handler.total_cycles);
+        assert_eq!(1, profiler.address(0x700).executions);
+        assert_eq!(1, profiler.address(0x600).executions);
+    }
+
+    #[test]
+    fn test_brk() {
+        let mut e = setup(vec![]);
+        e.memory
+            .load([JSR::absolute(0x700), JMP::absolute(0x603)].concat(), 0x600);
+        e.memory.load(
+            [BRK::immediate(0), INY::implied(), RTS::implied()].concat(),
+            0x700,
+        );
+        e.memory
+            .load([INX::implied(), RTI::implied()].concat(), 0x710);
+        e.memory.load(vec![0x10, 0x07], 0xFFFE);
+
+        let mut profiler = Profiler::new();
+        assert_eq!(StopReason::Trap(0x603), profiler.run(&mut e, 100));
+
+        let handler = profiler.subroutine(0x710);
+        assert_eq!(1, handler.calls);
+        // INX and RTI.
+        assert_eq!(2 + 6, handler.total_cycles);
+        // The handler returns to the subroutine, which is only left by its own RTS.
+        let outer = profiler.subroutine(0x700);
+        assert_eq!(7 + 2 + 6, outer.self_cycles);
+        assert_eq!(7 + 8 + 2 + 6, outer.total_cycles);
+
+        let mut out = Vec::new();
+        profiler.write_folded(&mut out, &symbols()).unwrap();
+        assert_eq!(
+            "root 9\nroot;outer 15\nroot;outer;inner 8\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_folded() {
+        let mut e = program();
+        let mut profiler = Profiler::new();
+        profiler.run(&mut e, 100);
+
+        let mut out = Vec::new();
+        profiler.write_folded(&mut out, &symbols()).unwrap();
+
+        assert_eq!(
+            "root 15\nroot;outer 28\nroot;outer;inner 16\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_report() {
+        let mut e = program();
+        let mut profiler = Profiler::new();
+        profiler.run(&mut e, 100);
+
+        let report = profiler.report(&e.memory, &symbols(), 1);
+        let lines: Vec<&str> = report.lines().collect();
+
+        assert_eq!(5, lines.len());
+        assert!(lines[1].ends_with("outer"));
+        assert!(lines[4].ends_with("0701  jsr inner"));
+    }
+}
@@ -0,0 +1,340 @@
+//! Execute backwards, by keeping a bounded history of what each instruction changed.
+//!
+//! `RewindMemory` wraps the memory of the emulator to record the old value of every byte that is
+//! written, and `History::step` executes instructions while recording the state before each of
+//! them. Memory mapped devices are written back to like any other memory, but their internal
+//! state is not rewound, and the old values are peeked at so that recording them does not
+//! disturb the devices.
+
+use std::collections::VecDeque;
+
+use crate::emulator::observer::Observer;
+use crate::emulator::registers::Registers;
+use crate::emulator::Emulator;
+use crate::memory::Memory;
+
+/// More writes than any instruction makes, together with the interrupt before it.
+const MAX_WRITES: usize = 8;
+
+/// Memory that remembers the previous value of each byte written.
+pub struct RewindMemory<M: Memory> {
+    pub memory: M,
+    writes: Vec<(u16, u8)>,
+}
+
+impl<M: Memory> RewindMemory<M> {
+    pub fn new(memory: M) -> RewindMemory<M> {
+        RewindMemory {
+            memory,
+            writes: Vec::new(),
+        }
+    }
+}
+
+impl<M: Memory> Memory for RewindMemory<M> {
+    fn read(&self, address: u16) -> u8 {
+        self.memory.read(address)
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.memory.peek(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        // Instructions executed without `History::step` leave their writes behind.
+        if self.writes.len() == MAX_WRITES {
+            self.writes.remove(0);
+        }
+        self.writes.push((address, self.memory.peek(address)));
+        self.memory.write(address, value)
+    }
+}
+
+/// Everything needed to undo a single instruction.
+struct Record {
+    registers: Registers,
+    irq: bool,
+    nmi: bool,
+    cycles: u64,
+    trapped: bool,
+    /// Addresses written by the instruction, with their previous values, in order.
+    writes: Vec<(u16, u8)>,
+}
+
+/// Reason for `History::run_back` to stop.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackStop {
+    /// The instruction at this address is about to be executed.
+    Breakpoint(u16),
+    /// The instruction just undone wrote to this address.
+    Watchpoint(u16),
+    /// There is no more history to undo.
+    Start,
+}
+
+pub struct History {
+    records: VecDeque<Record>,
+    capacity: usize,
+}
+
+impl History {
+    /// A history of at most `capacity` instructions. Once full, the oldest are forgotten.
+    pub fn new(capacity: usize) -> History {
+        History {
+            records: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// The number of instructions that can be undone.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    /// Execute the next instruction, recording how to undo it.
+    pub fn step<M: Memory, O: Observer>(&mut self, emulator: &mut Emulator<RewindMemory<M>, O>) {
+        emulator.memory.writes.clear();
+
+        let mut record = Record {
+            registers: emulator.registers.clone(),
+            irq: emulator.irq,
+            nmi: emulator.nmi,
+            cycles: emulator.cycles,
+            trapped: emulator.trapped,
+            writes: Vec::new(),
+        };
+
+        emulator.execute_next();
+        record.writes.append(&mut emulator.memory.writes);
+
+        if self.capacity == 0 {
+            return;
+        }
+        if self.records.len() == self.capacity {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    /// Undo the last instruction. Returns whether there was one to undo.
+    pub fn step_back<M: Memory, O: Observer>(
+        &mut self,
+        emulator: &mut Emulator<RewindMemory<M>, O>,
+    ) -> bool {
+        self.undo(emulator).is_some()
+    }
+
+    /// Undo instructions until one that is about to be executed is at a breakpoint, or one that
+    /// was undone wrote to a watched address. At least one instruction is undone.
+    pub fn run_back<M: Memory, O: Observer>(
+        &mut self,
+        emulator: &mut Emulator<RewindMemory<M>, O>,
+        breakpoints: &[u16],
+        watchpoints: &[u16],
+    ) -> BackStop {
+        while let Some(writes) = self.undo(emulator) {
+            if let Some((address, _)) = writes.iter().find(|(a, _)| watchpoints.contains(a)) {
+                return BackStop::Watchpoint(*address);
+            }
+
+            let pc = emulator.registers.program_counter;
+            if breakpoints.contains(&pc) {
+                return BackStop::Breakpoint(pc);
+            }
+        }
+
+        BackStop::Start
+    }
+
+    /// Undo instructions until the cycle count is at or before `cycle`. Returns whether that
+    /// point is still in the history.
+    pub fn rewind_to<M: Memory, O: Observer>(
+        &mut self,
+        emulator: &mut Emulator<RewindMemory<M>, O>,
+        cycle: u64,
+    ) -> bool {
+        while emulator.cycles > cycle {
+            if self.undo(emulator).is_none() {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Restore the state from before the last instruction, and return what it wrote.
+    fn undo<M: Memory, O: Observer>(
+        &mut self,
+        emulator: &mut Emulator<RewindMemory<M>, O>,
+    ) -> Option<Vec<(u16, u8)>> {
+        let record = self.records.pop_back()?;
+
+        for (address, value) in record.writes.iter().rev() {
+            emulator.memory.memory.write(*address, *value);
+        }
+
+        emulator.registers = record.registers;
+        emulator.irq = record.irq;
+        emulator.nmi = record.nmi;
+        emulator.cycles = record.cycles;
+        emulator.trapped = record.trapped;
+
+        Some(record.writes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::debug::rewind::{BackStop, History, RewindMemory, MAX_WRITES};
+    use crate::devices::bus::{Bus, Line};
+    use crate::devices::Device;
+    use crate::emulator::instructions::opcodes::*;
+    use crate::emulator::Emulator;
+    use crate::memory::default::DefaultMemory;
+    use crate::memory::Memory;
+
+    /// Counts down from 3 in X, storing each value in $0200,X.
+    fn program() -> Emulator<RewindMemory<DefaultMemory>> {
+        let mut memory = DefaultMemory::empty();
+        memory.load(
+            [
+                LDX::immediate(3),
+                TXA::implied(),
+                STA::absolute_x(0x200),
+                DEX::implied(),
+                BNE::relative(-7),
+                JMP::absolute(0x609),
+            ]
+            .concat(),
+            0x600,
+        );
+        memory.set_program_counter(0x600);
+
+        Emulator::new(RewindMemory::new(memory))
+    }
+
+    fn run(history: &mut History, e: &mut Emulator<RewindMemory<DefaultMemory>>) {
+        while !e.is_trapped() {
+            history.step(e);
+        }
+    }
+
+    #[test]
+    fn test_step_back() {
+        let mut e = program();
+        let mut history = History::new(100);
+        run(&mut history, &mut e);
+
+        assert_eq!(3, e.memory.read(0x203));
+        let cycles = e.cycles;
+
+        // JMP, BNE, DEX and STA.
+        for _ in 0..4 {
+            assert!(history.step_back(&mut e));
+        }
+
+        assert_eq!(0x603, e.registers.program_counter);
+        assert_eq!(1, e.registers.x);
+        assert_eq!(0, e.memory.read(0x201));
+        assert!(e.cycles < cycles);
+        assert!(!e.is_trapped());
+    }
+
+    #[test]
+    fn test_run_back() {
+        let mut e = program();
+        let mut history = History::new(100);
+        run(&mut history, &mut e);
+
+        assert_eq!(
+            BackStop::Watchpoint(0x202),
+            history.run_back(&mut e, &[], &[0x202])
+        );
+        assert_eq!(2, e.registers.x);
+        assert_eq!(0, e.memory.read(0x202));
+
+        assert_eq!(
+            BackStop::Breakpoint(0x602),
+            history.run_back(&mut e, &[0x602], &[])
+        );
+        assert_eq!(2, e.registers.x);
+
+        assert_eq!(BackStop::Start, history.run_back(&mut e, &[], &[]));
+        assert_eq!(0x600, e.registers.program_counter);
+    }
+
+    #[test]
+    fn test_rewind_to() {
+        let mut e = program();
+        let mut history = History::new(100);
+        run(&mut history, &mut e);
+
+        // LDX, TXA and STA take 9 cycles, and DEX 2 more.
+        assert!(history.rewind_to(&mut e, 10));
+        assert_eq!(9, e.cycles);
+        assert_eq!(0x606, e.registers.program_counter);
+
+        assert!(history.rewind_to(&mut e, 0));
+        assert_eq!(0x600, e.registers.program_counter);
+    }
+
+    /// A register that counts how often it is read.
+    #[derive(Default)]
+    struct Counter {
+        reads: u8,
+    }
+
+    impl Device for Counter {
+        fn read(&mut self, _offset: u16) -> u8 {
+            self.reads += 1;
+            self.reads
+        }
+
+        fn write(&mut self, _offset: u16, _value: u8) {}
+    }
+
+    #[test]
+    fn test_devices() {
+        let counter = Rc::new(RefCell::new(Counter::default()));
+        let mut bus = Bus::new(DefaultMemory::empty());
+        bus.attach("counter", 0x8000, 1, counter.clone(), Line::Irq);
+        let mut memory = RewindMemory::new(bus);
+
+        // Recording the old value does not read the device.
+        memory.write(0x8000, 0x42);
+        assert_eq!(0, counter.borrow().reads);
+
+        for _ in 0..2 * MAX_WRITES {
+            memory.write(0x0200, 0x42);
+        }
+        assert_eq!(MAX_WRITES, memory.writes.len());
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut e = program();
+        let mut history = History::new(2);
+        run(&mut history, &mut e);
+
+        assert_eq!(2, history.len());
+        assert!(history.step_back(&mut e));
+        assert!(history.step_back(&mut e));
+        assert!(!history.step_back(&mut e));
+        assert_eq!(0x607, e.registers.program_counter);
+    }
+}
@@ -0,0 +1,256 @@
+//! Check that the stack stays within the stack page, and optionally above a limit.
+//!
+//! The stack pointer is eight bits wide, so pushing with the stack pointer at $00 wraps around
+//! to $ff, and pulling at $ff wraps to $00. The processor does not mind, but the program has
+//! lost its stack by then.
+
+use crate::emulator::observer::{Execution, Observer};
+use crate::emulator::registers::Registers;
+
+/// The number of problems kept. Later problems are only counted.
+const MAX_PROBLEMS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StackProblem {
+    /// A push wrapped the stack pointer from $00 to $ff.
+    Overflow,
+    /// A pull wrapped the stack pointer from $ff to $00.
+    Underflow,
+    /// A push took the stack pointer below the limit.
+    BelowLimit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StackReport {
+    pub problem: StackProblem,
+    /// The instruction responsible, or the instruction that was interrupted when the push was
+    /// part of taking an interrupt.
+    pub program_counter: u16,
+    /// The stack pointer after the push or pull.
+    pub stack_pointer: u8,
+}
+
+pub struct StackChecker {
+    limit: Option<u8>,
+    /// Whether the stack pointer is below the limit, so that crossing it is reported once.
+    below_limit: bool,
+    lowest: u8,
+    program_counter: u16,
+    reports: Vec<StackReport>,
+    count: usize,
+}
+
+impl StackChecker {
+    /// A checker for overflow and underflow.
+    pub fn new() -> StackChecker {
+        StackChecker {
+            limit: None,
+            below_limit: false,
+            lowest: 0xff,
+            program_counter: 0,
+            reports: Vec::new(),
+            count: 0,
+        }
+    }
+
+    /// A checker that also reports when the stack pointer drops below `limit`, for example
+    /// because the bottom of the stack page is used for other purposes.
+    pub fn with_limit(limit: u8) -> StackChecker {
+        StackChecker {
+            limit: Some(limit),
+            ..StackChecker::new()
+        }
+    }
+
+    /// The first problems found, in order.
+    pub fn reports(&self) -> &[StackReport] {
+        &self.reports
+    }
+
+    /// The number of problems found, including those beyond what is kept in `reports`.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// The lowest the stack pointer has been, which tells how much of the stack page is used.
+    pub fn lowest(&self) -> u8 {
+        self.lowest
+    }
+
+    fn report(&mut self, problem: StackProblem, stack_pointer: u8) {
+        self.count += 1;
+        if self.reports.len() < MAX_PROBLEMS {
+            self.reports.push(StackReport {
+                problem,
+                program_counter: self.program_counter,
+                stack_pointer,
+            });
+        }
+    }
+}
+
+impl Default for StackChecker {
+    fn default() -> StackChecker {
+        StackChecker::new()
+    }
+}
+
+impl Observer for StackChecker {
+    fn before_instruction(&mut self, address: u16, _opcode: u8, _registers: &Registers) {
+        self.program_counter = address;
+    }
+
+    fn after_instruction(&mut self, _execution: &Execution, registers: &Registers) {
+        // Pushes that happen before the next instruction are part of taking an interrupt.
+        self.program_counter = registers.program_counter;
+
+        if let Some(limit) = self.limit {
+            if registers.stack_pointer >= limit {
+                self.below_limit = false;
+            }
+        }
+    }
+
+    fn push(&mut self, address: u16, _value: u8) {
+        let stack_pointer = (address as u8).wrapping_sub(1);
+        self.lowest = self.lowest.min(stack_pointer);
+
+        if address == 0x100 {
+            self.report(StackProblem::Overflow, stack_pointer);
+            self.lowest = 0;
+        }
+
+        match self.limit {
+            Some(limit) if stack_pointer < limit && !self.below_limit => {
+                self.below_limit = true;
+                self.report(StackProblem::BelowLimit, stack_pointer);
+            }
+            _ => {}
+        }
+    }
+
+    fn pull(&mut self, address: u16, _value: u8) {
+        if address == 0x100 {
+            self.report(StackProblem::Underflow, 0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debug::stack::{StackChecker, StackProblem, StackReport};
+    use crate::emulator::instructions::opcodes::*;
+    use crate::emulator::Emulator;
+    use crate::memory::default::DefaultMemory;
+
+    fn emulator(
+        program: Vec<Vec<u8>>,
+        checker: StackChecker,
+    ) -> Emulator<DefaultMemory, StackChecker> {
+        let mut memory = DefaultMemory::empty();
+        memory.load(program.concat(), 0x600);
+        memory.set_program_counter(0x600);
+
+        Emulator::with_observer(memory, checker)
+    }
+
+    #[test]
+    fn test_balanced() {
+        let mut e = emulator(
+            vec![
+                PHA::implied(),
+                PHA::implied(),
+                PLA::implied(),
+                PLA::implied(),
+            ],
+            StackChecker::new(),
+        );
+        e.run(4);
+
+        assert_eq!(0, e.observer.count());
+        assert_eq!(0xfd, e.observer.lowest());
+    }
+
+    #[test]
+    fn test_underflow() {
+        let mut e = emulator(vec![NOP::implied(), PLA::implied()], StackChecker::new());
+        e.run(2);
+
+        assert_eq!(
+            &[StackReport {
+                problem: StackProblem::Underflow,
+                program_counter: 0x601,
+                stack_pointer: 0x00,
+            }],
+            e.observer.reports()
+        );
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut e = emulator(
+            vec![PHA::implied(), JMP::absolute(0x600)],
+            StackChecker::new(),
+        );
+        e.run(2 * 256);
+
+        assert_eq!(
+            &[StackReport {
+                problem: StackProblem::Overflow,
+                program_counter: 0x600,
+                stack_pointer: 0xff,
+            }],
+            e.observer.reports()
+        );
+        assert_eq!(0, e.observer.lowest());
+    }
+
+    #[test]
+    fn test_limit() {
+        // Recurse three levels deep, twice.
+        let mut e = emulator(
+            vec![
+                LDX::immediate(3),
+                JSR::absolute(0x700),
+                LDX::immediate(3),
+                JSR::absolute(0x700),
+                JMP::absolute(0x60a),
+            ],
+            StackChecker::with_limit(0xfb),
+        );
+        e.memory.load(
+            [
+                DEX::implied(),
+                BEQ::relative(3),
+                JSR::absolute(0x700),
+                RTS::implied(),
+            ]
+            .concat(),
+            0x700,
+        );
+        e.run(100);
+
+        let reports = e.observer.reports();
+        assert_eq!(2, reports.len());
+        assert_eq!(StackProblem::BelowLimit, reports[0].problem);
+        assert_eq!(0x703, reports[0].program_counter);
+        assert_eq!(0xfa, reports[0].stack_pointer);
+        assert_eq!(0xf9, e.observer.lowest());
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let mut e = emulator(vec![NOP::implied(), NOP::implied()], StackChecker::new());
+        e.memory.load(vec![0x00, 0x07], 0xfffa);
+        e.memory.load(NOP::implied(), 0x700);
+        e.registers.stack_pointer = 0x01;
+
+        e.execute_next();
+        e.nmi = true;
+        e.execute_next();
+
+        let report = e.observer.reports()[0];
+        assert_eq!(StackProblem::Overflow, report.problem);
+        assert_eq!(0x601, report.program_counter);
+    }
+}
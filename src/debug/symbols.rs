@@ -0,0 +1,215 @@
+//! Symbol tables map labels from the assembler onto addresses, and back.
+//!
+//! Several formats can be loaded, and may be mixed within a single file:
+//!
+//! - VICE label files, which is also what ca65 writes with `-Ln`: `al C:8010 .seed`.
+//! - ca65 debug information written with `--dbgfile`: `sym id=3,name="seed",...,val=0x8010,...`.
+//! - vasm listings written with `-L`, of which the symbols sections are used: `seed  A:8010`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Default, Clone)]
+pub struct SymbolTable {
+    names: BTreeMap<u16, String>,
+    addresses: HashMap<String, u16>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    /// Read symbols from a file in any of the supported formats.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<SymbolTable> {
+        let contents = fs::read_to_string(path)?;
+        Ok(SymbolTable::parse(&contents))
+    }
+
+    /// Parse symbols in any of the supported formats. Lines that are not recognized are skipped.
+    pub fn parse(contents: &str) -> SymbolTable {
+        let mut table = SymbolTable::new();
+        table.extend(contents);
+        table
+    }
+
+    /// Add the symbols in `contents` to the table.
+    pub fn extend(&mut self, contents: &str) {
+        for line in contents.lines() {
+            let symbol = parse_vice(line)
+                .or_else(|| parse_ca65_debug(line))
+                .or_else(|| parse_vasm(line));
+
+            if let Some((name, address)) = symbol {
+                self.insert(name, address);
+            }
+        }
+    }
+
+    /// Add a symbol. An address keeps the first name given to it, but all names can be
+    /// looked up.
+    pub fn insert(&mut self, name: &str, address: u16) {
+        self.names
+            .entry(address)
+            .or_insert_with(|| name.to_string());
+        self.addresses.insert(name.to_string(), address);
+    }
+
+    /// The name for an address.
+    pub fn name(&self, address: u16) -> Option<&str> {
+        self.names.get(&address).map(|n| n.as_str())
+    }
+
+    /// The address for a name.
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).cloned()
+    }
+
+    /// The name for an address, for use with `Instruction::format`.
+    pub fn label(&self, address: u16) -> Option<String> {
+        self.name(address).map(|n| n.to_string())
+    }
+
+    /// The nearest symbol at or below the address, and the offset from it.
+    pub fn nearest(&self, address: u16) -> Option<(&str, u16)> {
+        self.names
+            .range(..=address)
+            .next_back()
+            .map(|(a, name)| (name.as_str(), address - a))
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+}
+
+fn parse_hex(s: &str) -> Option<u16> {
+    let digits = s.trim_start_matches("0x").trim_start_matches('$');
+    u32::from_str_radix(digits, 16)
+        .ok()
+        .filter(|v| *v <= 0xFFFF)
+        .map(|v| v as u16)
+}
+
+/// `al C:8010 .seed`, where the memory space prefix is optional.
+fn parse_vice(line: &str) -> Option<(&str, u16)> {
+    let mut words = line.split_whitespace();
+    if words.next()? != "al" {
+        return None;
+    }
+
+    let address = words.next()?;
+    let address = address.rsplit(':').next()?;
+    let name = words.next()?.trim_start_matches('.');
+
+    Some((name, parse_hex(address)?))
+}
+
+/// `sym id=3,name="seed",addrsize=absolute,scope=0,def=12,ref=3,val=0x8010,seg=0,type=lab`
+fn parse_ca65_debug(line: &str) -> Option<(&str, u16)> {
+    let (kind, fields) = line.split_once(char::is_whitespace)?;
+    if kind != "sym" {
+        return None;
+    }
+
+    let mut name = None;
+    let mut value = None;
+    for field in fields.split(',') {
+        match field.split_once('=')? {
+            ("name", v) => name = Some(v.trim_matches('"')),
+            ("val", v) => value = parse_hex(v),
+            _ => {}
+        }
+    }
+
+    Some((name?, value?))
+}
+
+/// `seed                             A:8010`, as found in the symbols section of a listing.
+fn parse_vasm(line: &str) -> Option<(&str, u16)> {
+    let mut words = line.split_whitespace();
+    let name = words.next()?;
+    let address = words.next()?;
+    if words.next().is_some() {
+        return None;
+    }
+
+    let (kind, address) = address.split_once(':')?;
+    if kind.len() != 1 || !kind.chars().all(|c| c.is_ascii_uppercase()) {
+        return None;
+    }
+
+    Some((name, parse_hex(address)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debug::symbols::SymbolTable;
+
+    #[test]
+    fn test_vice() {
+        let table = SymbolTable::parse("al C:8010 .seed\nal 008000 .reset\n");
+
+        assert_eq!(Some(0x8010), table.address("seed"));
+        assert_eq!(Some("reset"), table.name(0x8000));
+    }
+
+    #[test]
+    fn test_ca65_debug() {
+        let table = SymbolTable::parse(
+            "version\tmajor=2,minor=0\n\
+             sym\tid=0,name=\"seed\",addrsize=absolute,scope=0,def=12,ref=3,val=0x8010,seg=0,type=lab\n\
+             sym\tid=1,name=\"imported\",addrsize=absolute,scope=0,def=13,type=imp\n",
+        );
+
+        assert_eq!(1, table.len());
+        assert_eq!(Some(0x8010), table.address("seed"));
+    }
+
+    #[test]
+    fn test_vasm() {
+        let table = SymbolTable::parse(
+            "Sections:\n\
+             00: \"seg8000\" (8000-807D)\n\
+             \n\
+             Symbols by name:\n\
+             DATA_START                       E:DDD0\n\
+             seed                             A:800A\n",
+        );
+
+        assert_eq!(2, table.len());
+        assert_eq!(Some(0xddd0), table.address("DATA_START"));
+        assert_eq!(Some("seed"), table.name(0x800a));
+    }
+
+    #[test]
+    fn test_first_name_wins() {
+        let mut table = SymbolTable::new();
+        table.insert("first", 0x1000);
+        table.insert("second", 0x1000);
+
+        assert_eq!(Some("first"), table.name(0x1000));
+        assert_eq!(Some(0x1000), table.address("second"));
+    }
+
+    #[test]
+    fn test_nearest() {
+        let mut table = SymbolTable::new();
+        table.insert("table", 0x1000);
+
+        assert_eq!(Some(("table", 0x10)), table.nearest(0x1010));
+        assert_eq!(None, table.nearest(0x0fff));
+    }
+
+    #[test]
+    fn test_ignores_garbage() {
+        let table = SymbolTable::parse("al\nsym\n; comment\nfoo bar baz\n");
+        assert!(table.is_empty());
+    }
+}
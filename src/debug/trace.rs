@@ -0,0 +1,59 @@
+use crate::debug::disassembler::disassemble;
+use crate::debug::symbols::SymbolTable;
+use crate::emulator::observer::Observer;
+use crate::emulator::Emulator;
+use crate::memory::Memory;
+
+/// Describe the instruction about to be executed and the state of the registers, on one line.
+pub fn trace<C: Memory, O: Observer>(emulator: &Emulator<C, O>, symbols: &SymbolTable) -> String {
+    let r = &emulator.registers;
+    let instruction = disassemble(&emulator.memory, r.program_counter);
+
+    let bytes: Vec<String> = instruction
+        .bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+
+    let label = symbols
+        .name(r.program_counter)
+        .map(|n| format!("{}:", n))
+        .unwrap_or_default();
+
+    format!(
+        "{:04x}  {:<9} {:<12} {:<20} a={:02x} x={:02x} y={:02x} sp={:02x} p={:08b}",
+        r.program_counter,
+        bytes.join(" "),
+        label,
+        instruction.format(|a| symbols.label(a)),
+        r.accumulator,
+        r.x,
+        r.y,
+        r.stack_pointer,
+        r.status.flags
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debug::symbols::SymbolTable;
+    use crate::debug::trace::trace;
+    use crate::emulator::instructions::opcodes::*;
+    use crate::emulator::tests::setup;
+
+    #[test]
+    fn test_trace() {
+        let mut e = setup(vec![]);
+        e.memory.load(JSR::absolute(0x8010), 0x600);
+        e.registers.accumulator = 0x42;
+
+        let mut symbols = SymbolTable::new();
+        symbols.insert("main", 0x600);
+        symbols.insert("seed", 0x8010);
+
+        assert_eq!(
+            "0600  20 10 80  main:        jsr seed             a=42 x=00 y=00 sp=ff p=00100000",
+            trace(&e, &symbols)
+        );
+    }
+}
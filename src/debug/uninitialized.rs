@@ -0,0 +1,159 @@
+//! Find reads of RAM that was never written.
+//!
+//! `DefaultMemory` starts out as zeroes, so a program that forgets to initialize a variable may
+//! work in the emulator and fail on real hardware. `UninitializedReads` is an observer that
+//! keeps track of which bytes were written, and reports reads of any others. Bytes that hold
+//! the program or data loaded with it, ROM and I/O have to be marked as initialized up front.
+//!
+//! Filling RAM with `memory::fill::Fill` before starting makes such bugs show up without it.
+
+use crate::emulator::observer::{Execution, Observer};
+use crate::emulator::registers::Registers;
+
+/// The number of reads kept. Later reads are only counted.
+const MAX_REPORTS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UninitializedRead {
+    pub address: u16,
+    /// The instruction that read it.
+    pub program_counter: u16,
+}
+
+pub struct UninitializedReads {
+    initialized: Vec<bool>,
+    program_counter: u16,
+    reports: Vec<UninitializedRead>,
+    count: usize,
+}
+
+impl UninitializedReads {
+    /// Consider all memory uninitialized.
+    pub fn new() -> UninitializedReads {
+        UninitializedReads {
+            initialized: vec![false; 0x10000],
+            program_counter: 0,
+            reports: Vec::new(),
+            count: 0,
+        }
+    }
+
+    /// Consider memory from `start` to `end` (both inclusive) initialized, because it was loaded
+    /// or is not RAM.
+    pub fn initialize(&mut self, start: u16, end: u16) {
+        for address in start..=end {
+            self.initialized[address as usize] = true;
+        }
+    }
+
+    pub fn is_initialized(&self, address: u16) -> bool {
+        self.initialized[address as usize]
+    }
+
+    /// The first uninitialized reads, in order. Each address is reported once.
+    pub fn reports(&self) -> &[UninitializedRead] {
+        &self.reports
+    }
+
+    /// The number of uninitialized reads, including those beyond what is kept in `reports`.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+impl Default for UninitializedReads {
+    fn default() -> UninitializedReads {
+        UninitializedReads::new()
+    }
+}
+
+impl Observer for UninitializedReads {
+    fn before_instruction(&mut self, address: u16, _opcode: u8, _registers: &Registers) {
+        self.program_counter = address;
+    }
+
+    fn after_instruction(&mut self, _execution: &Execution, registers: &Registers) {
+        // Reads before the next instruction are fetching it, or taking an interrupt.
+        self.program_counter = registers.program_counter;
+    }
+
+    fn read(&mut self, address: u16, _value: u8) {
+        if self.initialized[address as usize] {
+            return;
+        }
+
+        // Report the address once, rather than every time it is read.
+        self.initialized[address as usize] = true;
+        self.count += 1;
+        if self.reports.len() < MAX_REPORTS {
+            self.reports.push(UninitializedRead {
+                address,
+                program_counter: self.program_counter,
+            });
+        }
+    }
+
+    fn write(&mut self, address: u16, _value: u8) {
+        self.initialized[address as usize] = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::debug::uninitialized::{UninitializedRead, UninitializedReads};
+    use crate::emulator::instructions::opcodes::*;
+    use crate::emulator::Emulator;
+    use crate::memory::default::DefaultMemory;
+
+    fn emulator(program: Vec<Vec<u8>>) -> Emulator<DefaultMemory, UninitializedReads> {
+        let program = program.concat();
+        let end = 0x600 + program.len() as u16 - 1;
+
+        let mut memory = DefaultMemory::empty();
+        memory.load(program, 0x600);
+        memory.set_program_counter(0x600);
+
+        let mut checker = UninitializedReads::new();
+        checker.initialize(0x600, end);
+        checker.initialize(0xfffc, 0xfffd);
+
+        Emulator::with_observer(memory, checker)
+    }
+
+    #[test]
+    fn test_written_before_read() {
+        let mut e = emulator(vec![STA::zero_page(0x10), LDA::zero_page(0x10)]);
+        e.run(2);
+
+        assert_eq!(0, e.observer.count());
+    }
+
+    #[test]
+    fn test_read_before_written() {
+        let mut e = emulator(vec![
+            LDX::immediate(1),
+            LDA::zero_page_x(0x10),
+            LDA::zero_page_x(0x10),
+        ]);
+        e.run(3);
+
+        assert_eq!(
+            &[UninitializedRead {
+                address: 0x11,
+                program_counter: 0x602
+            }],
+            e.observer.reports()
+        );
+        assert_eq!(1, e.observer.count());
+    }
+
+    #[test]
+    fn test_execute_uninitialized() {
+        let mut e = emulator(vec![JMP::absolute(0x700)]);
+        e.memory.load(NOP::implied(), 0x700);
+        e.run(2);
+
+        assert_eq!(0x700, e.observer.reports()[0].address);
+        assert_eq!(0x700, e.observer.reports()[0].program_counter);
+    }
+}
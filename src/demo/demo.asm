@@ -1,13 +1,19 @@
 DATA_START = $ddd0       ; First of 8 output bytes.
 RANDOM = $eee0           ; Random number seed.
-KEY = $fff0              ; Key press, either 'a' or 'd'.
-KEY_IRQ_HANDLED = $fff1  ; Handled interrupt.
+ACIA_DATA = $fff0        ; Key presses arrive through the 6551 ACIA.
+ACIA_STATUS = $fff1
+ACIA_COMMAND = $fff2
+ACIA_CONTROL = $fff3
 
   .org $8000
 
 reset:
   ldx #$ff
   txs
+  lda #$1f               ; 19200 baud, 8 data bits, 1 stop bit.
+  sta ACIA_CONTROL
+  lda #$09               ; Receive interrupts on, transmit interrupts off.
+  sta ACIA_COMMAND
   jsr seed
   cli                    ; Accept interrupts from here on out.
   jmp idle
@@ -32,7 +38,7 @@ seed:
   rts
 
 check_move:
-  ldy KEY
+  ldy ACIA_DATA
   cpy #'r'
   beq seed
   cpy #'a'
@@ -112,9 +118,11 @@ move_left:
 
 iterrupt_handler:
   sei
+  lda ACIA_STATUS        ; Reading the status releases IRQ.
+  and #$08               ; Was a key received?
+  beq handled
   jsr check_move
-  lda #$0
-  sta KEY_IRQ_HANDLED
+handled:
   cli
   rti                    ; Note that an interrupt can come in before this.
 
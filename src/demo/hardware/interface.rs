@@ -11,8 +11,7 @@ impl HardwareInterface {
     const ADDR_RND: u16 = 0xeee0;
     const ADDR_DATA_START: usize = 0xddd0;
     const ADDR_DATA_END: usize = 0xddd8;
-    const ADDR_KEY: u16 = 0xfff0;
-    const ADDR_KEY_IRQ_HANDLED: u16 = 0xfff1;
+    pub(crate) const ADDR_ACIA: u16 = 0xfff0;
     const STACK_RANGE: i8 = 5;
 
     pub(crate) fn from_binary(instructions: Vec<u8>, start_location: usize) -> HardwareInterface {
@@ -43,18 +42,6 @@ impl HardwareInterface {
             })
             .collect()
     }
-
-    pub(crate) fn send_key(&mut self, key: char) {
-        self.write(Self::ADDR_KEY, key as u8)
-    }
-
-    pub(crate) fn set_irq(&mut self) {
-        self.write(Self::ADDR_KEY_IRQ_HANDLED, true as u8)
-    }
-
-    pub(crate) fn is_irq_handled(&self) -> bool {
-        self.read(Self::ADDR_KEY_IRQ_HANDLED) == 0
-    }
 }
 
 impl Memory for HardwareInterface {
@@ -98,21 +85,6 @@ mod tests {
         assert_ne!(first, second);
     }
 
-    #[test]
-    fn test_irq_handled() {
-        let mut interface = HardwareInterface::from_binary(vec![], 0x0);
-
-        assert_eq!(true, interface.is_irq_handled());
-
-        // Request IRQ.
-        interface.set_irq();
-        assert_eq!(false, interface.is_irq_handled());
-
-        // 6502 write.
-        interface.memory[HardwareInterface::ADDR_KEY_IRQ_HANDLED as usize] = 0;
-        assert_eq!(true, interface.is_irq_handled());
-    }
-
     #[test]
     fn test_stack_around_stack_pointer() {
         let interface = HardwareInterface::from_binary(vec![1, 2, 3, 4, 5, 6, 7], 0x1fa);
@@ -3,13 +3,19 @@ extern crate crossterm;
 extern crate emulator;
 extern crate rand;
 
-use std::io::stdout;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{sink, stdout};
+use std::rc::Rc;
 use std::sync::mpsc::{channel, TryRecvError};
 use std::thread;
 use std::time::{Duration, Instant};
 
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 
+use emulator::devices::acia::Acia;
+use emulator::devices::bus;
+use emulator::devices::bus::{Bus, Line};
 use emulator::emulator::Emulator;
 use emulator::memory::Memory;
 use hardware::display::{display, DisplayData, Mode};
@@ -32,12 +38,26 @@ impl Mode {
 
 const REFRESH_INTERVAL_MS: u64 = 50;
 
+/// Cycles to run at a time in Run mode, a few instructions.
+const RUN_CYCLES: u64 = 10;
+
 fn main() {
     // Create memory with our assembled program.
     let memory = HardwareInterface::from_binary(asm6502!("demo"), 0x8000);
 
+    // Key presses are sent over a serial line.
+    let acia = Rc::new(RefCell::new(Acia::new(VecDeque::new(), sink())));
+    let mut bus = Bus::new(memory);
+    bus.attach(
+        "acia",
+        HardwareInterface::ADDR_ACIA,
+        4,
+        acia.clone(),
+        Line::Irq,
+    );
+
     // Attach memory to the emulator.
-    let mut emulator = Emulator::new(memory);
+    let mut emulator = Emulator::new(bus);
 
     // Fire up display peripheral.
     let (display_sender, display_receiver) = channel();
@@ -61,7 +81,7 @@ fn main() {
 
     loop {
         // Type annotation present to unconfuse the IntelliJ plugin.
-        let interface: &mut HardwareInterface = &mut emulator.memory;
+        let interface: &HardwareInterface = &emulator.memory.memory;
 
         // Refresh screen immediately in Step mode (which waits for keypress), avoid
         // too many refreshes in Run mode.
@@ -78,11 +98,6 @@ fn main() {
             last_refresh = Instant::now();
         }
 
-        // Release interrupt request if appropriate.
-        if interface.is_irq_handled() {
-            emulator.irq = false;
-        }
-
         // Check for key press.
         let input_data = match step_mode {
             Mode::Step => match keys_receiver.recv() {
@@ -104,18 +119,20 @@ fn main() {
                     step_mode = step_mode.toggle();
                 }
 
-                EmulatorKey { c } => {
-                    interface.send_key(c);
-                    interface.set_irq();
-                    emulator.irq = true;
-                }
+                EmulatorKey { c } => acia.borrow_mut().input().push_back(c as u8),
 
                 AnyKey => {}
             }
         }
 
-        // Execute instruction.
-        emulator.execute_next();
+        // Execute a single instruction in Step mode, as each takes at least two cycles. The ACIA
+        // is only clocked when it has something to do or is accessed.
+        let until = emulator.cycles
+            + match step_mode {
+                Mode::Step => 1,
+                Mode::Run => RUN_CYCLES,
+            };
+        bus::run_until(&mut emulator, until);
 
         // Avoid using too much CPU.
         thread::sleep(Duration::from_micros(250));
@@ -0,0 +1,317 @@
+//! The MOS 6551 Asynchronous Communications Interface Adapter, a serial port.
+//!
+//! The other end of the serial line is any `Read` and `Write` pair: stdin and stdout, a pipe, a
+//! pseudo-terminal, or a buffer in tests. Characters take as long to send and receive as they
+//! would at the configured baud rate. Input is read once the previous character would have been
+//! received, and a reader that returns `Ok(0)` or `WouldBlock` has nothing to send yet, so a
+//! `VecDeque<u8>` can be fed while the emulator runs. A reader that blocks, like stdin, makes the
+//! emulator wait for input while the receiver is enabled.
+
+use std::io::{Read, Write};
+
+use crate::devices::{send, Device};
+
+// Registers.
+const DATA: u16 = 0;
+const STATUS: u16 = 1;
+const COMMAND: u16 = 2;
+const CONTROL: u16 = 3;
+
+// Status register.
+const OVERRUN: u8 = 0x04;
+const RECEIVER_FULL: u8 = 0x08;
+const TRANSMITTER_EMPTY: u8 = 0x10;
+const INTERRUPT: u8 = 0x80;
+
+// Command register.
+const DTR: u8 = 0x01;
+const RECEIVER_INTERRUPT_DISABLE: u8 = 0x02;
+const TRANSMITTER_CONTROL: u8 = 0x0c;
+const TRANSMITTER_INTERRUPT: u8 = 0x04;
+const ECHO: u8 = 0x10;
+const PARITY_ENABLE: u8 = 0x20;
+
+/// Baud rates selected by the control register. The first is the external clock, which is
+/// taken to be as fast as the fastest internal rate.
+const BAUD_RATES: [u32; 16] = [
+    19200, 50, 75, 110, 135, 150, 300, 600, 1200, 1800, 2400, 3600, 4800, 7200, 9600, 19200,
+];
+
+/// The clock of the processor unless told otherwise.
+const DEFAULT_CLOCK: u32 = 1_000_000;
+
+pub struct Acia<R: Read, W: Write> {
+    input: R,
+    output: W,
+    /// Processor clock cycles per second, to time characters.
+    clock: u32,
+    data: u8,
+    status: u8,
+    command: u8,
+    control: u8,
+    /// The character being sent, and the cycles until it is.
+    transmitting: Option<u8>,
+    transmit_cycles: u64,
+    /// Cycles until the next character can be received.
+    receive_cycles: u64,
+}
+
+impl<R: Read, W: Write> Acia<R, W> {
+    /// An ACIA next to a processor running at 1 MHz.
+    pub fn new(input: R, output: W) -> Acia<R, W> {
+        Acia::with_clock(input, output, DEFAULT_CLOCK)
+    }
+
+    /// An ACIA next to a processor running at `clock` cycles per second.
+    pub fn with_clock(input: R, output: W, clock: u32) -> Acia<R, W> {
+        Acia {
+            input,
+            output,
+            clock,
+            data: 0,
+            status: TRANSMITTER_EMPTY,
+            command: 0,
+            control: 0,
+            transmitting: None,
+            transmit_cycles: 0,
+            receive_cycles: 0,
+        }
+    }
+
+    pub fn input(&mut self) -> &mut R {
+        &mut self.input
+    }
+
+    pub fn output(&mut self) -> &mut W {
+        &mut self.output
+    }
+
+    /// Clock cycles to send or receive a character: a start bit, the data bits, the parity bit
+    /// and the stop bits.
+    fn character_cycles(&self) -> u64 {
+        let data_bits = 8 - ((self.control >> 5) & 0x3) as u64;
+        let parity_bits = (self.command & PARITY_ENABLE != 0) as u64;
+        let stop_bits = 1 + (self.control >> 7) as u64;
+        let bits = 1 + data_bits + parity_bits + stop_bits;
+
+        let baud = BAUD_RATES[(self.control & 0xf) as usize] as u64;
+        (self.clock as u64 * bits / baud).max(1)
+    }
+
+    fn interrupt_request(&mut self) {
+        self.status |= INTERRUPT;
+    }
+
+    fn transmit(&mut self) {
+        if let Some(value) = self.transmitting.take() {
+            send(&mut self.output, &[value]);
+
+            self.status |= TRANSMITTER_EMPTY;
+            if self.command & TRANSMITTER_CONTROL == TRANSMITTER_INTERRUPT {
+                self.interrupt_request();
+            }
+        }
+    }
+
+    fn receive(&mut self) {
+        let mut byte = [0];
+        let value = match self.input.read(&mut byte) {
+            Ok(1) => byte[0],
+            // Nothing to receive yet, or an error which leaves nothing to receive either.
+            _ => return,
+        };
+
+        if self.status & RECEIVER_FULL != 0 {
+            // The character is lost.
+            self.status |= OVERRUN;
+        } else {
+            self.data = value;
+            self.status |= RECEIVER_FULL;
+        }
+
+        if self.command & RECEIVER_INTERRUPT_DISABLE == 0 {
+            self.interrupt_request();
+        }
+
+        // Echo mode sends what is received, while the transmitter is off.
+        if self.command & (ECHO | TRANSMITTER_CONTROL) == ECHO {
+            send(&mut self.output, &[value]);
+        }
+    }
+}
+
+impl<R: Read, W: Write> Device for Acia<R, W> {
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset & 0x3 {
+            DATA => {
+                self.status &= !(RECEIVER_FULL | OVERRUN);
+                self.data
+            }
+            STATUS => {
+                // Reading the status acknowledges the interrupt.
+                let status = self.status;
+                self.status &= !INTERRUPT;
+                status
+            }
+            COMMAND => self.command,
+            _ => self.control,
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        match offset & 0x3 {
+            DATA => {
+                // A character written while another is being sent replaces it.
+                self.transmitting = Some(value);
+                self.transmit_cycles = self.character_cycles();
+                self.status &= !TRANSMITTER_EMPTY;
+            }
+            STATUS => {
+                // A programmed reset.
+                self.command &= 0xe0;
+                self.status &= !OVERRUN;
+            }
+            COMMAND => self.command = value,
+            CONTROL => self.control = value,
+            _ => unreachable!(),
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        if self.transmitting.is_some() {
+            self.transmit_cycles = self.transmit_cycles.saturating_sub(cycles);
+            if self.transmit_cycles == 0 {
+                self.transmit();
+            }
+        }
+
+        // The receiver is only enabled while DTR is set. It takes in as many characters as
+        // would have arrived in the time.
+        if self.command & DTR == 0 {
+            return;
+        }
+        let mut cycles = cycles;
+        while cycles >= self.receive_cycles {
+            cycles -= self.receive_cycles;
+            self.receive();
+            self.receive_cycles = self.character_cycles();
+        }
+        self.receive_cycles -= cycles;
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        let transmit = Some(self.transmit_cycles).filter(|_| self.transmitting.is_some());
+        let receive = Some(self.receive_cycles).filter(|_| self.command & DTR != 0);
+        transmit.into_iter().chain(receive).min()
+    }
+
+    fn interrupt(&self) -> bool {
+        self.status & INTERRUPT != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::io::{empty, Empty};
+
+    use crate::devices::acia::*;
+    use crate::devices::Device;
+
+    fn acia(input: &str) -> Acia<VecDeque<u8>, Vec<u8>> {
+        let mut acia = Acia::new(input.bytes().collect(), Vec::new());
+        // 9600 baud, 8 data bits and 1 stop bit, so 1041 cycles per character.
+        acia.write(CONTROL, 0x1e);
+        acia
+    }
+
+    #[test]
+    fn test_transmit() {
+        let mut acia = acia("");
+        acia.write(DATA, b'A');
+        assert_eq!(0, acia.read(STATUS) & TRANSMITTER_EMPTY);
+
+        acia.tick(1000);
+        assert!(acia.output().is_empty());
+        acia.tick(41);
+
+        assert_eq!(b"A", acia.output().as_slice());
+        assert_eq!(TRANSMITTER_EMPTY, acia.read(STATUS) & TRANSMITTER_EMPTY);
+        assert!(!acia.interrupt());
+    }
+
+    #[test]
+    fn test_transmit_interrupt() {
+        let mut acia = acia("");
+        acia.write(
+            COMMAND,
+            DTR | RECEIVER_INTERRUPT_DISABLE | TRANSMITTER_INTERRUPT,
+        );
+        acia.write(DATA, b'A');
+        acia.tick(1041);
+
+        assert!(acia.interrupt());
+        assert_eq!(INTERRUPT, acia.read(STATUS) & INTERRUPT);
+        assert!(!acia.interrupt());
+    }
+
+    #[test]
+    fn test_receive() {
+        let mut acia = acia("hi");
+        acia.tick(10);
+        assert_eq!(0, acia.read(STATUS) & RECEIVER_FULL);
+
+        // Receiving is enabled with DTR.
+        acia.write(COMMAND, DTR | 0x08);
+        acia.tick(1);
+        assert!(acia.interrupt());
+        assert_eq!(RECEIVER_FULL, acia.read(STATUS) & RECEIVER_FULL);
+        assert_eq!(b'h', acia.read(DATA));
+        assert_eq!(0, acia.read(STATUS) & RECEIVER_FULL);
+
+        acia.tick(1041);
+        assert_eq!(b'i', acia.read(DATA));
+
+        // Nothing more to receive.
+        acia.tick(1041);
+        assert_eq!(0, acia.read(STATUS) & RECEIVER_FULL);
+        acia.input().push_back(b'!');
+        acia.tick(1041);
+        assert_eq!(b'!', acia.read(DATA));
+    }
+
+    #[test]
+    fn test_overrun() {
+        let mut acia = acia("ab");
+        acia.write(COMMAND, DTR | RECEIVER_INTERRUPT_DISABLE);
+        acia.tick(1);
+        acia.tick(1041);
+
+        assert!(!acia.interrupt());
+        assert_eq!(RECEIVER_FULL | OVERRUN, acia.read(STATUS) & 0x0f);
+        assert_eq!(b'a', acia.read(DATA));
+        assert_eq!(0, acia.read(STATUS) & OVERRUN);
+    }
+
+    #[test]
+    fn test_echo() {
+        let mut acia = acia("echo");
+        acia.write(COMMAND, DTR | RECEIVER_INTERRUPT_DISABLE | ECHO);
+        acia.tick(1);
+        assert_eq!(b"e", acia.output().as_slice());
+
+        // A long tick takes in every character that arrived in it.
+        assert_eq!(Some(1040), acia.next_event());
+        acia.tick(3 * 1041);
+        assert_eq!(b"echo", acia.output().as_slice());
+    }
+
+    #[test]
+    fn test_programmed_reset() {
+        let mut acia: Acia<Empty, Vec<u8>> = Acia::new(empty(), Vec::new());
+        acia.write(COMMAND, 0xff);
+        acia.write(STATUS, 0);
+
+        assert_eq!(0xe0, acia.read(COMMAND));
+    }
+}
@@ -0,0 +1,395 @@
+//! The keyboard and display of the Apple-1, through the 6821 PIA at $D010-$D013.
+//!
+//! The keyboard drives port A with an ASCII character with bit 7 set, and strobes CA1 when a key
+//! is pressed. The display takes the lower 7 bits of port B when the processor writes port B and
+//! CB2 goes low, and signals that it is ready for the next character on CB1. Programs see them
+//! through four registers, which is all that WozMon and Apple-1 BASIC use:
+//!
+//! | Address | Register | Purpose |
+//! |---------|----------|---------|
+//! | $D010   | KBD      | The last key, with bit 7 set |
+//! | $D011   | KBDCR    | Bit 7 is set when a key was pressed, until KBD is read |
+//! | $D012   | DSP      | The character to display, bit 7 is set while the display is busy |
+//! | $D013   | DSPCR    | Control register of the display port |
+//!
+//! The Apple-1 only knows upper case, and a carriage return ends a line, so lower case input is
+//! turned into upper case and newlines into carriage returns. Like for the ACIA, a reader that
+//! returns `Ok(0)` or `WouldBlock` has no key pressed yet.
+//!
+//! The PIA interrupts when a key is pressed or the display is ready if its control registers say
+//! so, which WozMon's do. The Apple-1 leaves IRQA and IRQB unconnected though, so it attaches the
+//! terminal to `Line::Unconnected`.
+//!
+//! The cassette interface is a separate card with its own ROM, see `Cassette`.
+
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+
+use crate::devices::pia::{Pia, IRQ1};
+use crate::devices::{send, Device};
+
+/// Where the Apple-1 has its PIA.
+pub const ADDRESS: u16 = 0xd010;
+
+// Registers.
+const KBDCR: u16 = 0x1;
+
+const CARRIAGE_RETURN: u8 = 0x0d;
+/// WozMon and BASIC take an underscore to rub out the last character.
+const RUB_OUT: u8 = b'_';
+
+/// Where the cassette interface has its registers, followed by its ROM.
+pub const CASSETTE_ADDRESS: u16 = 0xc000;
+
+/// The PIA with the keyboard and the display wired to it.
+pub struct Terminal<R: Read, W: Write> {
+    pub pia: Pia,
+    input: R,
+    output: W,
+    /// Cycles the display takes to show a character, and the cycles until it is ready again.
+    display_cycles: u64,
+    busy: u64,
+}
+
+impl<R: Read, W: Write> Terminal<R, W> {
+    /// A terminal with a display that is as fast as the program writing to it.
+    pub fn new(input: R, output: W) -> Terminal<R, W> {
+        Terminal::with_display_cycles(input, output, 0)
+    }
+
+    /// A terminal with a display that takes `cycles` clock cycles per character. The real one
+    /// shows about 60 characters per second.
+    pub fn with_display_cycles(input: R, output: W, cycles: u64) -> Terminal<R, W> {
+        // The display is ready, which it signals with PB7 low.
+        let mut pia = Pia::new();
+        pia.set_port_b(0x00);
+
+        Terminal {
+            pia,
+            input,
+            output,
+            display_cycles: cycles,
+            busy: 0,
+        }
+    }
+
+    pub fn input(&mut self) -> &mut R {
+        &mut self.input
+    }
+
+    pub fn output(&mut self) -> &mut W {
+        &mut self.output
+    }
+
+    /// Press the next key of the input, once the program took the last one.
+    fn keyboard(&mut self) {
+        if self.pia.read(KBDCR) & IRQ1 != 0 {
+            return;
+        }
+
+        let mut byte = [0];
+        let key = match self.input.read(&mut byte) {
+            Ok(1) => match byte[0] {
+                b'\n' => CARRIAGE_RETURN,
+                0x08 | 0x7f => RUB_OUT,
+                key => key.to_ascii_uppercase(),
+            },
+            // No key pressed yet, or an error which leaves no key either.
+            _ => return,
+        };
+
+        self.pia.set_port_a(key | 0x80);
+        self.pia.set_ca1(false);
+        self.pia.set_ca1(true);
+    }
+
+    /// Show the character on port B, and keep the display busy for as long as that takes.
+    fn display(&mut self) {
+        let character = match self.pia.port_b() & 0x7f {
+            CARRIAGE_RETURN => Some(b'\n'),
+            c if (0x20..0x7f).contains(&c) => Some(c),
+            _ => None,
+        };
+
+        if let Some(c) = character {
+            send(&mut self.output, &[c]);
+        }
+
+        if self.display_cycles == 0 {
+            self.ready();
+        } else {
+            self.busy = self.display_cycles;
+            self.pia.set_port_b(0x80);
+        }
+    }
+
+    /// Signal that the display is ready for the next character.
+    fn ready(&mut self) {
+        self.pia.set_port_b(0x00);
+        self.pia.set_cb1(false);
+        self.pia.set_cb1(true);
+    }
+}
+
+impl<R: Read, W: Write> Device for Terminal<R, W> {
+    fn read(&mut self, offset: u16) -> u8 {
+        // Programs wait for a key by polling, so see whether one was pressed first.
+        if offset & 0x3 == KBDCR {
+            self.keyboard();
+        }
+        self.pia.read(offset)
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        let cb2 = self.pia.cb2();
+        self.pia.write(offset, value);
+
+        // The display takes the character as CB2 goes low.
+        if cb2 && !self.pia.cb2() {
+            self.display();
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.pia.tick(cycles);
+        self.keyboard();
+
+        if self.busy > 0 {
+            self.busy = self.busy.saturating_sub(cycles);
+            if self.busy == 0 {
+                self.ready();
+            }
+        }
+    }
+
+    /// Keys are looked at when the keyboard control register is read, and otherwise whenever the
+    /// bus clocks the terminal.
+    fn next_event(&self) -> Option<u64> {
+        let busy = Some(self.busy).filter(|&busy| busy > 0);
+        self.pia.next_event().into_iter().chain(busy).min()
+    }
+
+    fn interrupt(&self) -> bool {
+        self.pia.interrupt()
+    }
+}
+
+/// The Apple Cassette Interface, which saves and loads programs as sound.
+///
+/// Any access to the first 128 of its 256 addresses toggles the output, and reading the other 128
+/// reads the ROM with address bit 0 replaced by the level of the input. The ROM itself follows
+/// at $C100. A tape is the cycles between the changes of level, which is what the ROM measures
+/// to tell the bits apart.
+pub struct Cassette {
+    rom: [u8; 256],
+    /// Clock cycles since the interface was created.
+    cycles: u64,
+    /// The cycle at which the output last changed, once it did.
+    toggled: Option<u64>,
+    recording: Vec<u64>,
+    input: bool,
+    /// The tape being played, and the cycle at which the input changes next.
+    tape: VecDeque<u64>,
+    next_edge: u64,
+}
+
+impl Cassette {
+    pub fn new(rom: [u8; 256]) -> Cassette {
+        Cassette {
+            rom,
+            cycles: 0,
+            toggled: None,
+            recording: Vec::new(),
+            input: false,
+            tape: VecDeque::new(),
+            next_edge: 0,
+        }
+    }
+
+    /// Start playing `tape` from here, replacing any tape that is still playing.
+    pub fn play(&mut self, tape: Vec<u64>) {
+        self.tape = tape.into();
+        self.next_edge = self.cycles + self.tape.front().copied().unwrap_or(0);
+    }
+
+    /// Whether the tape is still playing.
+    pub fn is_playing(&self) -> bool {
+        !self.tape.is_empty()
+    }
+
+    /// What the program saved so far, as the cycles between the changes of the output.
+    pub fn recording(&self) -> &[u64] {
+        &self.recording
+    }
+
+    fn access(&mut self, offset: u16) -> u8 {
+        let offset = offset & 0x1ff;
+        if offset >= 0x100 {
+            return self.rom[(offset & 0xff) as usize];
+        }
+
+        if offset & 0x80 != 0 {
+            return self.rom[((offset & 0xfe) | self.input as u16) as usize];
+        }
+
+        if let Some(toggled) = self.toggled {
+            self.recording.push(self.cycles - toggled);
+        }
+        self.toggled = Some(self.cycles);
+        self.rom[offset as usize]
+    }
+}
+
+impl Device for Cassette {
+    fn read(&mut self, offset: u16) -> u8 {
+        self.access(offset)
+    }
+
+    fn write(&mut self, offset: u16, _value: u8) {
+        self.access(offset);
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.cycles += cycles;
+
+        while !self.tape.is_empty() && self.cycles >= self.next_edge {
+            self.input = !self.input;
+            self.tape.pop_front();
+            if let Some(interval) = self.tape.front() {
+                self.next_edge += interval;
+            }
+        }
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    use crate::devices::apple1::{Cassette, Terminal, ADDRESS};
+    use crate::devices::bus::{step, Bus, Line};
+    use crate::devices::Device;
+    use crate::emulator::instructions::opcodes::{
+        Absolute, Immediate, Implied, Relative, BIT, BMI, BPL, JMP, JSR, LDA, LDY, RTS, STA, STY,
+    };
+    use crate::emulator::Emulator;
+    use crate::memory::default::DefaultMemory;
+
+    type Apple1Terminal = Terminal<VecDeque<u8>, Vec<u8>>;
+
+    /// Set up the PIA and echo every key, the way WozMon does.
+    fn emulator(input: &str) -> (Emulator<Bus<DefaultMemory>>, Rc<RefCell<Apple1Terminal>>) {
+        let mut memory = DefaultMemory::empty();
+        memory.load(
+            [
+                // Reset.
+                LDY::immediate(0x7f),
+                STY::absolute(0xd012),
+                LDA::immediate(0xa7),
+                STA::absolute(0xd011),
+                STA::absolute(0xd013),
+                // Wait for a key, at $060d.
+                LDA::absolute(0xd011),
+                BPL::relative(-5),
+                LDA::absolute(0xd010),
+                JSR::absolute(0x061b),
+                JMP::absolute(0x060d),
+                // Echo, at $061b.
+                BIT::absolute(0xd012),
+                BMI::relative(-5),
+                STA::absolute(0xd012),
+                RTS::implied(),
+            ]
+            .concat(),
+            0x600,
+        );
+        memory.set_program_counter(0x600);
+
+        let terminal = Rc::new(RefCell::new(Terminal::new(
+            input.bytes().collect(),
+            Vec::new(),
+        )));
+        let mut bus = Bus::new(memory);
+        bus.attach("terminal", ADDRESS, 4, terminal.clone(), Line::Irq);
+
+        (Emulator::new(bus), terminal)
+    }
+
+    #[test]
+    fn test_echo() {
+        let (mut e, terminal) = emulator("hello\n");
+        for _ in 0..200 {
+            step(&mut e);
+        }
+
+        assert_eq!(b"HELLO\n", terminal.borrow_mut().output().as_slice());
+        assert!(terminal.borrow_mut().input().is_empty());
+    }
+
+    #[test]
+    fn test_keys_wait_for_the_program() {
+        let (mut e, terminal) = emulator("ab");
+        // The keyboard does not press the next key until the program read the last one.
+        for _ in 0..5 {
+            step(&mut e);
+        }
+        assert_eq!(b"b", terminal.borrow_mut().input().make_contiguous());
+        assert!(terminal.borrow_mut().output().is_empty());
+
+        for _ in 0..20 {
+            step(&mut e);
+        }
+        assert_eq!(b"AB", terminal.borrow_mut().output().as_slice());
+    }
+
+    #[test]
+    fn test_display_rate() {
+        let mut terminal = Terminal::with_display_cycles(VecDeque::new(), Vec::new(), 100);
+        terminal.write(0x2, 0x7f);
+        terminal.write(0x3, 0xa7);
+
+        terminal.write(0x2, b'A' | 0x80);
+        assert_eq!(b"A", terminal.output().as_slice());
+        assert_eq!(0x80, terminal.read(0x2) & 0x80);
+
+        terminal.tick(99);
+        assert_eq!(0x80, terminal.read(0x2) & 0x80);
+        terminal.tick(1);
+        assert_eq!(0x00, terminal.read(0x2) & 0x80);
+    }
+
+    #[test]
+    fn test_cassette() {
+        let mut rom = [0; 256];
+        rom[0x80] = 0xaa;
+        rom[0x81] = 0xab;
+        rom[0xff] = 0x42;
+        let mut cassette = Cassette::new(rom);
+        assert_eq!(0x42, cassette.read(0x1ff));
+
+        // Record.
+        cassette.read(0x00);
+        cassette.tick(100);
+        cassette.write(0x00, 0);
+        cassette.tick(50);
+        cassette.read(0x00);
+        assert_eq!(&[100, 50], cassette.recording());
+
+        // Play.
+        cassette.play(vec![10, 20]);
+        assert_eq!(0xaa, cassette.read(0x81));
+        cassette.tick(10);
+        assert_eq!(0xab, cassette.read(0x81));
+        cassette.tick(19);
+        assert_eq!(0xab, cassette.read(0x81));
+        cassette.tick(1);
+        assert_eq!(0xaa, cassette.read(0x81));
+        assert!(!cassette.is_playing());
+    }
+}
@@ -0,0 +1,518 @@
+//! Memory with devices mapped into it.
+//!
+//! Devices are shared with the code that set up the bus through `Rc<RefCell<_>>`, so that it can
+//! still reach them to feed input or look at output, while the processor reads and writes their
+//! registers. `step` and `run` execute instructions, clock the devices with the cycles each
+//! instruction took, and hand their interrupt outputs to the processor, together with the
+//! sources of its `interrupts`. They also run the events of its `scheduler` as they come due.
+//!
+//! `run_until` instead clocks the devices and updates the interrupt lines only when something may
+//! have changed: an event is due, a device said it would do something by then, or the program
+//! accessed a device. Before an access, the devices are clocked up to the instruction making it,
+//! so the program sees them as `step` would have left them.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::devices::interrupts::{signal, Interrupts, Source};
+use crate::devices::scheduler::Scheduler;
+use crate::devices::Device;
+use crate::emulator::observer::Observer;
+use crate::emulator::run::StopReason;
+use crate::emulator::Emulator;
+use crate::memory::Memory;
+
+/// The processor input an interrupt output is connected to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Line {
+    Irq,
+    Nmi,
+    /// Neither, for a chip whose interrupt output the machine leaves unconnected.
+    Unconnected,
+}
+
+struct Mapping {
+    start: u16,
+    end: u16,
+    device: Rc<RefCell<dyn Device>>,
+    /// The interrupt output of the device.
+    source: Source,
+}
+
+pub struct Bus<M: Memory> {
+    pub memory: M,
+    /// Interrupt sources, with one for each device.
+    pub interrupts: Interrupts,
+    /// Events at given cycles of the processor, which are given the bus.
+    pub scheduler: Scheduler<Bus<M>>,
+    mappings: Vec<Mapping>,
+    /// Whether NMI was asserted after the last instruction, to take it once each time it is
+    /// asserted.
+    nmi: bool,
+    /// The cycle the devices are clocked up to, and the one the current instruction started at.
+    clocked: Cell<u64>,
+    cycle: Cell<u64>,
+    /// Whether a device was accessed since the devices were last clocked.
+    accessed: Cell<bool>,
+}
+
+impl<M: Memory> Bus<M> {
+    pub fn new(memory: M) -> Bus<M> {
+        Bus {
+            memory,
+            interrupts: Interrupts::new(),
+            scheduler: Scheduler::new(),
+            mappings: Vec::new(),
+            nmi: false,
+            clocked: Cell::new(0),
+            cycle: Cell::new(0),
+            accessed: Cell::new(false),
+        }
+    }
+
+    /// Map `device` to `size` addresses from `start`, taking precedence over memory and devices
+    /// attached earlier. Devices with fewer registers repeat them over the addresses, the way
+    /// chips that only decode some of the address lines do. Its interrupt output is connected to
+    /// `line`, as a source called `name`.
+    pub fn attach<D: Device + 'static>(
+        &mut self,
+        name: &str,
+        start: u16,
+        size: u16,
+        device: Rc<RefCell<D>>,
+        line: Line,
+    ) {
+        assert!(size > 0 && start as u32 + size as u32 <= 0x10000);
+
+        let source = self.interrupts.source(name, line);
+        self.mappings.insert(
+            0,
+            Mapping {
+                start,
+                end: start + (size - 1),
+                device,
+                source,
+            },
+        );
+    }
+
+    fn mapping(&self, address: u16) -> Option<&Mapping> {
+        self.mappings
+            .iter()
+            .find(|m| m.start <= address && address <= m.end)
+    }
+
+    /// Let `cycles` clock cycles pass for every device.
+    pub fn tick(&mut self, cycles: u64) {
+        self.tick_devices(cycles);
+    }
+
+    fn tick_devices(&self, cycles: u64) {
+        for mapping in self.mappings.iter() {
+            mapping.device.borrow_mut().tick(cycles);
+        }
+    }
+
+    /// Take the devices to be clocked up to `cycle`, at which the next instruction starts.
+    fn start(&self, cycle: u64) {
+        self.clocked.set(cycle);
+        self.cycle.set(cycle);
+    }
+
+    /// Clock the devices up to the start of the current instruction, before it accesses one.
+    fn catch_up(&self) {
+        let cycles = self.cycle.get() - self.clocked.get();
+        if cycles > 0 {
+            self.tick_devices(cycles);
+            self.clocked.set(self.cycle.get());
+        }
+        self.accessed.set(true);
+    }
+
+    /// The cycle at which the next event is due or the first device does something on its own,
+    /// if any will.
+    fn next_due(&self) -> Option<u64> {
+        let devices = self
+            .mappings
+            .iter()
+            .filter_map(|m| m.device.borrow().next_event())
+            .min()
+            .map(|cycles| self.clocked.get() + cycles);
+        self.scheduler.next_due().into_iter().chain(devices).min()
+    }
+
+    /// Run the events of the scheduler due at or before `cycle`, giving them the bus.
+    fn run_events(&mut self, cycle: u64) {
+        while let Some(mut event) = self.scheduler.take_due(cycle) {
+            event.run(self);
+            self.scheduler.repeat(event);
+        }
+    }
+
+    /// Whether any device or other source connected to `line` asserts it.
+    pub fn asserts(&self, line: Line) -> bool {
+        self.update_sources();
+        self.interrupts.asserts(line)
+    }
+
+    /// The names of the devices and other sources asserting `line`, in the order they were
+    /// attached or made.
+    pub fn holding(&self, line: Line) -> Vec<String> {
+        self.update_sources();
+        self.interrupts.holding(line)
+    }
+
+    /// Set the sources of the devices from their interrupt outputs.
+    fn update_sources(&self) {
+        for mapping in self.mappings.iter() {
+            mapping.source.set(mapping.device.borrow().interrupt());
+        }
+    }
+}
+
+impl<M: Memory> Memory for Bus<M> {
+    fn read(&self, address: u16) -> u8 {
+        match self.mapping(address) {
+            Some(m) => {
+                self.catch_up();
+                m.device.borrow_mut().read(address - m.start)
+            }
+            None => self.memory.read(address),
+        }
+    }
+
+    /// The memory under the devices, as reading a device may change it.
+    fn peek(&self, address: u16) -> u8 {
+        self.memory.peek(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match self.mapping(address) {
+            Some(m) => {
+                self.catch_up();
+                m.device.borrow_mut().write(address - m.start, value)
+            }
+            None => self.memory.write(address, value),
+        }
+    }
+}
+
+/// Execute the next instruction, then clock the devices, run the events that came due and update
+/// the interrupt lines.
+pub fn step<M: Memory, O: Observer>(emulator: &mut Emulator<Bus<M>, O>) {
+    let cycles = emulator.cycles;
+    emulator.memory.start(cycles);
+    emulator.execute_next();
+    emulator.memory.tick(emulator.cycles - cycles);
+    emulator.memory.run_events(emulator.cycles);
+    update_lines(emulator);
+}
+
+fn update_lines<M: Memory, O: Observer>(emulator: &mut Emulator<Bus<M>, O>) {
+    let irq = emulator.memory.asserts(Line::Irq);
+    let nmi = emulator.memory.asserts(Line::Nmi);
+    let was_nmi = emulator.memory.nmi;
+    signal(emulator, irq, nmi, was_nmi);
+    emulator.memory.nmi = nmi;
+}
+
+/// Like `Emulator::run`, but with `step`.
+pub fn run<M: Memory, O: Observer>(
+    emulator: &mut Emulator<Bus<M>, O>,
+    max_instructions: u64,
+) -> StopReason {
+    for _ in 0..max_instructions {
+        step(emulator);
+        if emulator.is_trapped() {
+            return StopReason::Trap(emulator.registers.program_counter);
+        }
+    }
+
+    StopReason::InstructionLimit
+}
+
+/// Execute instructions until `cycle`, only clocking the devices and updating the interrupt
+/// lines when an event is due, a device does something or the program accessed a device. A trap
+/// stops it early when nothing is due before `cycle` that could interrupt the processor.
+pub fn run_until<M: Memory, O: Observer>(
+    emulator: &mut Emulator<Bus<M>, O>,
+    cycle: u64,
+) -> StopReason {
+    emulator.memory.start(emulator.cycles);
+
+    while emulator.cycles < cycle {
+        let until = emulator
+            .memory
+            .next_due()
+            .map_or(cycle, |due| due.min(cycle));
+        emulator.memory.accessed.set(false);
+        loop {
+            emulator.memory.cycle.set(emulator.cycles);
+            emulator.execute_next();
+            // A trap only ends it early when it would end the run.
+            if emulator.cycles >= until
+                || emulator.memory.accessed.get()
+                || (emulator.is_trapped() && until == cycle)
+            {
+                break;
+            }
+        }
+
+        let bus = &mut emulator.memory;
+        bus.tick_devices(emulator.cycles - bus.clocked.get());
+        bus.start(emulator.cycles);
+        bus.run_events(emulator.cycles);
+        update_lines(emulator);
+
+        // Unless something may still interrupt it.
+        let due = emulator.memory.next_due().filter(|&due| due < cycle);
+        if emulator.is_trapped() && due.is_none() {
+            return StopReason::Trap(emulator.registers.program_counter);
+        }
+    }
+
+    StopReason::CycleLimit
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::devices::bus::{run_until, step, Bus, Line};
+    use crate::devices::Device;
+    use crate::emulator::instructions::opcodes::*;
+    use crate::emulator::run::StopReason;
+    use crate::emulator::Emulator;
+    use crate::memory::default::DefaultMemory;
+    use crate::memory::Memory;
+
+    /// A register that asserts the interrupt while it is non-zero, and counts cycles.
+    #[derive(Default)]
+    struct Latch {
+        value: u8,
+        reads: usize,
+        cycles: u64,
+    }
+
+    impl Device for Latch {
+        fn read(&mut self, _offset: u16) -> u8 {
+            self.reads += 1;
+            self.value
+        }
+
+        fn write(&mut self, _offset: u16, value: u8) {
+            self.value = value;
+        }
+
+        fn tick(&mut self, cycles: u64) {
+            self.cycles += cycles;
+        }
+
+        fn interrupt(&self) -> bool {
+            self.value != 0
+        }
+
+        fn next_event(&self) -> Option<u64> {
+            None
+        }
+    }
+
+    /// Interrupts once it counted down the hundreds of cycles written to it, until it is read.
+    #[derive(Default)]
+    struct Countdown(Option<u64>);
+
+    impl Device for Countdown {
+        fn read(&mut self, _offset: u16) -> u8 {
+            let remaining = self.0.take().unwrap_or(0);
+            (remaining / 100) as u8
+        }
+
+        fn write(&mut self, _offset: u16, value: u8) {
+            self.0 = Some(value as u64 * 100);
+        }
+
+        fn tick(&mut self, cycles: u64) {
+            self.0 = self.0.map(|remaining| remaining.saturating_sub(cycles));
+        }
+
+        fn interrupt(&self) -> bool {
+            self.0 == Some(0)
+        }
+
+        fn next_event(&self) -> Option<u64> {
+            self.0.filter(|&remaining| remaining > 0)
+        }
+    }
+
+    fn emulator(program: Vec<Vec<u8>>) -> Emulator<Bus<DefaultMemory>> {
+        let mut memory = DefaultMemory::empty();
+        memory.load(program.concat(), 0x600);
+        memory.set_program_counter(0x600);
+        memory.load(vec![0x00, 0x07, 0x00, 0x06, 0x00, 0x08], 0xfffa);
+        memory.load(NOP::implied(), 0x800);
+
+        Emulator::new(Bus::new(memory))
+    }
+
+    #[test]
+    fn test_mapping() {
+        let latch = Rc::new(RefCell::new(Latch::default()));
+        let mut bus = Bus::new(DefaultMemory::empty());
+        bus.attach("latch", 0x8000, 4, latch.clone(), Line::Irq);
+
+        bus.write(0x8003, 0x42);
+        bus.write(0x8004, 0x11);
+
+        assert_eq!(0x42, bus.read(0x8000));
+        assert_eq!(0x11, bus.read(0x8004));
+        assert_eq!(0x00, bus.memory.read(0x8003));
+        assert_eq!(1, latch.borrow().reads);
+
+        // Peeking leaves the device alone.
+        assert_eq!(0x00, bus.peek(0x8000));
+        assert_eq!(1, latch.borrow().reads);
+    }
+
+    #[test]
+    fn test_irq() {
+        let latch = Rc::new(RefCell::new(Latch::default()));
+        let mut e = emulator(vec![
+            CLI::implied(),
+            LDA::immediate(1),
+            STA::absolute(0x8000),
+        ]);
+        e.memory
+            .attach("latch", 0x8000, 1, latch.clone(), Line::Irq);
+
+        for _ in 0..3 {
+            step(&mut e);
+        }
+        assert!(e.irq);
+        assert_eq!(8, latch.borrow().cycles);
+
+        step(&mut e);
+        assert_eq!(0x801, e.registers.program_counter);
+    }
+
+    #[test]
+    fn test_nmi_edge() {
+        let latch = Rc::new(RefCell::new(Latch::default()));
+        let mut e = emulator(vec![LDA::immediate(1), STA::absolute(0x8000)]);
+        e.memory
+            .memory
+            .load(vec![NOP::implied(); 4].concat(), 0x700);
+        e.memory
+            .attach("latch", 0x8000, 1, latch.clone(), Line::Nmi);
+
+        step(&mut e);
+        step(&mut e);
+        assert!(e.nmi);
+
+        // Taken once, although the line stays asserted.
+        step(&mut e);
+        step(&mut e);
+        assert!(!e.nmi);
+        assert_eq!(0x702, e.registers.program_counter);
+    }
+
+    #[test]
+    fn test_holding() {
+        let latch = Rc::new(RefCell::new(Latch {
+            value: 1,
+            ..Latch::default()
+        }));
+        let mut e = emulator(vec![NOP::implied()]);
+        e.memory
+            .attach("latch", 0x8000, 1, latch.clone(), Line::Irq);
+        e.memory
+            .attach("mirror", 0x9000, 1, latch.clone(), Line::Nmi);
+        let button = e.memory.interrupts.source("button", Line::Irq);
+        button.assert();
+
+        assert_eq!(vec!["latch", "button"], e.memory.holding(Line::Irq));
+        assert_eq!(vec!["mirror"], e.memory.holding(Line::Nmi));
+
+        latch.borrow_mut().value = 0;
+        step(&mut e);
+        assert!(e.irq);
+        assert_eq!(vec!["button"], e.memory.holding(Line::Irq));
+
+        button.release();
+        step(&mut e);
+        assert!(!e.irq);
+    }
+
+    #[test]
+    fn test_run_until() {
+        let latch = Rc::new(RefCell::new(Latch::default()));
+        let mut e = emulator(vec![CLI::implied(), JMP::absolute(0x601)]);
+        e.memory
+            .attach("latch", 0x8000, 1, latch.clone(), Line::Irq);
+        e.memory.memory.load(JMP::absolute(0x800), 0x800);
+
+        // An event every 100 cycles, and one that interrupts at 1000.
+        let count = Rc::new(RefCell::new(0));
+        let c = count.clone();
+        let every = e
+            .memory
+            .scheduler
+            .every(100, 100, move |_, _| *c.borrow_mut() += 1);
+        let timer = Rc::new(e.memory.interrupts.source("timer", Line::Irq));
+        let t = timer.clone();
+        e.memory.scheduler.at(1000, move |_, _| t.assert());
+
+        // The trap only stops it once no event is due before the end.
+        assert_eq!(StopReason::Trap(0x601), run_until(&mut e, 950));
+        assert!(e.cycles >= 900 && e.cycles < 950);
+        assert_eq!(9, *count.borrow());
+        assert_eq!(e.cycles, latch.borrow().cycles);
+
+        e.memory.scheduler.cancel(every);
+        assert_eq!(StopReason::Trap(0x800), run_until(&mut e, 2000));
+        assert!(e.cycles > 1000 && e.cycles < 1020);
+        assert_eq!(vec!["timer"], e.memory.holding(Line::Irq));
+
+        assert_eq!(StopReason::CycleLimit, run_until(&mut e, 1000));
+    }
+
+    #[test]
+    fn test_run_until_devices() {
+        let program = || {
+            let mut e = emulator(vec![
+                CLI::implied(),
+                LDA::immediate(3),
+                STA::absolute(0x9000),
+                JMP::absolute(0x606),
+            ]);
+            let handler = [
+                LDA::absolute(0x9000),
+                STA::absolute(0x200),
+                JMP::absolute(0x806),
+            ];
+            e.memory.memory.load(handler.concat(), 0x800);
+            let countdown = Rc::new(RefCell::new(Countdown::default()));
+            e.memory
+                .attach("countdown", 0x9000, 1, countdown, Line::Irq);
+            e
+        };
+
+        // The device runs out at the same cycle as with `step`.
+        let mut stepped = program();
+        while stepped.registers.program_counter != 0x806 || !stepped.is_trapped() {
+            step(&mut stepped);
+        }
+        let mut e = program();
+        assert_eq!(StopReason::Trap(0x806), run_until(&mut e, 10_000));
+        assert_eq!(stepped.cycles, e.cycles);
+        assert!(!e.irq);
+
+        // Events can reach the bus.
+        e.memory
+            .scheduler
+            .at(5000, |bus, due| bus.memory.write(0x201, (due >> 8) as u8));
+        assert_eq!(StopReason::Trap(0x806), run_until(&mut e, 10_000));
+        assert!(e.cycles >= 5000);
+        assert_eq!(0x13, e.memory.read(0x201));
+    }
+}
@@ -0,0 +1,747 @@
+//! The MOS 6526 Complex Interface Adapter: two 8-bit ports, two 16-bit timers, a time of day
+//! clock and a shift register.
+//!
+//! Timer A counts clock cycles or rising edges of CNT. Timer B counts clock cycles, rising edges
+//! of CNT or the times timer A runs out, which chains the two into a 32-bit timer. A timer
+//! loaded with N runs out N + 1 counts later, and then every N + 1 counts while it is started.
+//!
+//! The time of day clock counts tenths of seconds, seconds, minutes and hours in BCD, with the
+//! hours from 1 to 12 and bit 7 set for PM. On the chip it counts the 50 or 60 Hz of the mains,
+//! here it counts the clock cycles of the processor instead, so it keeps time as long as the
+//! clock it is given is right. Reading the hours stops the registers from changing until the
+//! tenths are read, and writing the hours stops the clock until the tenths are written, so that
+//! the time can be read and set one register at a time.
+//!
+//! Reading the interrupt control register clears all interrupt flags, which releases the
+//! interrupt output. Whether that is IRQ or NMI depends on how the chip is attached to the bus:
+//! the Commodore 64 has one of each.
+
+use crate::devices::Device;
+
+// Registers.
+const PRA: u16 = 0x0;
+const PRB: u16 = 0x1;
+const DDRA: u16 = 0x2;
+const DDRB: u16 = 0x3;
+const TA_LO: u16 = 0x4;
+const TA_HI: u16 = 0x5;
+const TB_LO: u16 = 0x6;
+const TB_HI: u16 = 0x7;
+const TOD_TENTHS: u16 = 0x8;
+const TOD_SECONDS: u16 = 0x9;
+const TOD_MINUTES: u16 = 0xa;
+const TOD_HOURS: u16 = 0xb;
+const SDR: u16 = 0xc;
+const ICR: u16 = 0xd;
+const CRA: u16 = 0xe;
+const CRB: u16 = 0xf;
+
+// Interrupt flags.
+pub const TIMER_A: u8 = 0x01;
+pub const TIMER_B: u8 = 0x02;
+pub const ALARM: u8 = 0x04;
+pub const SERIAL: u8 = 0x08;
+pub const FLAG: u8 = 0x10;
+
+// Control registers, the same for both timers except where noted.
+const START: u8 = 0x01;
+/// Output the timer on PB6 for timer A, PB7 for timer B.
+const PB_ON: u8 = 0x02;
+/// Toggle the output when the timer runs out, rather than pulse it.
+const TOGGLE: u8 = 0x04;
+const ONE_SHOT: u8 = 0x08;
+/// Load the latch into the counter. This bit is a strobe and always reads as 0.
+const LOAD: u8 = 0x10;
+/// Bits of CRA: count CNT rather than cycles, and shift out rather than in.
+const CRA_COUNT_CNT: u8 = 0x20;
+const CRA_SERIAL_OUT: u8 = 0x40;
+/// Bits of CRB: what timer B counts, and whether the time of day registers write the alarm.
+const CRB_COUNT: u8 = 0x60;
+const CRB_COUNT_CNT: u8 = 0x20;
+const CRB_COUNT_TIMER_A: u8 = 0x40;
+const CRB_COUNT_TIMER_A_CNT: u8 = 0x60;
+const CRB_ALARM: u8 = 0x80;
+
+/// The clock of the processor unless told otherwise.
+const DEFAULT_CLOCK: u32 = 1_000_000;
+
+/// One of the two timers.
+#[derive(Default)]
+struct Timer {
+    counter: u16,
+    latch: u16,
+    control: u8,
+    /// The level of the output on port B in toggle mode.
+    toggle: bool,
+    /// Whether the output on port B is high for the pulse of a timer that just ran out.
+    pulse: bool,
+}
+
+impl Timer {
+    fn started(&self) -> bool {
+        self.control & START != 0
+    }
+
+    fn write_control(&mut self, value: u8) {
+        if value & START != 0 && !self.started() {
+            self.toggle = true;
+        }
+        if value & LOAD != 0 {
+            self.counter = self.latch;
+        }
+        self.control = value & !LOAD;
+    }
+
+    fn write_high(&mut self, value: u8) {
+        self.latch = (self.latch & 0x00ff) | ((value as u16) << 8);
+
+        // A stopped timer is loaded right away, and a one-shot timer starts as well.
+        if !self.started() {
+            self.counter = self.latch;
+            if self.control & ONE_SHOT != 0 {
+                self.write_control(self.control | START);
+            }
+        }
+    }
+
+    /// Count down once, returning whether the timer ran out.
+    fn count(&mut self) -> bool {
+        if self.counter > 0 {
+            self.counter -= 1;
+            return false;
+        }
+
+        self.counter = self.latch;
+        self.toggle = !self.toggle;
+        self.pulse = true;
+        if self.control & ONE_SHOT != 0 {
+            self.control &= !START;
+        }
+        true
+    }
+
+    /// The level of the output on port B.
+    fn output(&self) -> bool {
+        if self.control & TOGGLE != 0 {
+            self.toggle
+        } else {
+            self.pulse
+        }
+    }
+}
+
+/// The time of day, in the order of the registers: tenths, seconds, minutes and hours.
+type Time = [u8; 4];
+
+/// Add 1 to a BCD number.
+fn bcd_increment(value: u8) -> u8 {
+    if value & 0x0f == 0x09 {
+        (value & 0xf0) + 0x10
+    } else {
+        value + 1
+    }
+}
+
+pub struct Cia {
+    a_output: u8,
+    a_direction: u8,
+    /// The levels driven onto the port A pins from outside.
+    a_pins: u8,
+    b_output: u8,
+    b_direction: u8,
+    b_pins: u8,
+    a: Timer,
+    b: Timer,
+    tod: Time,
+    alarm: Time,
+    /// The time as it was when the hours were read, until the tenths are read.
+    tod_latch: Option<Time>,
+    /// Whether the clock is stopped, from writing the hours until writing the tenths.
+    tod_stopped: bool,
+    /// Cycles per tenth of a second, and the cycles until the next.
+    tod_cycles: u64,
+    tod_divider: u64,
+    sdr: u8,
+    shift: u8,
+    /// Bits left to shift out, or bits shifted in.
+    shift_bits: u8,
+    /// Whether a byte was written to shift out after the current one.
+    shift_pending: bool,
+    cnt: bool,
+    /// The level driven onto CNT while shifting out.
+    cnt_output: bool,
+    sp: bool,
+    flag_pin: bool,
+    icr: u8,
+    mask: u8,
+}
+
+impl Cia {
+    /// A CIA next to a processor running at 1 MHz.
+    pub fn new() -> Cia {
+        Cia::with_clock(DEFAULT_CLOCK)
+    }
+
+    /// A CIA next to a processor running at `clock` cycles per second.
+    pub fn with_clock(clock: u32) -> Cia {
+        let tod_cycles = (clock as u64 / 10).max(1);
+
+        Cia {
+            a_output: 0,
+            a_direction: 0,
+            a_pins: 0xff,
+            b_output: 0,
+            b_direction: 0,
+            b_pins: 0xff,
+            a: Timer::default(),
+            b: Timer::default(),
+            tod: [0, 0, 0, 0x01],
+            alarm: [0; 4],
+            tod_latch: None,
+            tod_stopped: false,
+            tod_cycles,
+            tod_divider: tod_cycles,
+            sdr: 0,
+            shift: 0,
+            shift_bits: 0,
+            shift_pending: false,
+            cnt: true,
+            cnt_output: true,
+            sp: true,
+            flag_pin: true,
+            icr: 0,
+            mask: 0,
+        }
+    }
+
+    /// The levels of the port A pins: outputs as written by the processor, inputs as driven
+    /// from outside.
+    pub fn port_a(&self) -> u8 {
+        (self.a_output & self.a_direction) | (self.a_pins & !self.a_direction)
+    }
+
+    /// The levels of the port B pins, including PB6 and PB7 when the timers drive them.
+    pub fn port_b(&self) -> u8 {
+        let mut levels = (self.b_output & self.b_direction) | (self.b_pins & !self.b_direction);
+        if self.a.control & PB_ON != 0 {
+            levels = (levels & !0x40) | ((self.a.output() as u8) << 6);
+        }
+        if self.b.control & PB_ON != 0 {
+            levels = (levels & !0x80) | ((self.b.output() as u8) << 7);
+        }
+        levels
+    }
+
+    /// Drive the port A pins. Only the pins set as inputs are read by the processor.
+    pub fn set_port_a(&mut self, pins: u8) {
+        self.a_pins = pins;
+    }
+
+    pub fn set_port_b(&mut self, pins: u8) {
+        self.b_pins = pins;
+    }
+
+    /// Drive CNT. Rising edges are counted by the timers that count CNT, and shift in SP when
+    /// the shift register is an input.
+    pub fn set_cnt(&mut self, level: bool) {
+        let rising = !self.cnt && level;
+        self.cnt = level;
+        if !rising {
+            return;
+        }
+
+        if self.a.started() && self.a.control & CRA_COUNT_CNT != 0 {
+            self.count_a();
+        }
+        if self.b.started() && self.b.control & CRB_COUNT == CRB_COUNT_CNT {
+            self.count_b();
+        }
+
+        if self.a.control & CRA_SERIAL_OUT == 0 {
+            self.shift = (self.shift << 1) | self.sp as u8;
+            self.shift_bits += 1;
+            if self.shift_bits == 8 {
+                self.sdr = self.shift;
+                self.shift_bits = 0;
+                self.flag(SERIAL);
+            }
+        }
+    }
+
+    /// Drive SP, the serial data input.
+    pub fn set_sp(&mut self, level: bool) {
+        self.sp = level;
+    }
+
+    /// Drive the FLAG input, whose falling edges set its interrupt flag.
+    pub fn set_flag(&mut self, level: bool) {
+        if self.flag_pin && !level {
+            self.flag(FLAG);
+        }
+        self.flag_pin = level;
+    }
+
+    /// The level of CNT, driven by the chip while shifting out.
+    pub fn cnt(&self) -> bool {
+        if self.a.control & CRA_SERIAL_OUT != 0 {
+            self.cnt_output
+        } else {
+            self.cnt
+        }
+    }
+
+    /// The level of SP, driven by the chip while shifting out.
+    pub fn sp(&self) -> bool {
+        self.sp
+    }
+
+    /// The time of day: tenths, seconds, minutes and hours.
+    pub fn time(&self) -> [u8; 4] {
+        self.tod
+    }
+
+    fn flag(&mut self, flags: u8) {
+        self.icr |= flags;
+    }
+
+    fn count_a(&mut self) {
+        if !self.a.count() {
+            return;
+        }
+
+        self.flag(TIMER_A);
+        if self.a.control & CRA_SERIAL_OUT != 0 {
+            self.shift_out();
+        }
+
+        if self.b.started() {
+            match self.b.control & CRB_COUNT {
+                CRB_COUNT_TIMER_A => self.count_b(),
+                CRB_COUNT_TIMER_A_CNT if self.cnt => self.count_b(),
+                _ => {}
+            }
+        }
+    }
+
+    fn count_b(&mut self) {
+        if self.b.count() {
+            self.flag(TIMER_B);
+        }
+    }
+
+    /// Shift out half a bit, each time timer A runs out: the data goes out on SP as CNT falls,
+    /// and is taken in by the other end as CNT rises.
+    fn shift_out(&mut self) {
+        if self.shift_bits == 0 {
+            return;
+        }
+
+        if self.cnt_output {
+            self.sp = self.shift & 0x80 != 0;
+            self.cnt_output = false;
+            return;
+        }
+
+        self.cnt_output = true;
+        self.shift <<= 1;
+        self.shift_bits -= 1;
+        if self.shift_bits == 0 {
+            self.flag(SERIAL);
+            if self.shift_pending {
+                self.shift_pending = false;
+                self.shift = self.sdr;
+                self.shift_bits = 8;
+            }
+        }
+    }
+
+    fn tenth(&mut self) {
+        let [tenths, seconds, minutes, hours] = &mut self.tod;
+
+        *tenths = (*tenths + 1) % 10;
+        if *tenths == 0 {
+            *seconds = bcd_increment(*seconds);
+            if *seconds == 0x60 {
+                *seconds = 0;
+                *minutes = bcd_increment(*minutes);
+                if *minutes == 0x60 {
+                    *minutes = 0;
+                    *hours = match *hours & 0x1f {
+                        0x11 => (*hours ^ 0x80) & 0x80 | 0x12,
+                        0x12 => *hours & 0x80 | 0x01,
+                        hour => *hours & 0x80 | bcd_increment(hour),
+                    };
+                }
+            }
+        }
+
+        if self.tod == self.alarm {
+            self.flag(ALARM);
+        }
+    }
+
+    fn read_tod(&mut self, register: usize) -> u8 {
+        if register == 3 && self.tod_latch.is_none() {
+            self.tod_latch = Some(self.tod);
+        }
+        let time = self.tod_latch.unwrap_or(self.tod);
+        if register == 0 {
+            self.tod_latch = None;
+        }
+        time[register]
+    }
+
+    fn write_tod(&mut self, register: usize, value: u8) {
+        let value = value & [0x0f, 0x7f, 0x7f, 0x9f][register];
+        if self.b.control & CRB_ALARM != 0 {
+            self.alarm[register] = value;
+            return;
+        }
+
+        self.tod[register] = value;
+        match register {
+            0 => {
+                self.tod_stopped = false;
+                self.tod_divider = self.tod_cycles;
+            }
+            3 => self.tod_stopped = true,
+            _ => {}
+        }
+    }
+
+    fn cycle(&mut self) {
+        self.a.pulse = false;
+        self.b.pulse = false;
+
+        if self.a.started() && self.a.control & CRA_COUNT_CNT == 0 {
+            self.count_a();
+        }
+        if self.b.started() && self.b.control & CRB_COUNT == 0 {
+            self.count_b();
+        }
+
+        if !self.tod_stopped {
+            self.tod_divider -= 1;
+            if self.tod_divider == 0 {
+                self.tod_divider = self.tod_cycles;
+                self.tenth();
+            }
+        }
+    }
+}
+
+impl Default for Cia {
+    fn default() -> Cia {
+        Cia::new()
+    }
+}
+
+impl Device for Cia {
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset & 0xf {
+            PRA => self.port_a(),
+            PRB => self.port_b(),
+            DDRA => self.a_direction,
+            DDRB => self.b_direction,
+            TA_LO => self.a.counter as u8,
+            TA_HI => (self.a.counter >> 8) as u8,
+            TB_LO => self.b.counter as u8,
+            TB_HI => (self.b.counter >> 8) as u8,
+            TOD_TENTHS => self.read_tod(0),
+            TOD_SECONDS => self.read_tod(1),
+            TOD_MINUTES => self.read_tod(2),
+            TOD_HOURS => self.read_tod(3),
+            SDR => self.sdr,
+            ICR => {
+                // Reading the flags clears them all, which releases the interrupt.
+                let icr = self.icr | ((self.interrupt() as u8) << 7);
+                self.icr = 0;
+                icr
+            }
+            CRA => self.a.control,
+            CRB => self.b.control,
+            _ => unreachable!(),
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        match offset & 0xf {
+            PRA => self.a_output = value,
+            PRB => self.b_output = value,
+            DDRA => self.a_direction = value,
+            DDRB => self.b_direction = value,
+            TA_LO => self.a.latch = (self.a.latch & 0xff00) | value as u16,
+            TA_HI => self.a.write_high(value),
+            TB_LO => self.b.latch = (self.b.latch & 0xff00) | value as u16,
+            TB_HI => self.b.write_high(value),
+            TOD_TENTHS => self.write_tod(0, value),
+            TOD_SECONDS => self.write_tod(1, value),
+            TOD_MINUTES => self.write_tod(2, value),
+            TOD_HOURS => self.write_tod(3, value),
+            SDR => {
+                self.sdr = value;
+                if self.a.control & CRA_SERIAL_OUT != 0 {
+                    if self.shift_bits == 0 {
+                        self.shift = value;
+                        self.shift_bits = 8;
+                    } else {
+                        self.shift_pending = true;
+                    }
+                }
+            }
+            ICR => {
+                if value & 0x80 != 0 {
+                    self.mask |= value & 0x1f;
+                } else {
+                    self.mask &= !value;
+                }
+            }
+            CRA => {
+                // Switching the direction of the shift register starts over.
+                if (value ^ self.a.control) & CRA_SERIAL_OUT != 0 {
+                    self.shift_bits = 0;
+                    self.shift_pending = false;
+                    self.cnt_output = true;
+                }
+                self.a.write_control(value);
+            }
+            CRB => self.b.write_control(value),
+            _ => unreachable!(),
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        for _ in 0..cycles {
+            self.cycle();
+        }
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        if self.a.pulse || self.b.pulse {
+            return Some(1);
+        }
+
+        // Timer B counting the underflows of timer A runs out with it.
+        let a = Some(self.a.counter as u64 + 1)
+            .filter(|_| self.a.started() && self.a.control & CRA_COUNT_CNT == 0);
+        let b = Some(self.b.counter as u64 + 1)
+            .filter(|_| self.b.started() && self.b.control & CRB_COUNT == 0);
+        let tod = Some(self.tod_divider).filter(|_| !self.tod_stopped);
+        a.into_iter().chain(b).chain(tod).min()
+    }
+
+    fn interrupt(&self) -> bool {
+        self.icr & self.mask != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::devices::bus::{step, Bus, Line};
+    use crate::devices::cia::*;
+    use crate::devices::Device;
+    use crate::emulator::instructions::opcodes::{
+        Absolute, Immediate, Implied, JMP, LDA, NOP, STA,
+    };
+    use crate::emulator::Emulator;
+    use crate::memory::default::DefaultMemory;
+
+    fn start_timer(cia: &mut Cia, low: u16, value: u16, control: u8) {
+        cia.write(low, value as u8);
+        cia.write(low + 1, (value >> 8) as u8);
+        cia.write(if low == TA_LO { CRA } else { CRB }, control | START);
+    }
+
+    #[test]
+    fn test_ports() {
+        let mut cia = Cia::new();
+        cia.write(DDRA, 0x0f);
+        cia.write(PRA, 0xa5);
+        cia.set_port_a(0x3c);
+        assert_eq!(0x35, cia.read(PRA));
+
+        // Timer A pulses PB6 when it runs out.
+        cia.write(DDRB, 0xff);
+        cia.write(PRB, 0x00);
+        start_timer(&mut cia, TA_LO, 1, PB_ON);
+        cia.tick(1);
+        assert_eq!(0x00, cia.read(PRB));
+        cia.tick(1);
+        assert_eq!(0x40, cia.read(PRB));
+        cia.tick(1);
+        assert_eq!(0x00, cia.read(PRB));
+    }
+
+    #[test]
+    fn test_timer_continuous() {
+        let mut cia = Cia::new();
+        start_timer(&mut cia, TA_LO, 3, 0);
+
+        let mut times = Vec::new();
+        for cycle in 1..=12 {
+            cia.tick(1);
+            if cia.read(ICR) & TIMER_A != 0 {
+                times.push(cycle);
+            }
+        }
+
+        assert_eq!(vec![4, 8, 12], times);
+    }
+
+    #[test]
+    fn test_timer_one_shot() {
+        let mut cia = Cia::new();
+        cia.write(ICR, 0x80 | TIMER_B);
+        cia.write(CRB, ONE_SHOT);
+        cia.write(TB_LO, 2);
+        // Writing the high byte starts a one-shot timer.
+        cia.write(TB_HI, 0);
+
+        cia.tick(3);
+        assert!(cia.interrupt());
+        assert_eq!(0, cia.read(CRB) & START);
+        assert_eq!(2, cia.read(TB_LO));
+    }
+
+    #[test]
+    fn test_chained_timers() {
+        let mut cia = Cia::new();
+        start_timer(&mut cia, TB_LO, 1, CRB_COUNT_TIMER_A);
+        start_timer(&mut cia, TA_LO, 9, 0);
+
+        cia.tick(19);
+        assert_eq!(TIMER_A, cia.read(ICR) & (TIMER_A | TIMER_B));
+        cia.tick(1);
+        assert_eq!(TIMER_A | TIMER_B, cia.read(ICR) & (TIMER_A | TIMER_B));
+    }
+
+    #[test]
+    fn test_interrupt_control() {
+        let mut cia = Cia::new();
+        cia.write(ICR, 0x80 | TIMER_A | FLAG);
+        cia.write(ICR, TIMER_A);
+
+        cia.set_flag(false);
+        assert!(cia.interrupt());
+        start_timer(&mut cia, TA_LO, 0, 0);
+        cia.tick(1);
+
+        // Reading clears all flags, masked or not.
+        assert_eq!(0x80 | FLAG | TIMER_A, cia.read(ICR));
+        assert!(!cia.interrupt());
+        assert_eq!(0, cia.read(ICR));
+    }
+
+    #[test]
+    fn test_time_of_day() {
+        let mut cia = Cia::with_clock(10);
+        // 11:59:59.9 AM.
+        cia.write(TOD_HOURS, 0x11);
+        cia.write(TOD_MINUTES, 0x59);
+        cia.write(TOD_SECONDS, 0x59);
+        cia.tick(5);
+        cia.write(TOD_TENTHS, 0x09);
+        assert_eq!([0x09, 0x59, 0x59, 0x11], cia.time());
+
+        cia.tick(1);
+        assert_eq!([0x00, 0x00, 0x00, 0x92], cia.time());
+
+        // Reading the hours holds the time until the tenths are read.
+        assert_eq!(0x92, cia.read(TOD_HOURS));
+        cia.tick(10);
+        assert_eq!(0x00, cia.read(TOD_SECONDS));
+        assert_eq!(0x00, cia.read(TOD_TENTHS));
+        assert_eq!(0x01, cia.read(TOD_SECONDS));
+    }
+
+    #[test]
+    fn test_alarm() {
+        let mut cia = Cia::with_clock(10);
+        cia.write(ICR, 0x80 | ALARM);
+        cia.write(CRB, CRB_ALARM);
+        cia.write(TOD_HOURS, 0x01);
+        cia.write(TOD_SECONDS, 0x01);
+        cia.write(TOD_TENTHS, 0x02);
+        cia.write(CRB, 0);
+
+        cia.tick(11);
+        assert!(!cia.interrupt());
+        cia.tick(1);
+        assert!(cia.interrupt());
+        assert_eq!([0x02, 0x01, 0x00, 0x01], cia.time());
+    }
+
+    #[test]
+    fn test_shift_out() {
+        let mut cia = Cia::new();
+        start_timer(&mut cia, TA_LO, 0, CRA_SERIAL_OUT);
+        cia.write(SDR, 0b1011_0001);
+
+        let mut bits = Vec::new();
+        for _ in 0..16 {
+            let cnt = cia.cnt();
+            cia.tick(1);
+            if !cnt && cia.cnt() {
+                bits.push(cia.sp() as u8);
+            }
+        }
+
+        assert_eq!(vec![1, 0, 1, 1, 0, 0, 0, 1], bits);
+        assert_eq!(SERIAL, cia.read(ICR) & SERIAL);
+    }
+
+    #[test]
+    fn test_shift_in() {
+        let mut cia = Cia::new();
+        for bit in [false, true, true, false, true, false, false, true].iter() {
+            cia.set_sp(*bit);
+            cia.set_cnt(false);
+            cia.set_cnt(true);
+        }
+
+        assert_eq!(0b0110_1001, cia.read(SDR));
+        assert_eq!(SERIAL, cia.read(ICR) & SERIAL);
+    }
+
+    #[test]
+    fn test_nmi() {
+        // Start timer A at $dd00 and wait for its interrupt, as on the second CIA of the C64.
+        let mut memory = DefaultMemory::empty();
+        memory.load(
+            [
+                LDA::immediate(0x81),
+                STA::absolute(0xdd0d),
+                LDA::immediate(0x10),
+                STA::absolute(0xdd04),
+                LDA::immediate(0x00),
+                STA::absolute(0xdd05),
+                LDA::immediate(0x01),
+                STA::absolute(0xdd0e),
+                JMP::absolute(0x614),
+            ]
+            .concat(),
+            0x600,
+        );
+        memory.set_program_counter(0x600);
+        memory.load(vec![0x00, 0x07], 0xfffa);
+        memory.load(NOP::implied(), 0x700);
+
+        let cia = Rc::new(RefCell::new(Cia::new()));
+        let mut bus = Bus::new(memory);
+        bus.attach("cia", 0xdd00, 0x100, cia.clone(), Line::Nmi);
+        let mut e = Emulator::new(bus);
+
+        for _ in 0..20 {
+            step(&mut e);
+            if e.registers.program_counter == 0x701 {
+                break;
+            }
+        }
+
+        assert_eq!(0x701, e.registers.program_counter);
+        assert!(cia.borrow().interrupt());
+    }
+}
@@ -0,0 +1,523 @@
+//! The Hitachi HD44780 character LCD controller, found on nearly every 16x2 text display.
+//!
+//! The controller is not on the processor bus. It is wired to the pins of a port chip such as a
+//! VIA: the register select, read/write and enable lines, and an 8-bit data bus. It reads the
+//! data bus as enable falls, and drives it while enable is high during a read. In 4-bit mode
+//! only D4 to D7 are used, and every transfer takes two pulses of enable, high nibble first.
+//!
+//! Instructions take time to execute, during which the busy flag is set. Like the real
+//! controller, it ignores anything written while it is busy, so programs have to wait for it.
+
+use crate::devices::Device;
+
+// Instructions, identified by their highest set bit.
+const CLEAR: u8 = 0x01;
+const HOME: u8 = 0x02;
+const ENTRY_MODE: u8 = 0x04;
+const DISPLAY_CONTROL: u8 = 0x08;
+const SHIFT: u8 = 0x10;
+const FUNCTION_SET: u8 = 0x20;
+const SET_CGRAM_ADDRESS: u8 = 0x40;
+const SET_DDRAM_ADDRESS: u8 = 0x80;
+
+const BUSY: u8 = 0x80;
+
+/// Characters per line of display data RAM in 2-line mode, which is all a line can hold
+/// whatever the size of the display.
+const LINE_LENGTH: usize = 40;
+const DDRAM_SIZE: usize = 80;
+const CGRAM_SIZE: usize = 64;
+
+/// Microseconds most instructions take, and clearing and returning home.
+const INSTRUCTION_TIME: u64 = 37;
+const LONG_INSTRUCTION_TIME: u64 = 1520;
+
+/// The clock of the processor unless told otherwise.
+const DEFAULT_CLOCK: u32 = 1_000_000;
+
+/// How a character of the A00 character ROM looks in a terminal. The first 16 are the custom
+/// characters in CGRAM, which are only drawn as a block.
+fn glyph(code: u8) -> char {
+    match code {
+        0x00..=0x0f => '\u{2592}',
+        b'\\' => '\u{a5}',
+        0x20..=0x7d => code as char,
+        0x7e => '\u{2192}',
+        0x7f => '\u{2190}',
+        0xff => '\u{2588}',
+        _ => '?',
+    }
+}
+
+pub struct Hd44780 {
+    columns: usize,
+    lines: usize,
+    ddram: [u8; DDRAM_SIZE],
+    cgram: [u8; CGRAM_SIZE],
+    /// The address counter, into DDRAM or CGRAM.
+    address: u8,
+    cgram_selected: bool,
+    increment: bool,
+    /// Whether writing data shifts the display.
+    shift_display: bool,
+    display_on: bool,
+    cursor_on: bool,
+    blink_on: bool,
+    eight_bit: bool,
+    two_lines: bool,
+    /// How many positions the display is shifted to the left.
+    shift: usize,
+    /// Cycles per microsecond, and cycles until the current instruction is done.
+    cycles_per_microsecond: u64,
+    busy: u64,
+    enable: bool,
+    /// The high nibble of a transfer in 4-bit mode, once written.
+    nibble: Option<u8>,
+    /// Whether the next read in 4-bit mode is of the low nibble.
+    low_nibble: bool,
+    /// The register select and read/write lines.
+    rs: bool,
+    rw: bool,
+}
+
+impl Hd44780 {
+    /// A display with `columns` characters on each of its `lines`, next to a processor running
+    /// at 1 MHz.
+    pub fn new(columns: usize, lines: usize) -> Hd44780 {
+        Hd44780::with_clock(columns, lines, DEFAULT_CLOCK)
+    }
+
+    /// A display next to a processor running at `clock` cycles per second.
+    pub fn with_clock(columns: usize, lines: usize, clock: u32) -> Hd44780 {
+        Hd44780 {
+            columns,
+            lines,
+            ddram: [b' '; DDRAM_SIZE],
+            cgram: [0; CGRAM_SIZE],
+            address: 0,
+            cgram_selected: false,
+            increment: true,
+            shift_display: false,
+            display_on: false,
+            cursor_on: false,
+            blink_on: false,
+            eight_bit: true,
+            two_lines: false,
+            shift: 0,
+            cycles_per_microsecond: (clock as u64 / 1_000_000).max(1),
+            busy: 0,
+            enable: false,
+            nibble: None,
+            low_nibble: false,
+            rs: false,
+            rw: false,
+        }
+    }
+
+    /// Drive the register select, read/write and enable lines, and the data bus.
+    pub fn set_pins(&mut self, rs: bool, rw: bool, enable: bool, data: u8) {
+        let falling = self.enable && !enable;
+        if falling {
+            if self.rw {
+                self.end_read();
+            } else {
+                self.transfer(self.rs, data);
+            }
+        }
+
+        self.rs = rs;
+        self.rw = rw;
+        self.enable = enable;
+    }
+
+    /// The levels the controller drives onto the data bus, while enable is high for a read.
+    pub fn data(&self) -> Option<u8> {
+        if !(self.enable && self.rw) {
+            return None;
+        }
+
+        let value = if self.rs {
+            self.ram()
+        } else {
+            ((self.busy > 0) as u8 * BUSY) | (self.address & 0x7f)
+        };
+
+        match (self.eight_bit, self.low_nibble) {
+            (true, _) => Some(value),
+            (false, false) => Some(value & 0xf0),
+            (false, true) => Some(value << 4),
+        }
+    }
+
+    /// What the display shows, one string per line. Custom characters are drawn as a block.
+    pub fn text(&self) -> Vec<String> {
+        (0..self.lines)
+            .map(|line| {
+                if !self.display_on || (!self.two_lines && line > 0) {
+                    return " ".repeat(self.columns);
+                }
+
+                // Lines 3 and 4 of a 4-line display continue lines 1 and 2.
+                let (start, length) = if self.two_lines {
+                    ((line % 2) * LINE_LENGTH, LINE_LENGTH)
+                } else {
+                    (0, DDRAM_SIZE)
+                };
+                let offset = (line / 2) * self.columns;
+
+                (0..self.columns)
+                    .map(|column| {
+                        let position = (offset + column + self.shift) % length;
+                        glyph(self.ddram[start + position])
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The bitmaps of the 8 custom characters, 8 rows of 5 pixels each.
+    pub fn cgram(&self) -> &[u8; CGRAM_SIZE] {
+        &self.cgram
+    }
+
+    /// The line and column of the cursor, when the cursor or blinking is on.
+    pub fn cursor(&self) -> Option<(usize, usize)> {
+        if !self.display_on || !(self.cursor_on || self.blink_on) || self.cgram_selected {
+            return None;
+        }
+
+        let index = self.ddram_index(self.address);
+        let length = if self.two_lines {
+            LINE_LENGTH
+        } else {
+            DDRAM_SIZE
+        };
+        let line = index / length;
+        let position = (index % length + length - self.shift % length) % length;
+        let (line, column) = (
+            line + 2 * (position / self.columns),
+            position % self.columns,
+        );
+
+        if line < self.lines {
+            Some((line, column))
+        } else {
+            None
+        }
+    }
+
+    fn ddram_index(&self, address: u8) -> usize {
+        if self.two_lines {
+            let line = (address >> 6) as usize & 1;
+            line * LINE_LENGTH + (address & 0x3f) as usize % LINE_LENGTH
+        } else {
+            address as usize % DDRAM_SIZE
+        }
+    }
+
+    fn ram(&self) -> u8 {
+        if self.cgram_selected {
+            self.cgram[(self.address & 0x3f) as usize]
+        } else {
+            self.ddram[self.ddram_index(self.address)]
+        }
+    }
+
+    /// Move the address counter one position, the way writing or reading data does.
+    fn advance(&mut self, forward: bool) {
+        if self.cgram_selected {
+            let step = if forward { 1 } else { 0x3f };
+            self.address = (self.address + step) & 0x3f;
+            return;
+        }
+
+        // Step through the positions that exist, from the end of a line to the start of the next
+        // and from the end of DDRAM to its start. An address set past them is taken to be the
+        // position that it shows.
+        let step = if forward { 1 } else { DDRAM_SIZE - 1 };
+        let index = (self.ddram_index(self.address) + step) % DDRAM_SIZE;
+        self.address = if self.two_lines {
+            (((index / LINE_LENGTH) << 6) | (index % LINE_LENGTH)) as u8
+        } else {
+            index as u8
+        };
+    }
+
+    fn shift_by(&mut self, left: bool) {
+        let length = if self.two_lines {
+            LINE_LENGTH
+        } else {
+            DDRAM_SIZE
+        };
+        self.shift = if left {
+            (self.shift + 1) % length
+        } else {
+            (self.shift + length - 1) % length
+        };
+    }
+
+    fn end_read(&mut self) {
+        if !self.eight_bit {
+            self.low_nibble = !self.low_nibble;
+            if self.low_nibble {
+                return;
+            }
+        }
+
+        if self.rs {
+            self.advance(self.increment);
+        }
+    }
+
+    fn transfer(&mut self, rs: bool, data: u8) {
+        let value = if self.eight_bit {
+            data
+        } else {
+            match self.nibble.take() {
+                Some(high) => high | (data >> 4),
+                None => {
+                    self.nibble = Some(data & 0xf0);
+                    return;
+                }
+            }
+        };
+
+        if self.busy > 0 {
+            return;
+        }
+        self.busy = INSTRUCTION_TIME * self.cycles_per_microsecond;
+
+        if rs {
+            self.write_data(value);
+        } else {
+            self.instruction(value);
+        }
+    }
+
+    fn write_data(&mut self, value: u8) {
+        if self.cgram_selected {
+            self.cgram[(self.address & 0x3f) as usize] = value & 0x1f;
+        } else {
+            let index = self.ddram_index(self.address);
+            self.ddram[index] = value;
+            if self.shift_display {
+                self.shift_by(self.increment);
+            }
+        }
+        self.advance(self.increment);
+    }
+
+    fn instruction(&mut self, value: u8) {
+        if value & SET_DDRAM_ADDRESS != 0 {
+            self.address = value & 0x7f;
+            self.cgram_selected = false;
+        } else if value & SET_CGRAM_ADDRESS != 0 {
+            self.address = value & 0x3f;
+            self.cgram_selected = true;
+        } else if value & FUNCTION_SET != 0 {
+            self.eight_bit = value & 0x10 != 0;
+            self.two_lines = value & 0x08 != 0;
+            self.nibble = None;
+            self.low_nibble = false;
+        } else if value & SHIFT != 0 {
+            let right = value & 0x04 != 0;
+            if value & 0x08 != 0 {
+                self.shift_by(!right);
+            } else {
+                self.advance(right);
+            }
+        } else if value & DISPLAY_CONTROL != 0 {
+            self.display_on = value & 0x04 != 0;
+            self.cursor_on = value & 0x02 != 0;
+            self.blink_on = value & 0x01 != 0;
+        } else if value & ENTRY_MODE != 0 {
+            self.increment = value & 0x02 != 0;
+            self.shift_display = value & 0x01 != 0;
+        } else if value & HOME != 0 {
+            self.address = 0;
+            self.cgram_selected = false;
+            self.shift = 0;
+            self.busy = LONG_INSTRUCTION_TIME * self.cycles_per_microsecond;
+        } else if value & CLEAR != 0 {
+            self.ddram = [b' '; DDRAM_SIZE];
+            self.address = 0;
+            self.cgram_selected = false;
+            self.shift = 0;
+            self.increment = true;
+            self.busy = LONG_INSTRUCTION_TIME * self.cycles_per_microsecond;
+        }
+    }
+}
+
+impl Device for Hd44780 {
+    /// Read like a controller mapped onto the bus, with the register select line on address
+    /// bit 0 and the read/write line taken from the access.
+    fn read(&mut self, offset: u16) -> u8 {
+        let rs = offset & 1 != 0;
+        self.set_pins(rs, true, true, 0xff);
+        let value = self.data().unwrap_or(0xff);
+        self.set_pins(rs, true, false, 0xff);
+        value
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        let rs = offset & 1 != 0;
+        self.set_pins(rs, false, true, value);
+        self.set_pins(rs, false, false, value);
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.busy = self.busy.saturating_sub(cycles);
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::hd44780::Hd44780;
+    use crate::devices::Device;
+
+    const INSTRUCTION: u16 = 0;
+    const DATA: u16 = 1;
+
+    /// Write to the controller, waiting for each instruction to finish.
+    fn write(lcd: &mut Hd44780, rs: u16, values: &[u8]) {
+        for value in values {
+            lcd.write(rs, *value);
+            lcd.tick(2000);
+        }
+    }
+
+    fn lcd() -> Hd44780 {
+        let mut lcd = Hd44780::new(16, 2);
+        // 8-bit, 2 lines, display on, increment.
+        write(&mut lcd, INSTRUCTION, &[0x38, 0x0c, 0x06, 0x01]);
+        lcd
+    }
+
+    #[test]
+    fn test_text() {
+        let mut lcd = lcd();
+        write(&mut lcd, DATA, b"Hello, world!");
+        write(&mut lcd, INSTRUCTION, &[0xc0]);
+        write(&mut lcd, DATA, b"6502\x7e");
+
+        assert_eq!(
+            vec!["Hello, world!   ", "6502\u{2192}           "],
+            lcd.text()
+        );
+    }
+
+    #[test]
+    fn test_busy() {
+        let mut lcd = lcd();
+        lcd.write(DATA, b'A');
+        assert_eq!(0x81, lcd.read(INSTRUCTION));
+
+        // Ignored while busy.
+        lcd.write(DATA, b'B');
+        lcd.tick(37);
+        assert_eq!(0x01, lcd.read(INSTRUCTION));
+        lcd.write(DATA, b'C');
+        lcd.tick(37);
+
+        assert_eq!("AC", &lcd.text()[0][..2]);
+    }
+
+    #[test]
+    fn test_read_data() {
+        let mut lcd = lcd();
+        write(&mut lcd, DATA, b"xyz");
+        write(&mut lcd, INSTRUCTION, &[0x81]);
+
+        assert_eq!(b'y', lcd.read(DATA));
+        assert_eq!(b'z', lcd.read(DATA));
+        assert_eq!(0x03, lcd.read(INSTRUCTION));
+    }
+
+    #[test]
+    fn test_four_bit_mode() {
+        let mut lcd = Hd44780::new(16, 2);
+        let mut nibbles = |rs: bool, values: &[u8]| {
+            for value in values {
+                lcd.set_pins(rs, false, true, *value);
+                lcd.set_pins(rs, false, false, *value);
+                lcd.tick(2000);
+            }
+        };
+
+        // Switch to 4-bit mode with a single transfer, then set 2 lines and turn the display on.
+        nibbles(false, &[0x20]);
+        nibbles(false, &[0x20, 0x80, 0x00, 0xc0]);
+        nibbles(true, &[0x40, 0x10]);
+
+        // Reading takes two transfers too.
+        lcd.set_pins(false, true, true, 0);
+        assert_eq!(Some(0x00), lcd.data());
+        lcd.set_pins(false, true, false, 0);
+        lcd.set_pins(false, true, true, 0);
+        assert_eq!(Some(0x10), lcd.data());
+        lcd.set_pins(false, true, false, 0);
+
+        assert_eq!("A", &lcd.text()[0][..1]);
+        assert_eq!(None, lcd.data());
+    }
+
+    #[test]
+    fn test_addressing() {
+        let mut lcd = lcd();
+        // The end of the first line continues on the second.
+        write(&mut lcd, INSTRUCTION, &[0xa7]);
+        write(&mut lcd, DATA, b"ab");
+        assert_eq!(0x41, lcd.read(INSTRUCTION));
+        assert_eq!("b", &lcd.text()[1][..1]);
+
+        // Decrementing, and moving the cursor.
+        write(&mut lcd, INSTRUCTION, &[0x04, 0x80, 0x14]);
+        write(&mut lcd, DATA, b"12");
+        assert_eq!("21", &lcd.text()[0][..2]);
+
+        // An address past the end of a line counts on from the position it shows.
+        write(&mut lcd, INSTRUCTION, &[0x06, 0xff]);
+        write(&mut lcd, DATA, b"xy");
+        assert_eq!(0x59, lcd.read(INSTRUCTION));
+    }
+
+    #[test]
+    fn test_shift() {
+        let mut lcd = lcd();
+        write(&mut lcd, DATA, b"abc");
+        write(&mut lcd, INSTRUCTION, &[0x18]);
+        assert_eq!("bc ", &lcd.text()[0][..3]);
+        write(&mut lcd, INSTRUCTION, &[0x1c, 0x1c]);
+        assert_eq!(" ab", &lcd.text()[0][..3]);
+
+        // The display follows the text.
+        write(&mut lcd, INSTRUCTION, &[0x02, 0x07, 0x90]);
+        write(&mut lcd, DATA, b"!");
+        assert_eq!("bc", &lcd.text()[0][..2]);
+        assert_eq!("!", &lcd.text()[0][15..]);
+    }
+
+    #[test]
+    fn test_cgram_and_cursor() {
+        let mut lcd = lcd();
+        write(&mut lcd, INSTRUCTION, &[0x40]);
+        write(&mut lcd, DATA, &[0x1f, 0x11]);
+        write(&mut lcd, INSTRUCTION, &[0x80]);
+        write(&mut lcd, DATA, &[0x00]);
+
+        assert_eq!(&[0x1f, 0x11], &lcd.cgram()[..2]);
+        assert_eq!("\u{2592}", &lcd.text()[0][..3]);
+        assert_eq!(None, lcd.cursor());
+
+        write(&mut lcd, INSTRUCTION, &[0x0e, 0xc3]);
+        assert_eq!(Some((1, 3)), lcd.cursor());
+
+        write(&mut lcd, INSTRUCTION, &[0x08]);
+        assert_eq!(vec![" ".repeat(16); 2], lcd.text());
+    }
+}
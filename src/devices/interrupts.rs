@@ -0,0 +1,192 @@
+//! Interrupt lines shared by any number of sources.
+//!
+//! IRQ and NMI are open drain: any chip can pull them low, and a line stays asserted until every
+//! source lets go of it. Each source is a `Source` handle kept by whatever drives it, which
+//! asserts and releases it on its own, while `Interrupts` gives the processor the wired-OR of
+//! them all and tells who is holding a line.
+//!
+//! The bus has an `Interrupts` for everything connected to it. Each device attached to it gets a
+//! source named after it, which follows `Device::interrupt`, and other sources, such as buttons
+//! or chips that are not mapped, are made on it directly.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::devices::bus::Line;
+use crate::emulator::observer::Observer;
+use crate::emulator::Emulator;
+use crate::memory::Memory;
+
+struct State {
+    id: u64,
+    name: String,
+    line: Line,
+    asserted: bool,
+}
+
+/// One output connected to an interrupt line. Dropping it lets go of the line and disconnects it.
+pub struct Source {
+    states: Rc<RefCell<Vec<State>>>,
+    id: u64,
+}
+
+impl Source {
+    pub fn set(&self, asserted: bool) {
+        self.with_state(|state| state.asserted = asserted);
+    }
+
+    pub fn assert(&self) {
+        self.set(true);
+    }
+
+    pub fn release(&self) {
+        self.set(false);
+    }
+
+    pub fn is_asserted(&self) -> bool {
+        self.with_state(|state| state.asserted)
+    }
+
+    fn with_state<T, F: FnOnce(&mut State) -> T>(&self, f: F) -> T {
+        let mut states = self.states.borrow_mut();
+        let state = states.iter_mut().find(|s| s.id == self.id).unwrap();
+        f(state)
+    }
+}
+
+impl Drop for Source {
+    fn drop(&mut self) {
+        self.states.borrow_mut().retain(|s| s.id != self.id);
+    }
+}
+
+#[derive(Default)]
+pub struct Interrupts {
+    states: Rc<RefCell<Vec<State>>>,
+    next_id: Cell<u64>,
+    /// Whether NMI was asserted when the lines were last handed to the processor.
+    nmi: bool,
+}
+
+impl Interrupts {
+    pub fn new() -> Interrupts {
+        Interrupts::default()
+    }
+
+    /// A new source connected to `line`, released to begin with. The name tells it apart in
+    /// `holding`.
+    pub fn source(&self, name: &str, line: Line) -> Source {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.states.borrow_mut().push(State {
+            id,
+            name: name.to_string(),
+            line,
+            asserted: false,
+        });
+        Source {
+            states: self.states.clone(),
+            id,
+        }
+    }
+
+    /// Whether any source asserts `line`.
+    pub fn asserts(&self, line: Line) -> bool {
+        self.states
+            .borrow()
+            .iter()
+            .any(|s| s.line == line && s.asserted)
+    }
+
+    /// The names of the sources asserting `line`, in the order they were made.
+    pub fn holding(&self, line: Line) -> Vec<String> {
+        self.states
+            .borrow()
+            .iter()
+            .filter(|s| s.line == line && s.asserted)
+            .map(|s| s.name.clone())
+            .collect()
+    }
+
+    /// Hand the lines to the processor, for machines without a `Bus`, which does this itself.
+    pub fn update<M: Memory, O: Observer>(&mut self, emulator: &mut Emulator<M, O>) {
+        let nmi = self.asserts(Line::Nmi);
+        signal(emulator, self.asserts(Line::Irq), nmi, self.nmi);
+        self.nmi = nmi;
+    }
+}
+
+/// Set the interrupt inputs of the processor from the levels of the lines. IRQ is level
+/// triggered, while NMI is edge triggered: it is only taken if it was not asserted before.
+pub(crate) fn signal<M: Memory, O: Observer>(
+    emulator: &mut Emulator<M, O>,
+    irq: bool,
+    nmi: bool,
+    was_nmi: bool,
+) {
+    emulator.irq = irq;
+    if nmi && !was_nmi {
+        emulator.nmi = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::bus::Line;
+    use crate::devices::interrupts::Interrupts;
+    use crate::emulator::Emulator;
+    use crate::memory::default::DefaultMemory;
+
+    #[test]
+    fn test_wired_or() {
+        let interrupts = Interrupts::new();
+        let timer = interrupts.source("timer", Line::Irq);
+        let serial = interrupts.source("serial", Line::Irq);
+        let button = interrupts.source("button", Line::Nmi);
+
+        timer.assert();
+        serial.assert();
+        assert!(interrupts.asserts(Line::Irq));
+        assert!(!interrupts.asserts(Line::Nmi));
+        assert_eq!(vec!["timer", "serial"], interrupts.holding(Line::Irq));
+
+        // The line stays asserted until every source lets go.
+        timer.release();
+        assert!(interrupts.asserts(Line::Irq));
+        assert_eq!(vec!["serial"], interrupts.holding(Line::Irq));
+        drop(serial);
+        assert!(!interrupts.asserts(Line::Irq));
+        assert_eq!(2, interrupts.states.borrow().len());
+
+        button.set(true);
+        assert!(button.is_asserted());
+        assert_eq!(vec!["button"], interrupts.holding(Line::Nmi));
+    }
+
+    #[test]
+    fn test_update() {
+        let mut interrupts = Interrupts::new();
+        let irq = interrupts.source("irq", Line::Irq);
+        let nmi = interrupts.source("nmi", Line::Nmi);
+        let mut e = Emulator::new(DefaultMemory::empty());
+
+        irq.assert();
+        nmi.assert();
+        interrupts.update(&mut e);
+        assert!(e.irq);
+        assert!(e.nmi);
+
+        // NMI is only taken again once it was released.
+        e.nmi = false;
+        irq.release();
+        interrupts.update(&mut e);
+        assert!(!e.irq);
+        assert!(!e.nmi);
+
+        nmi.release();
+        interrupts.update(&mut e);
+        nmi.assert();
+        interrupts.update(&mut e);
+        assert!(e.nmi);
+    }
+}
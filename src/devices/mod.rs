@@ -0,0 +1,46 @@
+//! Peripheral chips, attached to the processor through a `bus::Bus`.
+
+pub mod acia;
+pub mod apple1;
+pub mod bus;
+pub mod cia;
+pub mod hd44780;
+pub mod interrupts;
+pub mod pia;
+pub mod riot;
+pub mod scheduler;
+pub mod via;
+
+use std::io::Write;
+
+/// A memory mapped device with registers, a clock and an interrupt output.
+pub trait Device {
+    /// Read the register at `offset` from the start of the device. Unlike reading memory, this
+    /// may change the state of the device, for example by clearing an interrupt flag.
+    fn read(&mut self, offset: u16) -> u8;
+
+    /// Write the register at `offset` from the start of the device.
+    fn write(&mut self, offset: u16, value: u8);
+
+    /// Let `cycles` clock cycles pass.
+    fn tick(&mut self, _cycles: u64) {}
+
+    /// Cycles until the device next does something on its own that the processor could notice,
+    /// such as a timer running out or a character arriving, or `None` if it only changes when its
+    /// registers are accessed. `bus::run_until` clocks the devices in one go until then, which
+    /// unless told otherwise is after every instruction.
+    fn next_event(&self) -> Option<u64> {
+        Some(1)
+    }
+
+    /// Whether the interrupt output is asserted.
+    fn interrupt(&self) -> bool {
+        false
+    }
+}
+
+/// Write bytes out to the host and flush them. The processor has no way of handling errors, so
+/// output that cannot be written is lost, as if the line were disconnected.
+pub(crate) fn send<W: Write>(output: &mut W, bytes: &[u8]) {
+    let _ = output.write_all(bytes).and_then(|_| output.flush());
+}
@@ -0,0 +1,344 @@
+//! The Motorola 6820/6821 Peripheral Interface Adapter: two 8-bit ports, each with a control
+//! register and two control lines.
+//!
+//! Each port has two registers: the data direction register and the peripheral register share
+//! an address, and bit 2 of the control register chooses between them. C1 is an interrupt
+//! input, and C2 is either an interrupt input or an output. Each port has its own interrupt
+//! output, IRQA and IRQB, which the chip asserts together as far as the bus is concerned.
+//!
+//! Everything outside of the chip goes through the methods that set and get the levels of the
+//! pins, such as `set_port_a` and `port_a`. Inputs that are not driven read as high.
+
+use crate::devices::Device;
+
+// Registers.
+const PRA: u16 = 0x0;
+const CRA: u16 = 0x1;
+const PRB: u16 = 0x2;
+const CRB: u16 = 0x3;
+
+// Control register.
+const C1_INTERRUPT_ENABLE: u8 = 0x01;
+const C1_RISING: u8 = 0x02;
+/// Set to access the peripheral register, clear for the data direction register.
+const PERIPHERAL: u8 = 0x04;
+/// C2 as an input: interrupt enable and active edge. As an output: the mode, or the level.
+const C2_BIT3: u8 = 0x08;
+const C2_BIT4: u8 = 0x10;
+const C2_OUTPUT: u8 = 0x20;
+/// Set by an active edge of C2 while it is an input.
+pub const IRQ2: u8 = 0x40;
+/// Set by an active edge of C1.
+pub const IRQ1: u8 = 0x80;
+
+/// A port with its control register and control lines.
+struct Port {
+    output: u8,
+    direction: u8,
+    /// The levels driven onto the pins from outside.
+    pins: u8,
+    control: u8,
+    c1: bool,
+    c2: bool,
+    /// The level C2 is driven to as an output.
+    c2_output: bool,
+    /// Whether C2 is low for a pulse that ends with the next cycle.
+    c2_pulse: bool,
+}
+
+impl Port {
+    fn new() -> Port {
+        Port {
+            output: 0,
+            direction: 0,
+            pins: 0xff,
+            control: 0,
+            c1: true,
+            c2: true,
+            c2_output: true,
+            c2_pulse: false,
+        }
+    }
+
+    fn levels(&self) -> u8 {
+        (self.output & self.direction) | (self.pins & !self.direction)
+    }
+
+    fn write_control(&mut self, value: u8) {
+        // The flags can only be cleared by reading the peripheral register.
+        self.control = (self.control & (IRQ1 | IRQ2)) | (value & 0x3f);
+
+        if self.control & (C2_OUTPUT | C2_BIT4) == C2_OUTPUT | C2_BIT4 {
+            self.c2_output = self.control & C2_BIT3 != 0;
+        } else {
+            self.c2_output = true;
+        }
+        self.c2_pulse = false;
+    }
+
+    /// Reading port A or writing port B lowers C2 in handshake and pulse mode.
+    fn start_handshake(&mut self) {
+        if self.control & (C2_OUTPUT | C2_BIT4) == C2_OUTPUT {
+            self.c2_output = false;
+            self.c2_pulse = self.control & C2_BIT3 != 0;
+        }
+    }
+
+    fn set_c1(&mut self, level: bool) {
+        let rising = self.control & C1_RISING != 0;
+        if self.c1 != level && level == rising {
+            self.control |= IRQ1;
+            // Handshake mode raises C2 again.
+            if self.control & (C2_OUTPUT | C2_BIT4 | C2_BIT3) == C2_OUTPUT {
+                self.c2_output = true;
+            }
+        }
+        self.c1 = level;
+    }
+
+    fn set_c2(&mut self, level: bool) {
+        let rising = self.control & C2_BIT4 != 0;
+        if self.control & C2_OUTPUT == 0 && self.c2 != level && level == rising {
+            self.control |= IRQ2;
+        }
+        self.c2 = level;
+    }
+
+    fn c2_level(&self) -> bool {
+        if self.control & C2_OUTPUT != 0 {
+            self.c2_output
+        } else {
+            self.c2
+        }
+    }
+
+    fn interrupt(&self) -> bool {
+        (self.control & IRQ1 != 0 && self.control & C1_INTERRUPT_ENABLE != 0)
+            || (self.control & IRQ2 != 0 && self.control & (C2_OUTPUT | C2_BIT3) == C2_BIT3)
+    }
+}
+
+pub struct Pia {
+    a: Port,
+    b: Port,
+}
+
+impl Pia {
+    pub fn new() -> Pia {
+        Pia {
+            a: Port::new(),
+            b: Port::new(),
+        }
+    }
+
+    /// The levels of the port A pins: outputs as written by the processor, inputs as driven
+    /// from outside.
+    pub fn port_a(&self) -> u8 {
+        self.a.levels()
+    }
+
+    pub fn port_b(&self) -> u8 {
+        self.b.levels()
+    }
+
+    /// Drive the port A pins. Only the pins set as inputs are read by the processor.
+    pub fn set_port_a(&mut self, pins: u8) {
+        self.a.pins = pins;
+    }
+
+    pub fn set_port_b(&mut self, pins: u8) {
+        self.b.pins = pins;
+    }
+
+    pub fn set_ca1(&mut self, level: bool) {
+        self.a.set_c1(level);
+    }
+
+    pub fn set_cb1(&mut self, level: bool) {
+        self.b.set_c1(level);
+    }
+
+    pub fn set_ca2(&mut self, level: bool) {
+        self.a.set_c2(level);
+    }
+
+    pub fn set_cb2(&mut self, level: bool) {
+        self.b.set_c2(level);
+    }
+
+    /// The level of CA2, whether an input or an output.
+    pub fn ca2(&self) -> bool {
+        self.a.c2_level()
+    }
+
+    /// The level of CB2, whether an input or an output.
+    pub fn cb2(&self) -> bool {
+        self.b.c2_level()
+    }
+
+    /// Whether IRQA is asserted.
+    pub fn irq_a(&self) -> bool {
+        self.a.interrupt()
+    }
+
+    /// Whether IRQB is asserted.
+    pub fn irq_b(&self) -> bool {
+        self.b.interrupt()
+    }
+}
+
+impl Default for Pia {
+    fn default() -> Pia {
+        Pia::new()
+    }
+}
+
+impl Device for Pia {
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset & 0x3 {
+            PRA if self.a.control & PERIPHERAL != 0 => {
+                self.a.control &= !(IRQ1 | IRQ2);
+                self.a.start_handshake();
+                self.a.levels()
+            }
+            PRA => self.a.direction,
+            CRA => self.a.control,
+            PRB if self.b.control & PERIPHERAL != 0 => {
+                self.b.control &= !(IRQ1 | IRQ2);
+                self.b.levels()
+            }
+            PRB => self.b.direction,
+            CRB => self.b.control,
+            _ => unreachable!(),
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        match offset & 0x3 {
+            PRA if self.a.control & PERIPHERAL != 0 => self.a.output = value,
+            PRA => self.a.direction = value,
+            CRA => self.a.write_control(value),
+            PRB if self.b.control & PERIPHERAL != 0 => {
+                self.b.output = value;
+                self.b.start_handshake();
+            }
+            PRB => self.b.direction = value,
+            CRB => self.b.write_control(value),
+            _ => unreachable!(),
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        if cycles == 0 {
+            return;
+        }
+
+        for port in [&mut self.a, &mut self.b].iter_mut() {
+            if port.c2_pulse {
+                port.c2_pulse = false;
+                port.c2_output = true;
+            }
+        }
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        Some(1).filter(|_| self.a.c2_pulse || self.b.c2_pulse)
+    }
+
+    fn interrupt(&self) -> bool {
+        self.irq_a() || self.irq_b()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::pia::*;
+    use crate::devices::Device;
+
+    #[test]
+    fn test_ports() {
+        let mut pia = Pia::new();
+        // The data direction register until the peripheral register is selected.
+        pia.write(PRA, 0x0f);
+        pia.write(CRA, PERIPHERAL);
+        pia.write(PRA, 0xa5);
+        pia.set_port_a(0x3c);
+
+        assert_eq!(0x35, pia.port_a());
+        assert_eq!(0x35, pia.read(PRA));
+        pia.write(CRA, 0);
+        assert_eq!(0x0f, pia.read(PRA));
+    }
+
+    #[test]
+    fn test_c1() {
+        let mut pia = Pia::new();
+        pia.write(CRA, PERIPHERAL | C1_RISING | C1_INTERRUPT_ENABLE);
+
+        pia.set_ca1(false);
+        assert!(!pia.interrupt());
+        pia.set_ca1(true);
+        assert!(pia.irq_a());
+        assert_eq!(IRQ1, pia.read(CRA) & (IRQ1 | IRQ2));
+
+        // Writing the control register leaves the flag, reading the port clears it.
+        pia.write(CRA, PERIPHERAL | C1_RISING | C1_INTERRUPT_ENABLE);
+        assert!(pia.irq_a());
+        pia.read(PRA);
+        assert!(!pia.irq_a());
+    }
+
+    #[test]
+    fn test_c2_input() {
+        let mut pia = Pia::new();
+        // Falling edges without interrupts, then with.
+        pia.write(CRB, PERIPHERAL);
+        pia.set_cb2(false);
+        assert_eq!(IRQ2, pia.read(CRB) & IRQ2);
+        assert!(!pia.interrupt());
+
+        pia.write(CRB, PERIPHERAL | C2_BIT3);
+        assert!(pia.irq_b());
+        assert!(!pia.cb2());
+    }
+
+    #[test]
+    fn test_handshake() {
+        let mut pia = Pia::new();
+        // Port A lowers CA2 on reading, port B lowers CB2 on writing, and both raise it on an
+        // active edge of C1.
+        pia.write(CRA, PERIPHERAL | C1_RISING | C2_OUTPUT);
+        pia.write(CRB, PERIPHERAL | C1_RISING | C2_OUTPUT);
+
+        pia.write(PRA, 0);
+        assert!(pia.ca2());
+        pia.read(PRA);
+        assert!(!pia.ca2());
+
+        pia.read(PRB);
+        assert!(pia.cb2());
+        pia.write(PRB, 0x42);
+        assert!(!pia.cb2());
+        pia.tick(10);
+        assert!(!pia.cb2());
+
+        pia.set_cb1(false);
+        pia.set_cb1(true);
+        assert!(pia.cb2());
+    }
+
+    #[test]
+    fn test_pulse_and_manual() {
+        let mut pia = Pia::new();
+        pia.write(CRB, PERIPHERAL | C2_OUTPUT | C2_BIT3);
+        pia.write(PRB, 0x42);
+        assert!(!pia.cb2());
+        pia.tick(1);
+        assert!(pia.cb2());
+
+        pia.write(CRA, C2_OUTPUT | C2_BIT4);
+        assert!(!pia.ca2());
+        pia.write(CRA, C2_OUTPUT | C2_BIT4 | C2_BIT3);
+        assert!(pia.ca2());
+    }
+}
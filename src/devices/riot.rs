@@ -0,0 +1,417 @@
+//! The MOS 6532 RAM-I/O-Timer: 128 bytes of RAM, two 8-bit ports and an interval timer.
+//!
+//! The chip selects between its RAM and its registers with a separate pin, so systems tend to map
+//! them far apart (the Atari 2600 has the RAM at $80 and the registers at $280). The registers are
+//! the `Riot` itself, and the RAM is reached through a `RiotRam` that shares it, so that both
+//! can be attached to a bus while the timer is only clocked once.
+//!
+//! The timer counts down once every 1, 8, 64 or 1024 cycles, as chosen by the address it is
+//! written to. It first counts down in the cycle after it is written, so a timer loaded with N
+//! runs out N * prescaler + 1 cycles later. It then sets its interrupt flag and keeps counting
+//! down once every cycle, which tells how long ago it ran out, until it is written again.
+//!
+//! The 6530 of the KIM-1 is the same design with a mask ROM, 64 bytes of RAM and no PA7 edge
+//! detection. Every write with A2 set loads its timer, where the 6532 also looks at A4.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::devices::Device;
+
+// Registers, with the chip in I/O mode.
+const ORA: u16 = 0x00;
+const DDRA: u16 = 0x01;
+const ORB: u16 = 0x02;
+const DDRB: u16 = 0x03;
+/// Reading with this bit set reads the timer or the interrupt flags, and writing writes the
+/// timer or the edge detect control.
+const TIMER_SELECT: u16 = 0x04;
+/// Set to read the interrupt flags rather than the timer.
+const READ_FLAGS: u16 = 0x01;
+/// Set to write the timer rather than the edge detect control.
+const WRITE_TIMER: u16 = 0x10;
+/// Set when reading or writing the timer to enable its interrupt, and clear to disable it.
+const TIMER_INTERRUPT_ENABLE: u16 = 0x08;
+
+// Edge detect control, from the address written to.
+const EDGE_RISING: u16 = 0x01;
+const EDGE_INTERRUPT_ENABLE: u16 = 0x02;
+
+// Interrupt flags.
+pub const TIMER: u8 = 0x80;
+pub const PA7: u8 = 0x40;
+
+/// Which of the chips sharing this design a `Riot` is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Model {
+    Mos6532,
+    Mos6530,
+}
+
+/// Cycles per count of the timer, selected by the two lowest address bits when writing it.
+const PRESCALERS: [u16; 4] = [1, 8, 64, 1024];
+
+pub struct Riot {
+    model: Model,
+    ram: [u8; 128],
+    a_output: u8,
+    a_direction: u8,
+    /// The levels driven onto the port A pins from outside.
+    a_pins: u8,
+    b_output: u8,
+    b_direction: u8,
+    b_pins: u8,
+    timer: u8,
+    /// Cycles per count, which is 1 once the timer has run out.
+    prescaler: u16,
+    /// Cycles until the next count.
+    divider: u16,
+    timer_interrupt: bool,
+    edge_rising: bool,
+    edge_interrupt: bool,
+    flags: u8,
+}
+
+impl Riot {
+    pub fn new() -> Riot {
+        Riot::with_model(Model::Mos6532)
+    }
+
+    pub fn with_model(model: Model) -> Riot {
+        Riot {
+            model,
+            ram: [0; 128],
+            a_output: 0,
+            a_direction: 0,
+            a_pins: 0xff,
+            b_output: 0,
+            b_direction: 0,
+            b_pins: 0xff,
+            timer: 0,
+            prescaler: PRESCALERS[3],
+            divider: PRESCALERS[3],
+            timer_interrupt: false,
+            edge_rising: false,
+            edge_interrupt: false,
+            flags: 0,
+        }
+    }
+
+    pub fn ram(&self) -> &[u8; 128] {
+        &self.ram
+    }
+
+    /// The levels of the port A pins: outputs as written by the processor, inputs as driven
+    /// from outside.
+    pub fn port_a(&self) -> u8 {
+        (self.a_output & self.a_direction) | (self.a_pins & !self.a_direction)
+    }
+
+    pub fn port_b(&self) -> u8 {
+        (self.b_output & self.b_direction) | (self.b_pins & !self.b_direction)
+    }
+
+    /// Drive the port A pins. Only the pins set as inputs are read by the processor, and an
+    /// active edge of PA7 sets its interrupt flag.
+    pub fn set_port_a(&mut self, pins: u8) {
+        let pa7 = self.port_a() & 0x80 != 0;
+        self.a_pins = pins;
+        self.detect_edge(pa7);
+    }
+
+    pub fn set_port_b(&mut self, pins: u8) {
+        self.b_pins = pins;
+    }
+
+    /// Set the PA7 flag if PA7 went from `from` to the level of the active edge.
+    fn detect_edge(&mut self, from: bool) {
+        let to = self.port_a() & 0x80 != 0;
+        if self.model == Model::Mos6532 && from != to && to == self.edge_rising {
+            self.flags |= PA7;
+        }
+    }
+
+    fn ram_index(&self, offset: u16) -> usize {
+        match self.model {
+            Model::Mos6532 => (offset & 0x7f) as usize,
+            Model::Mos6530 => (offset & 0x3f) as usize,
+        }
+    }
+
+    fn write_timer(&mut self, offset: u16, value: u8) {
+        self.timer = value;
+        self.prescaler = PRESCALERS[(offset & 0x3) as usize];
+        self.divider = 1;
+        self.timer_interrupt = offset & TIMER_INTERRUPT_ENABLE != 0;
+        self.flags &= !TIMER;
+    }
+
+    fn cycle(&mut self) {
+        self.divider -= 1;
+        if self.divider > 0 {
+            return;
+        }
+
+        let (timer, ran_out) = self.timer.overflowing_sub(1);
+        self.timer = timer;
+        if ran_out {
+            self.flags |= TIMER;
+            self.prescaler = 1;
+        }
+        self.divider = self.prescaler;
+    }
+}
+
+impl Default for Riot {
+    fn default() -> Riot {
+        Riot::new()
+    }
+}
+
+impl Device for Riot {
+    fn read(&mut self, offset: u16) -> u8 {
+        if offset & TIMER_SELECT == 0 {
+            return match offset & 0x3 {
+                ORA => self.port_a(),
+                DDRA => self.a_direction,
+                ORB => self.port_b(),
+                DDRB => self.b_direction,
+                _ => unreachable!(),
+            };
+        }
+
+        if offset & READ_FLAGS != 0 {
+            // Reading the flags acknowledges the PA7 interrupt.
+            let flags = self.flags;
+            self.flags &= !PA7;
+            flags
+        } else {
+            self.timer_interrupt = offset & TIMER_INTERRUPT_ENABLE != 0;
+            self.flags &= !TIMER;
+            self.timer
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        if offset & TIMER_SELECT != 0 {
+            if offset & WRITE_TIMER != 0 || self.model == Model::Mos6530 {
+                self.write_timer(offset, value);
+            } else {
+                self.edge_rising = offset & EDGE_RISING != 0;
+                self.edge_interrupt = offset & EDGE_INTERRUPT_ENABLE != 0;
+            }
+            return;
+        }
+
+        // Changing an output can make an edge on PA7 just as well.
+        let pa7 = self.port_a() & 0x80 != 0;
+        match offset & 0x3 {
+            ORA => self.a_output = value,
+            DDRA => self.a_direction = value,
+            ORB => self.b_output = value,
+            DDRB => self.b_direction = value,
+            _ => unreachable!(),
+        }
+        self.detect_edge(pa7);
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        for _ in 0..cycles {
+            self.cycle();
+        }
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        // The timer runs out as it counts down from 0.
+        Some(self.divider as u64 + self.timer as u64 * self.prescaler as u64)
+    }
+
+    fn interrupt(&self) -> bool {
+        (self.timer_interrupt && self.flags & TIMER != 0)
+            || (self.edge_interrupt && self.flags & PA7 != 0)
+    }
+}
+
+/// The RAM of a `Riot`, to map separately from its registers. Only the first 64 bytes exist on
+/// a 6530.
+pub struct RiotRam(Rc<RefCell<Riot>>);
+
+impl RiotRam {
+    pub fn new(riot: Rc<RefCell<Riot>>) -> RiotRam {
+        RiotRam(riot)
+    }
+}
+
+impl Device for RiotRam {
+    fn read(&mut self, offset: u16) -> u8 {
+        let riot = self.0.borrow();
+        riot.ram[riot.ram_index(offset)]
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        let mut riot = self.0.borrow_mut();
+        let index = riot.ram_index(offset);
+        riot.ram[index] = value;
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::devices::bus::{step, Bus, Line};
+    use crate::devices::riot::*;
+    use crate::devices::Device;
+    use crate::emulator::instructions::opcodes::{
+        Absolute, Immediate, Implied, CLI, JMP, LDA, NOP, STA,
+    };
+    use crate::emulator::Emulator;
+    use crate::memory::default::DefaultMemory;
+    use crate::memory::Memory;
+
+    #[test]
+    fn test_ram() {
+        let riot = Rc::new(RefCell::new(Riot::new()));
+        let mut bus = Bus::new(DefaultMemory::empty());
+        bus.attach(
+            "riot ram",
+            0x80,
+            0x80,
+            Rc::new(RefCell::new(RiotRam::new(riot.clone()))),
+            Line::Irq,
+        );
+        bus.attach("riot", 0x280, 0x20, riot.clone(), Line::Irq);
+
+        bus.write(0xff, 0x42);
+        bus.write(0x281, 0xff);
+
+        assert_eq!(0x42, riot.borrow().ram()[0x7f]);
+        assert_eq!(0x42, bus.read(0xff));
+        assert_eq!(0x00, bus.memory.read(0xff));
+        assert_eq!(0xff, bus.read(0x281));
+    }
+
+    #[test]
+    fn test_ports() {
+        let mut riot = Riot::new();
+        riot.write(DDRA, 0xf0);
+        riot.write(ORA, 0xa5);
+        riot.set_port_a(0x3c);
+        assert_eq!(0xac, riot.port_a());
+        assert_eq!(0xac, riot.read(ORA));
+
+        riot.write(DDRB, 0xff);
+        riot.write(ORB, 0x42);
+        assert_eq!(0x42, riot.port_b());
+    }
+
+    #[test]
+    fn test_timer() {
+        let mut riot = Riot::new();
+        // Count every 8 cycles from 2, with interrupts.
+        riot.write(TIMER_SELECT | WRITE_TIMER | TIMER_INTERRUPT_ENABLE | 1, 2);
+
+        riot.tick(1);
+        assert_eq!(1, riot.read(TIMER_SELECT | TIMER_INTERRUPT_ENABLE));
+        riot.tick(8);
+        assert_eq!(0, riot.read(TIMER_SELECT | TIMER_INTERRUPT_ENABLE));
+        riot.tick(7);
+        assert!(!riot.interrupt());
+        riot.tick(1);
+        assert!(riot.interrupt());
+        assert_eq!(TIMER, riot.read(TIMER_SELECT | READ_FLAGS));
+
+        // Then it counts every cycle.
+        riot.tick(3);
+        assert_eq!(0xfc, riot.read(TIMER_SELECT));
+        assert!(!riot.interrupt());
+    }
+
+    #[test]
+    fn test_timer_interrupt_disabled() {
+        let mut riot = Riot::new();
+        riot.write(TIMER_SELECT | WRITE_TIMER, 1);
+        riot.tick(2);
+
+        assert!(!riot.interrupt());
+        assert_eq!(TIMER, riot.read(TIMER_SELECT | READ_FLAGS));
+    }
+
+    #[test]
+    fn test_pa7_edge() {
+        let mut riot = Riot::new();
+        // Rising edge, with interrupts.
+        riot.write(TIMER_SELECT | EDGE_INTERRUPT_ENABLE | EDGE_RISING, 0);
+        riot.set_port_a(0x00);
+        assert!(!riot.interrupt());
+        riot.set_port_a(0x80);
+        assert!(riot.interrupt());
+
+        // Reading the flags clears the PA7 flag.
+        assert_eq!(PA7, riot.read(TIMER_SELECT | READ_FLAGS));
+        assert!(!riot.interrupt());
+
+        // An output makes edges too, and falling edges are ignored.
+        riot.write(DDRA, 0x80);
+        assert!(!riot.interrupt());
+        riot.write(ORA, 0x80);
+        assert!(riot.interrupt());
+    }
+
+    #[test]
+    fn test_6530() {
+        let mut riot = Riot::with_model(Model::Mos6530);
+        // Without A4, which would write the edge detect control of a 6532.
+        riot.write(TIMER_SELECT | 2, 1);
+        riot.tick(65);
+        assert_eq!(TIMER, riot.read(TIMER_SELECT | READ_FLAGS));
+
+        riot.write(DDRA, 0x80);
+        riot.write(ORA, 0x80);
+        assert_eq!(TIMER, riot.read(TIMER_SELECT | READ_FLAGS));
+
+        let riot = Rc::new(RefCell::new(riot));
+        let mut ram = RiotRam::new(riot.clone());
+        ram.write(0x7f, 0x42);
+        assert_eq!(0x42, ram.read(0x3f));
+    }
+
+    #[test]
+    fn test_irq() {
+        // Start the timer at $0294 and wait for its interrupt.
+        let mut memory = DefaultMemory::empty();
+        memory.load(
+            [
+                LDA::immediate(0x04),
+                STA::absolute(0x029c),
+                CLI::implied(),
+                JMP::absolute(0x606),
+            ]
+            .concat(),
+            0x600,
+        );
+        memory.set_program_counter(0x600);
+        memory.load(vec![0x00, 0x07], 0xfffe);
+        memory.load(NOP::implied(), 0x700);
+
+        let riot = Rc::new(RefCell::new(Riot::new()));
+        let mut bus = Bus::new(memory);
+        bus.attach("riot", 0x280, 0x20, riot.clone(), Line::Irq);
+        let mut e = Emulator::new(bus);
+
+        for _ in 0..10 {
+            step(&mut e);
+            if e.registers.program_counter == 0x701 {
+                break;
+            }
+        }
+
+        assert_eq!(0x701, e.registers.program_counter);
+        assert!(riot.borrow().interrupt());
+    }
+}
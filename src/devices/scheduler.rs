@@ -0,0 +1,155 @@
+//! Events at given cycles, for devices whose timing matters but which have nothing to do in
+//! between: timers running out, a video chip starting a line, a serial port shifting a bit.
+//!
+//! A callback runs once at a cycle, or every so many cycles. It is given the context the events
+//! run in, which for the scheduler of a `Bus` is the bus itself, and the cycle it was due at,
+//! which may be a few cycles before the instruction that crossed it ended. Callbacks that are due
+//! at the same cycle run in the order they were scheduled.
+
+/// Tells a scheduled event apart, to cancel it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EventId(u64);
+
+type Callback<T> = Box<dyn FnMut(&mut T, u64)>;
+
+pub(crate) struct Event<T> {
+    id: EventId,
+    due: u64,
+    /// Cycles until the event is due again, for the ones that repeat.
+    period: Option<u64>,
+    callback: Callback<T>,
+}
+
+impl<T> Event<T> {
+    pub(crate) fn run(&mut self, context: &mut T) {
+        (self.callback)(context, self.due);
+    }
+}
+
+pub struct Scheduler<T> {
+    events: Vec<Event<T>>,
+    next_id: u64,
+}
+
+impl<T> Default for Scheduler<T> {
+    fn default() -> Scheduler<T> {
+        Scheduler {
+            events: Vec::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<T> Scheduler<T> {
+    pub fn new() -> Scheduler<T> {
+        Scheduler::default()
+    }
+
+    /// Run `callback` once at `cycle`.
+    pub fn at<F: FnMut(&mut T, u64) + 'static>(&mut self, cycle: u64, callback: F) -> EventId {
+        self.schedule(cycle, None, Box::new(callback))
+    }
+
+    /// Run `callback` at `first`, and then every `period` cycles.
+    pub fn every<F: FnMut(&mut T, u64) + 'static>(
+        &mut self,
+        first: u64,
+        period: u64,
+        callback: F,
+    ) -> EventId {
+        assert!(period > 0);
+        self.schedule(first, Some(period), Box::new(callback))
+    }
+
+    fn schedule(&mut self, due: u64, period: Option<u64>, callback: Callback<T>) -> EventId {
+        let id = EventId(self.next_id);
+        self.next_id += 1;
+        self.events.push(Event {
+            id,
+            due,
+            period,
+            callback,
+        });
+        id
+    }
+
+    /// Remove an event, returning whether it was still scheduled. An event cannot cancel itself
+    /// while it runs, as it is not scheduled then.
+    pub fn cancel(&mut self, id: EventId) -> bool {
+        let count = self.events.len();
+        self.events.retain(|e| e.id != id);
+        self.events.len() != count
+    }
+
+    /// The cycle the next event is due at, if any.
+    pub fn next_due(&self) -> Option<u64> {
+        self.events.iter().map(|e| e.due).min()
+    }
+
+    /// Run every event due at or before `cycle`, in the order they are due. An event that repeats
+    /// runs as many times as it was due.
+    pub fn advance(&mut self, context: &mut T, cycle: u64) {
+        while let Some(mut event) = self.take_due(cycle) {
+            event.run(context);
+            self.repeat(event);
+        }
+    }
+
+    /// Take out the earliest event due at or before `cycle`, so that it can run with a context
+    /// that holds the scheduler.
+    pub(crate) fn take_due(&mut self, cycle: u64) -> Option<Event<T>> {
+        // Events are kept in the order they were scheduled, so the first one found is the
+        // earliest of those due at the same cycle.
+        let index = self
+            .events
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.due <= cycle)
+            .min_by_key(|(_, e)| e.due)
+            .map(|(i, _)| i)?;
+        Some(self.events.remove(index))
+    }
+
+    /// Put back an event that ran, if it repeats.
+    pub(crate) fn repeat(&mut self, mut event: Event<T>) {
+        if let Some(period) = event.period {
+            event.due += period;
+            // Back in the order it was scheduled in.
+            let index = self
+                .events
+                .iter()
+                .position(|e| e.id.0 > event.id.0)
+                .unwrap_or(self.events.len());
+            self.events.insert(index, event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::devices::scheduler::Scheduler;
+
+    #[test]
+    fn test_events() {
+        let mut log = Vec::new();
+        let mut scheduler = Scheduler::new();
+        scheduler.every(100, 100, |log: &mut Vec<_>, cycle| {
+            log.push(("tick", cycle))
+        });
+        scheduler.at(200, |log: &mut Vec<_>, cycle| log.push(("once", cycle)));
+        let never = scheduler.at(250, |log: &mut Vec<_>, cycle| log.push(("never", cycle)));
+
+        assert_eq!(Some(100), scheduler.next_due());
+        scheduler.advance(&mut log, 99);
+        assert!(log.is_empty());
+
+        assert!(scheduler.cancel(never));
+        assert!(!scheduler.cancel(never));
+        scheduler.advance(&mut log, 305);
+        assert_eq!(
+            vec![("tick", 100), ("tick", 200), ("once", 200), ("tick", 300)],
+            log
+        );
+        assert_eq!(Some(400), scheduler.next_due());
+    }
+}
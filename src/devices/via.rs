@@ -0,0 +1,760 @@
+//! The MOS 6522 Versatile Interface Adapter: two 8-bit ports with handshake lines, two 16-bit
+//! timers and a shift register.
+//!
+//! The timers count clock cycles, so the VIA runs at the speed of the processor. A timer loaded
+//! with N runs out N + 1 cycles later (N + 1.5 on the chip, which has half cycles), and timer 1
+//! in free-running mode then runs out every N + 2 cycles.
+//!
+//! Everything outside of the chip goes through the methods that set and get the levels of the
+//! pins, such as `set_port_a` and `port_a`. Inputs that are not driven read as high.
+
+use crate::devices::Device;
+
+// Registers.
+const ORB: u16 = 0x0;
+const ORA: u16 = 0x1;
+const DDRB: u16 = 0x2;
+const DDRA: u16 = 0x3;
+const T1C_L: u16 = 0x4;
+const T1C_H: u16 = 0x5;
+const T1L_L: u16 = 0x6;
+const T1L_H: u16 = 0x7;
+const T2C_L: u16 = 0x8;
+const T2C_H: u16 = 0x9;
+const SR: u16 = 0xa;
+const ACR: u16 = 0xb;
+const PCR: u16 = 0xc;
+const IFR: u16 = 0xd;
+const IER: u16 = 0xe;
+/// Port A without handshake.
+const ORA_NH: u16 = 0xf;
+
+// Interrupt flags.
+pub const CA2: u8 = 0x01;
+pub const CA1: u8 = 0x02;
+pub const SHIFT: u8 = 0x04;
+pub const CB2: u8 = 0x08;
+pub const CB1: u8 = 0x10;
+pub const TIMER2: u8 = 0x20;
+pub const TIMER1: u8 = 0x40;
+
+// Auxiliary control register.
+const ACR_LATCH_A: u8 = 0x01;
+const ACR_LATCH_B: u8 = 0x02;
+const ACR_T2_COUNT_PULSES: u8 = 0x20;
+const ACR_T1_FREE_RUN: u8 = 0x40;
+const ACR_T1_PB7: u8 = 0x80;
+
+/// What CA2 or CB2 does, from the peripheral control register.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Control {
+    /// An input, setting its interrupt flag on a falling or rising edge. Independent inputs
+    /// keep the flag when the port is read or written.
+    Input {
+        rising: bool,
+        independent: bool,
+    },
+    /// Goes low when the port is accessed, and high on an active edge of CA1 or CB1.
+    Handshake,
+    /// Goes low for one cycle when the port is accessed.
+    Pulse,
+    Low,
+    High,
+}
+
+impl Control {
+    fn from(bits: u8) -> Control {
+        match bits & 0x7 {
+            0..=3 => Control::Input {
+                rising: bits & 0x2 != 0,
+                independent: bits & 0x1 != 0,
+            },
+            4 => Control::Handshake,
+            5 => Control::Pulse,
+            6 => Control::Low,
+            _ => Control::High,
+        }
+    }
+}
+
+/// A port and its control lines, which work the same for A and B except where noted.
+#[derive(Default)]
+struct Port {
+    output: u8,
+    direction: u8,
+    /// The levels driven onto the pins from outside.
+    pins: u8,
+    /// The inputs as they were at the last active edge of C1, when latching is enabled.
+    latch: u8,
+    c1: bool,
+    c2: bool,
+    /// The level C2 is driven to in handshake and pulse mode.
+    c2_output: bool,
+    /// Whether C2 is low for a pulse that ends with the next cycle.
+    c2_pulse: bool,
+}
+
+impl Port {
+    fn new() -> Port {
+        Port {
+            pins: 0xff,
+            latch: 0xff,
+            c1: true,
+            c2: true,
+            c2_output: true,
+            ..Port::default()
+        }
+    }
+
+    fn levels(&self) -> u8 {
+        (self.output & self.direction) | (self.pins & !self.direction)
+    }
+
+    fn read(&self, latching: bool) -> u8 {
+        let inputs = if latching { self.latch } else { self.pins };
+        (self.output & self.direction) | (inputs & !self.direction)
+    }
+}
+
+pub struct Via {
+    a: Port,
+    b: Port,
+    t1_counter: u16,
+    t1_latch: u16,
+    /// Whether timer 1 interrupts when it next runs out in one-shot mode.
+    t1_armed: bool,
+    /// Whether timer 1 reloads from the latch in the next cycle.
+    t1_reload: bool,
+    pb7: bool,
+    t2_counter: u16,
+    t2_latch: u8,
+    t2_armed: bool,
+    shift: u8,
+    /// Bits shifted since the shift register was last accessed, up to 8.
+    shifted: u8,
+    /// Cycles until the next shift, when shifting at the rate of timer 2.
+    shift_divider: u16,
+    /// The last bit shifted out, which drives CB2.
+    shift_out: bool,
+    acr: u8,
+    pcr: u8,
+    ifr: u8,
+    ier: u8,
+}
+
+impl Via {
+    pub fn new() -> Via {
+        Via {
+            a: Port::new(),
+            b: Port::new(),
+            t1_counter: 0,
+            t1_latch: 0,
+            t1_armed: false,
+            t1_reload: false,
+            pb7: true,
+            t2_counter: 0,
+            t2_latch: 0,
+            t2_armed: false,
+            shift: 0,
+            shifted: 8,
+            shift_divider: 0,
+            shift_out: true,
+            acr: 0,
+            pcr: 0,
+            ifr: 0,
+            ier: 0,
+        }
+    }
+
+    /// The levels of the port A pins: outputs as written by the processor, inputs as driven
+    /// from outside.
+    pub fn port_a(&self) -> u8 {
+        self.a.levels()
+    }
+
+    /// The levels of the port B pins, including PB7 when timer 1 drives it.
+    pub fn port_b(&self) -> u8 {
+        let levels = self.b.levels();
+        if self.acr & ACR_T1_PB7 != 0 {
+            (levels & 0x7f) | ((self.pb7 as u8) << 7)
+        } else {
+            levels
+        }
+    }
+
+    /// Drive the port A pins. Only the pins set as inputs are read by the processor.
+    pub fn set_port_a(&mut self, pins: u8) {
+        self.a.pins = pins;
+    }
+
+    /// Drive the port B pins. Falling edges of PB6 are counted by timer 2 in pulse counting mode.
+    pub fn set_port_b(&mut self, pins: u8) {
+        let pb6_falls = self.b.pins & 0x40 != 0 && pins & 0x40 == 0;
+        self.b.pins = pins;
+
+        if pb6_falls && self.acr & ACR_T2_COUNT_PULSES != 0 {
+            self.t2_counter = self.t2_counter.wrapping_sub(1);
+            if self.t2_counter == 0 && self.t2_armed {
+                self.t2_armed = false;
+                self.flag(TIMER2);
+            }
+        }
+    }
+
+    pub fn set_ca1(&mut self, level: bool) {
+        let rising = self.pcr & 0x01 != 0;
+        if edge(self.a.c1, level, rising) {
+            self.flag(CA1);
+            if self.acr & ACR_LATCH_A != 0 {
+                self.a.latch = self.a.pins;
+            }
+            if Control::from(self.pcr >> 1) == Control::Handshake {
+                self.a.c2_output = true;
+            }
+        }
+        self.a.c1 = level;
+    }
+
+    pub fn set_cb1(&mut self, level: bool) {
+        let rising = self.pcr & 0x10 != 0;
+        if edge(self.b.c1, level, rising) {
+            self.flag(CB1);
+            if self.acr & ACR_LATCH_B != 0 {
+                self.b.latch = self.b.pins;
+            }
+            if Control::from(self.pcr >> 5) == Control::Handshake {
+                self.b.c2_output = true;
+            }
+        }
+
+        // CB1 is the clock of the shift register in external clock modes. Bits are shifted in
+        // on the rising edge, and out on the falling edge.
+        let mode = self.shift_mode();
+        if (mode == 3 && !self.b.c1 && level) || (mode == 7 && self.b.c1 && !level) {
+            self.shift_bit();
+        }
+
+        self.b.c1 = level;
+    }
+
+    pub fn set_ca2(&mut self, level: bool) {
+        if let Control::Input { rising, .. } = Control::from(self.pcr >> 1) {
+            if edge(self.a.c2, level, rising) {
+                self.flag(CA2);
+            }
+        }
+        self.a.c2 = level;
+    }
+
+    pub fn set_cb2(&mut self, level: bool) {
+        if let Control::Input { rising, .. } = Control::from(self.pcr >> 5) {
+            if edge(self.b.c2, level, rising) {
+                self.flag(CB2);
+            }
+        }
+        self.b.c2 = level;
+    }
+
+    /// The level of CA2, whether an input or an output.
+    pub fn ca2(&self) -> bool {
+        c2_level(&self.a, Control::from(self.pcr >> 1))
+    }
+
+    /// The level of CB2, whether an input, an output or the output of the shift register.
+    pub fn cb2(&self) -> bool {
+        if self.shift_mode() >= 4 {
+            return self.shift_out;
+        }
+        c2_level(&self.b, Control::from(self.pcr >> 5))
+    }
+
+    fn flag(&mut self, flags: u8) {
+        self.ifr |= flags;
+    }
+
+    fn clear(&mut self, flags: u8) {
+        self.ifr &= !flags;
+    }
+
+    fn shift_mode(&self) -> u8 {
+        (self.acr >> 2) & 0x7
+    }
+
+    /// Reading or writing a port clears the interrupt flags of its control lines, and starts a
+    /// handshake on C2.
+    fn access_a(&mut self) {
+        let control = Control::from(self.pcr >> 1);
+        self.clear(CA1 | c2_flag(control, CA2));
+        start_handshake(&mut self.a, control);
+    }
+
+    fn access_b(&mut self) {
+        let control = Control::from(self.pcr >> 5);
+        self.clear(CB1 | c2_flag(control, CB2));
+        start_handshake(&mut self.b, control);
+    }
+
+    fn start_shift(&mut self) {
+        self.clear(SHIFT);
+        self.shifted = 0;
+        self.shift_divider = self.t2_latch as u16 + 1;
+    }
+
+    fn shift_bit(&mut self) {
+        let mode = self.shift_mode();
+        if mode == 0 || (self.shifted == 8 && mode != 4) {
+            return;
+        }
+
+        if mode < 4 {
+            self.shift = (self.shift << 1) | self.b.c2 as u8;
+        } else {
+            self.shift_out = self.shift & 0x80 != 0;
+            self.shift = self.shift.rotate_left(1);
+        }
+
+        self.shifted += 1;
+        if self.shifted == 8 {
+            if mode == 4 {
+                // Free-running output repeats the byte, without interrupts.
+                self.shifted = 0;
+            } else {
+                self.flag(SHIFT);
+            }
+        }
+    }
+
+    fn cycle(&mut self) {
+        if self.a.c2_pulse {
+            self.a.c2_pulse = false;
+            self.a.c2_output = true;
+        }
+        if self.b.c2_pulse {
+            self.b.c2_pulse = false;
+            self.b.c2_output = true;
+        }
+
+        // Timer 1 counts down to 0, then runs out in the next cycle as it wraps around, and in
+        // free-running mode it is reloaded from the latch in the cycle after that.
+        if self.t1_reload {
+            self.t1_reload = false;
+            self.t1_counter = self.t1_latch;
+        } else {
+            let (counter, ran_out) = self.t1_counter.overflowing_sub(1);
+            self.t1_counter = counter;
+            if ran_out {
+                if self.acr & ACR_T1_FREE_RUN != 0 {
+                    self.flag(TIMER1);
+                    self.pb7 = !self.pb7;
+                    self.t1_reload = true;
+                } else if self.t1_armed {
+                    self.flag(TIMER1);
+                    self.pb7 = true;
+                    self.t1_armed = false;
+                }
+            }
+        }
+
+        if self.acr & ACR_T2_COUNT_PULSES == 0 {
+            let (counter, ran_out) = self.t2_counter.overflowing_sub(1);
+            self.t2_counter = counter;
+            if ran_out && self.t2_armed {
+                self.flag(TIMER2);
+                self.t2_armed = false;
+            }
+        }
+
+        match self.shift_mode() {
+            1 | 4 | 5 => {
+                if self.shift_divider == 0 {
+                    self.shift_bit();
+                    self.shift_divider = self.t2_latch as u16 + 1;
+                } else {
+                    self.shift_divider -= 1;
+                }
+            }
+            2 | 6 => self.shift_bit(),
+            _ => {}
+        }
+    }
+}
+
+impl Default for Via {
+    fn default() -> Via {
+        Via::new()
+    }
+}
+
+impl Device for Via {
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset & 0xf {
+            ORB => {
+                self.clear(CB1 | c2_flag(Control::from(self.pcr >> 5), CB2));
+                let value = self.b.read(self.acr & ACR_LATCH_B != 0);
+                if self.acr & ACR_T1_PB7 != 0 {
+                    (value & 0x7f) | ((self.pb7 as u8) << 7)
+                } else {
+                    value
+                }
+            }
+            ORA => {
+                self.access_a();
+                self.a.read(self.acr & ACR_LATCH_A != 0)
+            }
+            DDRB => self.b.direction,
+            DDRA => self.a.direction,
+            T1C_L => {
+                self.clear(TIMER1);
+                self.t1_counter as u8
+            }
+            T1C_H => (self.t1_counter >> 8) as u8,
+            T1L_L => self.t1_latch as u8,
+            T1L_H => (self.t1_latch >> 8) as u8,
+            T2C_L => {
+                self.clear(TIMER2);
+                self.t2_counter as u8
+            }
+            T2C_H => (self.t2_counter >> 8) as u8,
+            SR => {
+                self.start_shift();
+                self.shift
+            }
+            ACR => self.acr,
+            PCR => self.pcr,
+            IFR => self.ifr | ((self.interrupt() as u8) << 7),
+            IER => self.ier | 0x80,
+            ORA_NH => self.a.read(self.acr & ACR_LATCH_A != 0),
+            _ => unreachable!(),
+        }
+    }
+
+    fn write(&mut self, offset: u16, value: u8) {
+        match offset & 0xf {
+            ORB => {
+                self.b.output = value;
+                self.access_b();
+            }
+            ORA => {
+                self.a.output = value;
+                self.access_a();
+            }
+            DDRB => self.b.direction = value,
+            DDRA => self.a.direction = value,
+            T1C_L | T1L_L => self.t1_latch = (self.t1_latch & 0xff00) | value as u16,
+            T1C_H => {
+                self.t1_latch = (self.t1_latch & 0x00ff) | ((value as u16) << 8);
+                self.t1_counter = self.t1_latch;
+                self.t1_armed = true;
+                self.t1_reload = false;
+                self.pb7 = false;
+                self.clear(TIMER1);
+            }
+            T1L_H => {
+                self.t1_latch = (self.t1_latch & 0x00ff) | ((value as u16) << 8);
+                self.clear(TIMER1);
+            }
+            T2C_L => self.t2_latch = value,
+            T2C_H => {
+                self.t2_counter = ((value as u16) << 8) | self.t2_latch as u16;
+                self.t2_armed = true;
+                self.clear(TIMER2);
+            }
+            SR => {
+                self.shift = value;
+                self.start_shift();
+            }
+            ACR => self.acr = value,
+            PCR => self.pcr = value,
+            IFR => self.clear(value & 0x7f),
+            IER => {
+                if value & 0x80 != 0 {
+                    self.ier |= value & 0x7f;
+                } else {
+                    self.ier &= !value;
+                }
+            }
+            ORA_NH => self.a.output = value,
+            _ => unreachable!(),
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        for _ in 0..cycles {
+            self.cycle();
+        }
+    }
+
+    fn next_event(&self) -> Option<u64> {
+        // Pulses end and bits are shifted cycle by cycle.
+        if self.a.c2_pulse || self.b.c2_pulse || matches!(self.shift_mode(), 1 | 2 | 4 | 5 | 6) {
+            return Some(1);
+        }
+
+        let t1 = if self.t1_reload {
+            Some(1)
+        } else if self.acr & ACR_T1_FREE_RUN != 0 || self.t1_armed {
+            Some(self.t1_counter as u64 + 1)
+        } else {
+            None
+        };
+        let t2 = if self.acr & ACR_T2_COUNT_PULSES == 0 && self.t2_armed {
+            Some(self.t2_counter as u64 + 1)
+        } else {
+            None
+        };
+        t1.into_iter().chain(t2).min()
+    }
+
+    fn interrupt(&self) -> bool {
+        self.ifr & self.ier & 0x7f != 0
+    }
+}
+
+/// Whether going from `from` to `to` is the active edge.
+fn edge(from: bool, to: bool, rising: bool) -> bool {
+    from != to && to == rising
+}
+
+/// The flag of C2 that accessing the port clears, unless C2 is an independent input.
+fn c2_flag(control: Control, flag: u8) -> u8 {
+    match control {
+        Control::Input {
+            independent: true, ..
+        } => 0,
+        _ => flag,
+    }
+}
+
+fn start_handshake(port: &mut Port, control: Control) {
+    match control {
+        Control::Handshake => port.c2_output = false,
+        Control::Pulse => {
+            port.c2_output = false;
+            port.c2_pulse = true;
+        }
+        _ => {}
+    }
+}
+
+fn c2_level(port: &Port, control: Control) -> bool {
+    match control {
+        Control::Input { .. } => port.c2,
+        Control::Handshake | Control::Pulse => port.c2_output,
+        Control::Low => false,
+        Control::High => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::devices::bus::{step, Bus, Line};
+    use crate::devices::via::*;
+    use crate::devices::Device;
+    use crate::emulator::instructions::opcodes::{
+        Absolute, Immediate, Implied, CLI, JMP, LDA, NOP, STA,
+    };
+    use crate::emulator::Emulator;
+    use crate::memory::default::DefaultMemory;
+
+    #[test]
+    fn test_ports() {
+        let mut via = Via::new();
+        via.write(DDRA, 0x0f);
+        via.write(ORA, 0xa5);
+        via.set_port_a(0x3c);
+
+        assert_eq!(0x35, via.port_a());
+        assert_eq!(0x35, via.read(ORA));
+
+        via.write(DDRB, 0xff);
+        via.write(ORB, 0x42);
+        assert_eq!(0x42, via.port_b());
+    }
+
+    #[test]
+    fn test_timer1_one_shot() {
+        let mut via = Via::new();
+        via.write(IER, 0x80 | TIMER1);
+        via.write(T1C_L, 10);
+        via.write(T1C_H, 0);
+
+        via.tick(10);
+        assert!(!via.interrupt());
+        via.tick(1);
+        assert!(via.interrupt());
+        assert_eq!(0x80 | TIMER1, via.read(IFR));
+
+        // Reading the low byte of the counter clears the flag, and it does not come back.
+        via.read(T1C_L);
+        assert!(!via.interrupt());
+        via.tick(0x20000);
+        assert!(!via.interrupt());
+    }
+
+    #[test]
+    fn test_timer1_free_running() {
+        let mut via = Via::new();
+        via.write(ACR, ACR_T1_FREE_RUN | ACR_T1_PB7);
+        via.write(T1C_L, 4);
+        via.write(T1C_H, 0);
+        assert_eq!(0, via.port_b() & 0x80);
+
+        // After 5 cycles, then every 6.
+        let mut times = Vec::new();
+        for cycle in 1..=20 {
+            via.tick(1);
+            if via.ifr & TIMER1 != 0 {
+                times.push(cycle);
+                via.write(IFR, TIMER1);
+            }
+        }
+
+        assert_eq!(vec![5, 11, 17], times);
+        assert_eq!(0x80, via.port_b() & 0x80);
+    }
+
+    #[test]
+    fn test_timer2() {
+        let mut via = Via::new();
+        via.write(IER, 0x80 | TIMER2);
+        via.write(T2C_L, 3);
+        via.write(T2C_H, 0);
+        via.tick(5);
+        assert!(via.interrupt());
+
+        via.write(ACR, ACR_T2_COUNT_PULSES);
+        via.write(T2C_L, 2);
+        via.write(T2C_H, 0);
+        for _ in 0..2 {
+            assert!(!via.interrupt());
+            via.set_port_b(0xbf);
+            via.set_port_b(0xff);
+        }
+        assert!(via.interrupt());
+    }
+
+    #[test]
+    fn test_interrupt_enable() {
+        let mut via = Via::new();
+        via.write(IER, 0x80 | CA1 | CB1);
+        via.write(IER, CB1);
+        assert_eq!(0x80 | CA1, via.read(IER));
+
+        via.set_cb1(false);
+        assert_eq!(CB1, via.read(IFR));
+        assert!(!via.interrupt());
+
+        via.write(IFR, 0x7f);
+        assert_eq!(0, via.read(IFR));
+    }
+
+    #[test]
+    fn test_ca1_latch_and_handshake() {
+        let mut via = Via::new();
+        // Positive CA1 edge, CA2 handshake output, latch port A.
+        via.write(PCR, 0x09);
+        via.write(ACR, ACR_LATCH_A);
+        via.write(IER, 0x80 | CA1);
+
+        via.set_ca1(false);
+        via.set_port_a(0x41);
+        via.set_ca1(true);
+        via.set_port_a(0x00);
+        assert!(via.interrupt());
+
+        assert_eq!(0x41, via.read(ORA));
+        assert!(!via.interrupt());
+        assert!(!via.ca2());
+
+        via.set_ca1(false);
+        via.set_ca1(true);
+        assert!(via.ca2());
+
+        // Port A without handshake leaves the flag alone.
+        via.read(ORA_NH);
+        assert!(via.interrupt());
+    }
+
+    #[test]
+    fn test_cb2_pulse() {
+        let mut via = Via::new();
+        via.write(PCR, 0xa0);
+        via.write(ORB, 0);
+        assert!(!via.cb2());
+        via.tick(1);
+        assert!(via.cb2());
+    }
+
+    #[test]
+    fn test_shift_out() {
+        let mut via = Via::new();
+        // Shift out at the rate of the clock.
+        via.write(ACR, 0x18);
+        via.write(SR, 0b1011_0000);
+
+        let mut bits = Vec::new();
+        for _ in 0..8 {
+            via.tick(1);
+            bits.push(via.cb2() as u8);
+        }
+
+        assert_eq!(vec![1, 0, 1, 1, 0, 0, 0, 0], bits);
+        assert_eq!(SHIFT, via.ifr & SHIFT);
+    }
+
+    #[test]
+    fn test_shift_in_external_clock() {
+        let mut via = Via::new();
+        via.write(ACR, 0x0c);
+        via.read(SR);
+
+        for bit in [true, false, false, true, true, false, true, false].iter() {
+            via.set_cb2(*bit);
+            via.set_cb1(false);
+            via.set_cb1(true);
+        }
+
+        assert_eq!(0b1001_1010, via.read(SR));
+    }
+
+    #[test]
+    fn test_irq() {
+        // Start timer 1 at $6000 and wait for its interrupt.
+        let mut memory = DefaultMemory::empty();
+        memory.load(
+            [
+                LDA::immediate(0xc0),
+                STA::absolute(0x600e),
+                LDA::immediate(0x20),
+                STA::absolute(0x6004),
+                LDA::immediate(0x00),
+                STA::absolute(0x6005),
+                CLI::implied(),
+                JMP::absolute(0x610),
+            ]
+            .concat(),
+            0x600,
+        );
+        memory.set_program_counter(0x600);
+        memory.load(vec![0x00, 0x07], 0xfffe);
+        memory.load(NOP::implied(), 0x700);
+
+        let via = Rc::new(RefCell::new(Via::new()));
+        let mut bus = Bus::new(memory);
+        bus.attach("via", 0x6000, 0x10, via.clone(), Line::Irq);
+        let mut e = Emulator::new(bus);
+
+        for _ in 0..30 {
+            step(&mut e);
+            if e.registers.program_counter == 0x701 {
+                break;
+            }
+        }
+
+        assert_eq!(0x701, e.registers.program_counter);
+        assert!(via.borrow().interrupt());
+    }
+}
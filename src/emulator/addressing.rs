@@ -1,7 +1,8 @@
+use crate::emulator::observer::Observer;
 use crate::emulator::Emulator;
 use crate::memory::Memory;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum AddressMode {
     Immediate,
     ZeroPage,
@@ -16,7 +17,7 @@ pub enum AddressMode {
     IndirectIndexed,
 }
 
-impl<C: Memory> Emulator<C> {
+impl<C: Memory, O: Observer> Emulator<C, O> {
     fn address_zero_page(&mut self) -> u16 {
         let immediate_address = self.address_immediate();
         self.read(immediate_address) as u16
@@ -40,7 +41,7 @@ impl<C: Memory> Emulator<C> {
     // itself, so read the program counter (and increment).
     pub(crate) fn address_immediate(&mut self) -> u16 {
         let v = self.registers.program_counter;
-        self.registers.program_counter += 1;
+        self.registers.program_counter = v.wrapping_add(1);
         v
     }
 
@@ -56,11 +57,13 @@ impl<C: Memory> Emulator<C> {
     }
 
     fn address_absolute_x(&mut self) -> u16 {
-        self.address_absolute() + self.registers.x as u16
+        self.address_absolute()
+            .wrapping_add(self.registers.x as u16)
     }
 
     fn address_absolute_y(&mut self) -> u16 {
-        self.address_absolute() + self.registers.y as u16
+        self.address_absolute()
+            .wrapping_add(self.registers.y as u16)
     }
 
     fn address_indirect(&mut self) -> u16 {
@@ -68,7 +71,7 @@ impl<C: Memory> Emulator<C> {
         // Bug compatible with the original 6502.
         self.read_two(
             least_significant,
-            (least_significant & 0xFF00) | ((least_significant + 1) % 0x100),
+            (least_significant & 0xFF00) | (least_significant.wrapping_add(1) & 0xFF),
         )
     }
 
@@ -81,11 +84,12 @@ impl<C: Memory> Emulator<C> {
     fn address_indirect_indexed(&mut self) -> u16 {
         let least_significant = self.address_zero_page();
         let most_significant = (least_significant + 1) % 0x100;
-        self.read_two(least_significant, most_significant) + self.registers.y as u16
+        self.read_two(least_significant, most_significant)
+            .wrapping_add(self.registers.y as u16)
     }
 
     pub(crate) fn address(&mut self, address_mode: AddressMode) -> u16 {
-        match address_mode {
+        let address = match address_mode {
             AddressMode::Immediate => self.address_immediate(),
             AddressMode::ZeroPage => self.address_zero_page(),
             AddressMode::ZeroPageX => self.address_zero_page_offset_x(),
@@ -97,7 +101,18 @@ impl<C: Memory> Emulator<C> {
             AddressMode::Indirect => self.address_indirect(),
             AddressMode::IndexedIndirect => self.address_indexed_indirect(),
             AddressMode::IndirectIndexed => self.address_indirect_indexed(),
+        };
+
+        if O::ENABLED {
+            // Relative addresses are displacements, but the observer is told the target.
+            let effective_address = match address_mode {
+                AddressMode::Relative => self.registers.program_counter.wrapping_add(address),
+                _ => address,
+            };
+            self.operand = Some((address_mode, effective_address));
         }
+
+        address
     }
 }
 
@@ -0,0 +1,173 @@
+use crate::emulator::observer::Observer;
+use crate::emulator::Emulator;
+use crate::memory::Memory;
+
+/// Cycles taken by hardware interrupts, which are serviced like a BRK.
+pub(crate) const INTERRUPT_CYCLES: u64 = 7;
+
+/// Cycles taken by each opcode, not counting page crossings and taken branches. Opcodes that
+/// are not implemented are 0.
+#[rustfmt::skip]
+const CYCLES: [u8; 256] = [
+//  0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f
+    7, 6, 0, 0, 0, 3, 5, 0, 3, 2, 2, 0, 0, 4, 6, 0, // 0
+    2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0, // 1
+    6, 6, 0, 0, 3, 3, 5, 0, 4, 2, 2, 0, 4, 4, 6, 0, // 2
+    2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0, // 3
+    6, 6, 0, 0, 0, 3, 5, 0, 3, 2, 2, 0, 3, 4, 6, 0, // 4
+    2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0, // 5
+    6, 6, 0, 0, 0, 3, 5, 0, 4, 2, 2, 0, 5, 4, 6, 0, // 6
+    2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0, // 7
+    0, 6, 0, 0, 3, 3, 3, 0, 2, 0, 2, 0, 4, 4, 4, 0, // 8
+    2, 6, 0, 0, 4, 4, 4, 0, 2, 5, 2, 0, 0, 5, 0, 0, // 9
+    2, 6, 2, 0, 3, 3, 3, 0, 2, 2, 2, 0, 4, 4, 4, 0, // a
+    2, 5, 0, 0, 4, 4, 4, 0, 2, 4, 2, 0, 4, 4, 4, 0, // b
+    2, 6, 0, 0, 3, 3, 5, 0, 2, 2, 2, 0, 4, 4, 6, 0, // c
+    2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0, // d
+    2, 6, 0, 0, 3, 3, 5, 0, 2, 2, 2, 0, 4, 4, 6, 0, // e
+    2, 5, 0, 0, 0, 4, 6, 0, 2, 4, 0, 0, 0, 4, 7, 0, // f
+];
+
+/// Branches take a cycle more when taken, and another when they land on a different page.
+fn is_branch(instruction: u8) -> bool {
+    instruction & 0x1F == 0x10
+}
+
+enum Indexed {
+    Absolute(u8),
+    IndirectIndexed,
+}
+
+/// Reads that take a cycle more when indexing crosses a page boundary. Writes and
+/// read-modify-write instructions always take that cycle, so it is part of their base count.
+fn page_crossing_mode(instruction: u8, x: u8, y: u8) -> Option<Indexed> {
+    match instruction {
+        0x11 | 0x31 | 0x51 | 0x71 | 0xB1 | 0xD1 | 0xF1 => Some(Indexed::IndirectIndexed),
+        0x19 | 0x39 | 0x59 | 0x79 | 0xB9 | 0xD9 | 0xF9 | 0xBE => Some(Indexed::Absolute(y)),
+        0x1D | 0x3D | 0x5D | 0x7D | 0xBD | 0xDD | 0xFD | 0xBC => Some(Indexed::Absolute(x)),
+        _ => None,
+    }
+}
+
+fn crosses_page(from: u16, to: u16) -> bool {
+    from & 0xFF00 != to & 0xFF00
+}
+
+impl<C: Memory, O: Observer> Emulator<C, O> {
+    /// The cycles taken by the instruction at `address`, before it is executed. Branches are
+    /// counted as not taken; see `branch_cycles`.
+    pub(crate) fn instruction_cycles(&self, instruction: u8, address: u16) -> u64 {
+        let operand = address.wrapping_add(1);
+        let base = match page_crossing_mode(instruction, self.registers.x, self.registers.y) {
+            Some(Indexed::Absolute(index)) => {
+                let base = self.peek_two(operand, operand.wrapping_add(1));
+                Some((base, index))
+            }
+            Some(Indexed::IndirectIndexed) => {
+                let pointer = self.memory.peek(operand);
+                let base = self.peek_two(pointer as u16, pointer.wrapping_add(1) as u16);
+                Some((base, self.registers.y))
+            }
+            None => None,
+        };
+
+        let penalty = match base {
+            Some((base, index)) if crosses_page(base, base.wrapping_add(index as u16)) => 1,
+            _ => 0,
+        };
+
+        CYCLES[instruction as usize] as u64 + penalty
+    }
+
+    /// The extra cycles taken by a branch at `address`, after it is executed.
+    pub(crate) fn branch_cycles(&self, instruction: u8, address: u16) -> u64 {
+        let next = address.wrapping_add(2);
+        let target = self.registers.program_counter;
+
+        match (is_branch(instruction), target == next) {
+            (true, false) if crosses_page(next, target) => 2,
+            (true, false) => 1,
+            _ => 0,
+        }
+    }
+
+    fn peek_two(&self, least_significant: u16, most_significant: u16) -> u16 {
+        ((self.memory.peek(most_significant) as u16) << 8)
+            | (self.memory.peek(least_significant) as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::instructions::opcodes::*;
+    use crate::emulator::registers::Flag;
+    use crate::emulator::tests::setup;
+    use crate::emulator::Emulator;
+    use crate::memory::default::DefaultMemory;
+
+    fn cycles(program: Vec<Vec<u8>>, setup_fn: fn(&mut Emulator<DefaultMemory>)) -> u64 {
+        let mut e = setup(vec![]);
+        e.memory.load(program.concat(), 0x600);
+        setup_fn(&mut e);
+
+        e.execute_next();
+        e.cycles
+    }
+
+    #[test]
+    fn test_base_cycles() {
+        assert_eq!(2, cycles(vec![LDA::immediate(1)], |_| {}));
+        assert_eq!(6, cycles(vec![JSR::absolute(0x700)], |_| {}));
+        assert_eq!(
+            7,
+            cycles(vec![INC::absolute_x(0x7FF)], |e| e.registers.x = 1)
+        );
+    }
+
+    #[test]
+    fn test_page_crossing() {
+        assert_eq!(
+            4,
+            cycles(vec![LDA::absolute_x(0x700)], |e| e.registers.x = 0xFF)
+        );
+        assert_eq!(
+            5,
+            cycles(vec![LDA::absolute_x(0x701)], |e| e.registers.x = 0xFF)
+        );
+        assert_eq!(
+            5,
+            cycles(vec![STA::absolute_x(0x700)], |e| e.registers.x = 0xFF)
+        );
+        assert_eq!(
+            6,
+            cycles(vec![LDA::indirect_indexed(0x10)], |e| {
+                e.memory.load(vec![0x80, 0x07], 0x10);
+                e.registers.y = 0x80;
+            })
+        );
+    }
+
+    #[test]
+    fn test_branches() {
+        assert_eq!(
+            2,
+            cycles(vec![BNE::relative(0x10)], |e| e
+                .registers
+                .status
+                .set(Flag::Zero))
+        );
+        assert_eq!(3, cycles(vec![BNE::relative(0x10)], |_| {}));
+        assert_eq!(4, cycles(vec![BNE::relative(-3)], |_| {}));
+    }
+
+    #[test]
+    fn test_interrupt() {
+        assert_eq!(
+            9,
+            cycles(vec![NOP::implied()], |e| {
+                e.memory.load(vec![0x00, 0x06], 0xFFFA);
+                e.nmi = true;
+            })
+        );
+    }
+}
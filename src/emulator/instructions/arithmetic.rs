@@ -1,43 +1,73 @@
 use crate::emulator::addressing::AddressMode;
+use crate::emulator::observer::Observer;
 use crate::emulator::registers::Flag;
 use crate::emulator::Emulator;
 use crate::memory::Memory;
 
-impl<C: Memory> Emulator<C> {
-    fn adc_value(&mut self, value: u8) {
+impl<C: Memory, O: Observer> Emulator<C, O> {
+    fn add_binary(&mut self, value: u8) {
         let carry = self.registers.status.get(Flag::Carry) as u16;
+        let result = self.registers.accumulator as u16 + value as u16 + carry;
 
-        // Split into least and most significant.
-        let mut result_least_significant =
-            (self.registers.accumulator as u16 & 0x0F) + (value as u16 & 0x0F) + carry;
-        let mut result_most_significant =
-            (self.registers.accumulator as u16 & 0xF0) + (value as u16 & 0xF0);
+        self.registers.status.update_carry(result);
+        self.registers
+            .status
+            .update_overflow(self.registers.accumulator, value, result);
 
-        // Correct values for BCD.
-        if self.registers.status.get(Flag::Decimal) {
-            if result_least_significant > 0x09 {
-                result_least_significant = (result_least_significant + 0x06) & 0x0F;
-                result_most_significant += 0x10; // Intermediate carry.
-            }
+        let result = (result & 0xFF) as u8;
+        self.registers.accumulator = result;
 
-            if result_most_significant > 0x90 {
-                result_most_significant += 0x60;
-            }
+        self.registers.status.update_zero_negative(result);
+    }
+
+    /// Add in decimal mode, including the flags of the NMOS 6502. See readme for details.
+    fn add_decimal(&mut self, value: u8) {
+        let accumulator = self.registers.accumulator as u16;
+        let carry = self.registers.status.get(Flag::Carry) as u16;
+
+        // Add the least significant digits, correcting the result for BCD.
+        let mut least_significant = (accumulator & 0x0F) + (value as u16 & 0x0F) + carry;
+        if least_significant > 0x09 {
+            least_significant = ((least_significant + 0x06) & 0x0F) + 0x10; // Intermediate carry.
         }
+        let mut result = (accumulator & 0xF0) + (value as u16 & 0xF0) + least_significant;
 
-        let result_intermediate = result_least_significant + result_most_significant;
+        // Zero follows the binary sum, negative and overflow the sum before the most significant
+        // digit is corrected.
+        let binary = accumulator + value as u16 + carry;
+        self.registers.status.update_zero((binary & 0xFF) as u8);
+        self.registers
+            .status
+            .set_to(Flag::Negative, result & 0x80 != 0);
+        self.registers
+            .status
+            .update_overflow(self.registers.accumulator, value, result);
 
-        self.registers.status.update_carry(result_intermediate);
-        self.registers.status.update_overflow(
-            self.registers.accumulator,
-            value,
-            result_intermediate,
-        );
+        if result > 0x9F {
+            result += 0x60;
+        }
 
-        let result = (result_intermediate & 0xFF) as u8;
-        self.registers.accumulator = result;
+        self.registers.status.update_carry(result);
+        self.registers.accumulator = (result & 0xFF) as u8;
+    }
 
-        self.registers.status.update_zero_negative(result);
+    /// Subtract in decimal mode. The flags are those of binary subtraction. See readme for
+    /// details.
+    fn subtract_decimal(&mut self, value: u8) {
+        let accumulator = self.registers.accumulator as i16;
+        let borrow = !self.registers.status.get(Flag::Carry) as i16;
+
+        let mut least_significant = (accumulator & 0x0F) - (value as i16 & 0x0F) - borrow;
+        if least_significant < 0 {
+            least_significant = ((least_significant - 0x06) & 0x0F) - 0x10; // Intermediate borrow.
+        }
+        let mut result = (accumulator & 0xF0) - (value as i16 & 0xF0) + least_significant;
+        if result < 0 {
+            result -= 0x60;
+        }
+
+        self.add_binary(value ^ 0xFF);
+        self.registers.accumulator = (result & 0xFF) as u8;
     }
 
     /// Add with carry. See readme for details.
@@ -45,19 +75,24 @@ impl<C: Memory> Emulator<C> {
         let addr = self.address(address_mode);
         let value = self.read(addr);
 
-        self.adc_value(value);
+        if self.registers.status.get(Flag::Decimal) {
+            self.add_decimal(value);
+        } else {
+            self.add_binary(value);
+        }
     }
 
     /// Subtract with carry. See readme for details.
     pub(crate) fn sbc(&mut self, address_mode: AddressMode) {
         let addr = self.address(address_mode);
-        let mut value = self.read(addr) ^ 0xFF;
+        let value = self.read(addr);
+
         if self.registers.status.get(Flag::Decimal) {
-            // Use nine's complement.
-            value -= 0x66;
+            self.subtract_decimal(value);
+        } else {
+            // Subtraction is addition of the twos' complement, the carry providing the 1.
+            self.add_binary(value ^ 0xFF);
         }
-
-        self.adc_value(value);
     }
 
     fn cmp_value(&mut self, address_mode: AddressMode, register_value: u8) {
@@ -168,7 +203,33 @@ mod test {
             0x99,
             0x09,
             0x08,
-            vec![Decimal, Carry],
+            // Negative is bit 7 of $a8, the sum before the most significant digit is corrected.
+            vec![Decimal, Carry, Negative],
+        );
+    }
+
+    #[test]
+    fn test_adc_decimal_zero() {
+        // Zero follows the binary sum, $9a.
+        test_arithmetic(
+            Emulator::adc,
+            vec![Decimal],
+            0x99,
+            0x01,
+            0x00,
+            vec![Decimal, Carry, Negative],
+        );
+    }
+
+    #[test]
+    fn test_adc_decimal_invalid() {
+        test_arithmetic(
+            Emulator::adc,
+            vec![Decimal],
+            0x0F,
+            0x0F,
+            0x14,
+            vec![Decimal],
         );
     }
 
@@ -221,10 +282,97 @@ mod test {
             0x91,
             0x23,
             0x68,
-            vec![Carry, Decimal],
+            // The flags of binary subtraction: -111 - 35 overflows.
+            vec![Carry, Decimal, Overflow],
         )
     }
 
+    /// ADC and SBC in decimal mode as described by Bruce Clark in "Decimal Mode", appendix A,
+    /// for the NMOS 6502. Returns the accumulator and the flags.
+    fn reference_decimal(subtract: bool, a: u8, b: u8, carry: bool) -> (u8, Vec<Flag>) {
+        let (a, b, c) = (a as i32, b as i32, carry as i32);
+        let mut flags = vec![Decimal];
+
+        if subtract {
+            let mut al = (a & 0x0F) - (b & 0x0F) + c - 1;
+            if al < 0 {
+                al = ((al - 0x06) & 0x0F) - 0x10;
+            }
+            let mut result = (a & 0xF0) - (b & 0xF0) + al;
+            if result < 0 {
+                result -= 0x60;
+            }
+
+            let binary = a - b + c - 1;
+            let signed = a as u8 as i8 as i32 - b as u8 as i8 as i32 + c - 1;
+            for (flag, set) in [
+                (Carry, binary >= 0),
+                (Zero, binary & 0xFF == 0),
+                (Negative, binary & 0x80 != 0),
+                (Overflow, !(-128..=127).contains(&signed)),
+            ]
+            .iter()
+            {
+                if *set {
+                    flags.push(*flag);
+                }
+            }
+
+            return ((result & 0xFF) as u8, flags);
+        }
+
+        let mut al = (a & 0x0F) + (b & 0x0F) + c;
+        if al >= 0x0A {
+            al = ((al + 0x06) & 0x0F) + 0x10;
+        }
+        let mut result = (a & 0xF0) + (b & 0xF0) + al;
+        if result >= 0xA0 {
+            result += 0x60;
+        }
+
+        let signed = (a & 0xF0) as u8 as i8 as i32 + (b & 0xF0) as u8 as i8 as i32 + al;
+        for (flag, set) in [
+            (Carry, result >= 0x100),
+            (Zero, (a + b + c) & 0xFF == 0),
+            (Negative, signed & 0x80 != 0),
+            (Overflow, !(-128..=127).contains(&signed)),
+        ]
+        .iter()
+        {
+            if *set {
+                flags.push(*flag);
+            }
+        }
+
+        ((result & 0xFF) as u8, flags)
+    }
+
+    #[test]
+    fn test_decimal_exhaustive() {
+        for &subtract in [false, true].iter() {
+            let instruction: AddressInstruction = if subtract {
+                Emulator::sbc
+            } else {
+                Emulator::adc
+            };
+
+            for a in 0..=0xFF {
+                for b in 0..=0xFF {
+                    for &carry in [false, true].iter() {
+                        let (expected, flags) = reference_decimal(subtract, a, b, carry);
+                        let setup_flags = if carry {
+                            vec![Decimal, Carry]
+                        } else {
+                            vec![Decimal]
+                        };
+
+                        test_arithmetic(instruction, setup_flags, a, b, expected, flags);
+                    }
+                }
+            }
+        }
+    }
+
     fn test_compare<R: Fn(&mut Registers)>(
         instruction: AddressInstruction,
         register_setup: R,
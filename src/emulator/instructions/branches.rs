@@ -1,9 +1,10 @@
 use crate::emulator::addressing::AddressMode;
+use crate::emulator::observer::Observer;
 use crate::emulator::registers::Flag;
 use crate::emulator::Emulator;
 use crate::memory::Memory;
 
-impl<C: Memory> Emulator<C> {
+impl<C: Memory, O: Observer> Emulator<C, O> {
     fn branch(&mut self, flag: Flag, branch_if: bool) {
         let displacement = self.address(AddressMode::Relative);
         if self.registers.status.get(flag) == branch_if {
@@ -1,9 +1,10 @@
 use crate::emulator::addressing::AddressMode;
 use crate::emulator::instructions::opcodes::*;
+use crate::emulator::observer::Observer;
 use crate::emulator::Emulator;
 use crate::memory::Memory;
 
-impl<C: Memory> Emulator<C> {
+impl<C: Memory, O: Observer> Emulator<C, O> {
     pub(crate) fn execute(&mut self, instruction: u8) {
         match instruction {
             ADC::IMMEDIATE => self.adc(AddressMode::Immediate),
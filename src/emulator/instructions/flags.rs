@@ -1,8 +1,9 @@
+use crate::emulator::observer::Observer;
 use crate::emulator::registers::Flag;
 use crate::emulator::Emulator;
 use crate::memory::Memory;
 
-impl<C: Memory> Emulator<C> {
+impl<C: Memory, O: Observer> Emulator<C, O> {
     /// Clear carry.
     pub(crate) fn clc(&mut self) {
         self.registers.status.clear(Flag::Carry);
@@ -1,8 +1,9 @@
 use crate::emulator::addressing::AddressMode;
+use crate::emulator::observer::Observer;
 use crate::emulator::Emulator;
 use crate::memory::Memory;
 
-impl<C: Memory> Emulator<C> {
+impl<C: Memory, O: Observer> Emulator<C, O> {
     fn inc_dec<F: Fn(u8, u8) -> u8>(&mut self, address_mode: AddressMode, apply: F) {
         let address = self.address(address_mode);
         let result = apply(self.read(address), 1);
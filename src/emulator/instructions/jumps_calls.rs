@@ -1,8 +1,9 @@
 use crate::emulator::addressing::AddressMode;
+use crate::emulator::observer::Observer;
 use crate::emulator::Emulator;
 use crate::memory::Memory;
 
-impl<C: Memory> Emulator<C> {
+impl<C: Memory, O: Observer> Emulator<C, O> {
     /// Jump.
     pub(crate) fn jmp(&mut self, address_mode: AddressMode) {
         let address = self.address(address_mode);
@@ -11,12 +12,12 @@ impl<C: Memory> Emulator<C> {
 
     pub(crate) fn jsr(&mut self, address_mode: AddressMode) {
         // See readme for explanation.
-        self.push_pc(self.registers.program_counter + 1);
+        self.push_pc(self.registers.program_counter.wrapping_add(1));
         self.jmp(address_mode);
     }
 
     pub(crate) fn rts(&mut self) {
-        self.registers.program_counter = self.pull_pc() + 1;
+        self.registers.program_counter = self.pull_pc().wrapping_add(1);
     }
 }
 
@@ -1,8 +1,9 @@
 use crate::emulator::addressing::AddressMode;
+use crate::emulator::observer::Observer;
 use crate::emulator::Emulator;
 use crate::memory::Memory;
 
-impl<C: Memory> Emulator<C> {
+impl<C: Memory, O: Observer> Emulator<C, O> {
     fn load(&mut self, address_mode: AddressMode) -> u8 {
         let address = self.address(address_mode);
         let value = self.read(address);
@@ -1,11 +1,12 @@
 use std::ops::{BitAndAssign, BitOrAssign, BitXorAssign};
 
 use crate::emulator::addressing::AddressMode;
+use crate::emulator::observer::Observer;
 use crate::emulator::registers::Flag;
 use crate::emulator::Emulator;
 use crate::memory::Memory;
 
-impl<C: Memory> Emulator<C> {
+impl<C: Memory, O: Observer> Emulator<C, O> {
     fn and_eor_ora<F: for<'r> Fn(&'r mut u8, u8)>(&mut self, address_mode: AddressMode, apply: F) {
         let address = self.address(address_mode);
         let r = self.read(address);
@@ -1,7 +1,8 @@
+use crate::emulator::observer::Observer;
 use crate::emulator::Emulator;
 use crate::memory::Memory;
 
-impl<C: Memory> Emulator<C> {
+impl<C: Memory, O: Observer> Emulator<C, O> {
     /// Transfer Accumulator to X.
     pub(crate) fn tax(&mut self) {
         self.registers.x = self.registers.accumulator;
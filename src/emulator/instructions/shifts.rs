@@ -1,9 +1,10 @@
 use crate::emulator::addressing::AddressMode;
+use crate::emulator::observer::Observer;
 use crate::emulator::registers::Flag;
 use crate::emulator::Emulator;
 use crate::memory::Memory;
 
-impl<C: Memory> Emulator<C> {
+impl<C: Memory, O: Observer> Emulator<C, O> {
     fn shift_accumulator<F: Fn(&mut Self, u8) -> u8>(&mut self, shift: F) {
         let value = self.registers.accumulator;
         let result = shift(self, value);
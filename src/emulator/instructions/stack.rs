@@ -1,8 +1,9 @@
+use crate::emulator::observer::Observer;
 use crate::emulator::registers::Flag;
 use crate::emulator::Emulator;
 use crate::memory::Memory;
 
-impl<C: Memory> Emulator<C> {
+impl<C: Memory, O: Observer> Emulator<C, O> {
     /// Transfer X to Stack Pointer.
     pub(crate) fn txs(&mut self) {
         self.registers.stack_pointer = self.registers.x;
@@ -16,6 +17,7 @@ impl<C: Memory> Emulator<C> {
 
     pub(crate) fn push(&mut self, value: u8) {
         let address = 0x100 + self.registers.stack_pointer as u16;
+        self.observer.push(address, value);
         self.write(address, value);
         self.registers.stack_pointer = self.registers.stack_pointer.wrapping_sub(1);
     }
@@ -24,7 +26,9 @@ impl<C: Memory> Emulator<C> {
         self.registers.stack_pointer = self.registers.stack_pointer.wrapping_add(1);
 
         let address = 0x100 + self.registers.stack_pointer as u16;
-        self.read(address)
+        let value = self.read(address);
+        self.observer.pull(address, value);
+        value
     }
 
     pub(crate) fn pha(&mut self) {
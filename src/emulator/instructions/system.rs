@@ -1,10 +1,11 @@
 use crate::emulator::bytes_little_endian;
+use crate::emulator::observer::{Interrupt, Observer};
 use crate::emulator::registers::Flag;
 use crate::emulator::Emulator;
 use crate::memory::Memory;
 use emulator::{INT_VECTOR_ADDR, NMI_VECTOR_ADDR};
 
-impl<C: Memory> Emulator<C> {
+impl<C: Memory, O: Observer> Emulator<C, O> {
     pub(crate) fn pull_pc(&mut self) -> u16 {
         let least_significant = self.pop() as u16;
         let most_significant = self.pop() as u16;
@@ -22,9 +23,16 @@ impl<C: Memory> Emulator<C> {
     /// No operation.
     pub(crate) fn nop(&self) {}
 
-    fn interrupt(&mut self, is_brk: bool, int_vector_addr_least: u16, int_vector_addr_most: u16) {
+    fn interrupt(&mut self, interrupt: Interrupt) {
+        let is_brk = interrupt == Interrupt::Brk;
+        let int_vector_addr_least = match interrupt {
+            Interrupt::Nmi => NMI_VECTOR_ADDR,
+            Interrupt::Brk | Interrupt::Irq => INT_VECTOR_ADDR,
+        };
+
         // BRK skips one instruction.
-        self.push_pc(self.registers.program_counter + is_brk as u16);
+        let return_address = self.registers.program_counter.wrapping_add(is_brk as u16);
+        self.push_pc(return_address);
 
         // BRK pushes the status registers with the BRK flag set, just like PHP.
         if is_brk {
@@ -35,23 +43,27 @@ impl<C: Memory> Emulator<C> {
 
         self.registers.status.set(Flag::Interrupt);
 
-        let interrupt_handler_addr = self.read_two(int_vector_addr_least, int_vector_addr_most);
+        let interrupt_handler_addr =
+            self.read_two(int_vector_addr_least, int_vector_addr_least + 1);
         self.registers.program_counter = interrupt_handler_addr;
+
+        self.observer
+            .interrupt(interrupt, return_address, interrupt_handler_addr);
     }
 
     /// Force interrupt.
     pub(crate) fn brk(&mut self) {
-        self.interrupt(true, INT_VECTOR_ADDR, INT_VECTOR_ADDR + 1);
+        self.interrupt(Interrupt::Brk);
     }
 
     /// Interrupt request.
     pub(crate) fn irq(&mut self) {
-        self.interrupt(false, INT_VECTOR_ADDR, INT_VECTOR_ADDR + 1);
+        self.interrupt(Interrupt::Irq);
     }
 
     /// Non-maskable interrupt.
     pub(crate) fn nmi(&mut self) {
-        self.interrupt(false, NMI_VECTOR_ADDR, NMI_VECTOR_ADDR + 1);
+        self.interrupt(Interrupt::Nmi);
         self.nmi = false;
     }
 
@@ -1,10 +1,15 @@
+use crate::emulator::addressing::AddressMode;
+use crate::emulator::observer::{Execution, NoObserver, Observer};
 use crate::emulator::registers::{Flag, Registers};
 use crate::memory::Memory;
 
-mod addressing;
+pub mod addressing;
+mod cycles;
 pub mod instructions;
+pub mod observer;
 pub mod read_write;
 pub mod registers;
+pub mod run;
 
 /// Address for the least significant byte of the NMI vector.
 pub(crate) const NMI_VECTOR_ADDR: u16 = 0xfffa;
@@ -15,27 +20,47 @@ pub(crate) const RESET_VECTOR_ADDR: u16 = 0xfffc;
 /// Address for the least significant byte of the IRQ and BRK vector.
 pub(crate) const INT_VECTOR_ADDR: u16 = 0xfffe;
 
-pub struct Emulator<C: Memory> {
+pub struct Emulator<C: Memory, O: Observer = NoObserver> {
     pub registers: Registers,
     pub memory: C,
+    pub observer: O,
     pub irq: bool,
     pub nmi: bool,
+    /// Clock cycles taken since the emulator was created, including page crossings and taken
+    /// branches.
+    pub cycles: u64,
+    pub(crate) trapped: bool,
+    /// The addressing mode and effective address of the instruction being executed, for the
+    /// observer.
+    operand: Option<(AddressMode, u16)>,
 }
 
 impl<C: Memory> Emulator<C> {
     pub fn new(memory: C) -> Emulator<C> {
+        Emulator::with_observer(memory, NoObserver)
+    }
+}
+
+impl<C: Memory, O: Observer> Emulator<C, O> {
+    /// Create an emulator that reports what it does to `observer`.
+    pub fn with_observer(memory: C, observer: O) -> Emulator<C, O> {
         let mut emulator = Emulator {
             registers: Registers::new(),
             memory,
+            observer,
             irq: false,
             nmi: false,
+            cycles: 0,
+            trapped: false,
+            operand: None,
         };
 
         emulator.reset();
         emulator
     }
 
-    fn reset(&mut self) {
+    /// Reset the processor, as the RESET line does. Memory and devices are left alone.
+    pub fn reset(&mut self) {
         self.nmi = false;
         self.irq = false;
         self.registers.status.set(Flag::Interrupt);
@@ -45,17 +70,74 @@ impl<C: Memory> Emulator<C> {
     }
 
     pub fn execute_next(&mut self) {
-        // Hardware interrupts. See readme for details.
+        let cycles = self.cycles;
+        self.service_interrupt();
+
+        let address = self.registers.program_counter;
+        let instruction = self.read(address);
+        if O::ENABLED {
+            self.operand = None;
+            self.observer
+                .before_instruction(address, instruction, &self.registers);
+        }
+        self.registers.program_counter = address.wrapping_add(1);
+
+        self.cycles += self.instruction_cycles(instruction, address);
+        self.execute(instruction);
+        self.cycles += self.branch_cycles(instruction, address);
+
+        if O::ENABLED {
+            let execution = Execution {
+                address,
+                opcode: instruction,
+                mode: self.operand.map(|(mode, _)| mode),
+                effective_address: self.operand.map(|(_, address)| address),
+                cycles: self.cycles - cycles,
+            };
+            self.observer.after_instruction(&execution, &self.registers);
+        }
+
+        // An instruction that only ever leads back to itself.
+        self.trapped = run::is_self_loop(instruction) && self.registers.program_counter == address;
+    }
+
+    /// Take a pending hardware interrupt, without executing the first instruction of its handler
+    /// yet. Returns whether there was one. See readme for details.
+    pub fn service_interrupt(&mut self) -> bool {
+        if !self.is_interrupt_pending() {
+            return false;
+        }
+
         if self.nmi {
             self.nmi()
-        } else if self.irq && !self.registers.status.get(Flag::Interrupt) {
+        } else {
             self.irq()
         }
+        self.cycles += cycles::INTERRUPT_CYCLES;
+        true
+    }
 
-        let instruction = self.read(self.registers.program_counter);
-        self.registers.program_counter += 1;
+    /// Whether a hardware interrupt will be serviced before the next instruction.
+    pub fn is_interrupt_pending(&self) -> bool {
+        self.nmi || (self.irq && !self.registers.status.get(Flag::Interrupt))
+    }
 
-        self.execute(instruction);
+    /// The address of the instruction that `execute_next` will execute, which is the start of an
+    /// interrupt handler when an interrupt is pending.
+    pub fn next_instruction_address(&self) -> u16 {
+        let vector = match (self.is_interrupt_pending(), self.nmi) {
+            (false, _) => return self.registers.program_counter,
+            (true, true) => NMI_VECTOR_ADDR,
+            (true, false) => INT_VECTOR_ADDR,
+        };
+
+        ((self.memory.peek(vector + 1) as u16) << 8) | self.memory.peek(vector) as u16
+    }
+
+    /// Whether the last instruction jumped or branched to itself, and no interrupt is pending
+    /// to take execution elsewhere. See readme for details.
+    pub fn is_trapped(&self) -> bool {
+        self.trapped && !self.is_interrupt_pending()
     }
 }
 
@@ -66,8 +148,9 @@ pub(crate) fn bytes_little_endian(value: u16) -> (u8, u8) {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use crate::emulator::addressing::AddressMode;
+    use crate::emulator::observer::NoObserver;
     use crate::emulator::registers::Flag::*;
     use crate::emulator::registers::{Flag, Registers};
     use crate::emulator::Emulator;
@@ -129,8 +212,12 @@ mod tests {
         let mut c = Emulator {
             registers: r,
             memory: DefaultMemory::empty(),
+            observer: NoObserver,
             irq: false,
             nmi: false,
+            cycles: 0,
+            trapped: false,
+            operand: None,
         };
 
         c.set_flags(flags);
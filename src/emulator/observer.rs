@@ -0,0 +1,256 @@
+use crate::emulator::addressing::AddressMode;
+use crate::emulator::registers::Registers;
+
+/// Kinds of interrupt, including the BRK instruction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interrupt {
+    Brk,
+    Irq,
+    Nmi,
+}
+
+/// An instruction that has just been executed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Execution {
+    /// Address of the opcode.
+    pub address: u16,
+    pub opcode: u8,
+    /// The addressing mode, or `None` for implied and accumulator instructions.
+    pub mode: Option<AddressMode>,
+    /// The address the instruction operated on. For immediate instructions this is the address
+    /// of the operand, and for branches it is the target, whether taken or not.
+    pub effective_address: Option<u16>,
+    /// Cycles taken, including an interrupt serviced before it.
+    pub cycles: u64,
+}
+
+/// Callbacks for what happens inside the emulator. All of them do nothing by default, so an
+/// observer only needs to implement what it is interested in.
+///
+/// The observer is a type parameter of `Emulator`, so the calls are resolved at compile time.
+/// `NoObserver`, the default, compiles to nothing at all.
+pub trait Observer {
+    /// Whether the emulator keeps track of the information handed to the observer. Only an
+    /// observer that ignores every callback should set this to `false`.
+    const ENABLED: bool = true;
+
+    /// Before the instruction at `address` is executed.
+    fn before_instruction(&mut self, _address: u16, _opcode: u8, _registers: &Registers) {}
+
+    /// After an instruction is executed.
+    fn after_instruction(&mut self, _execution: &Execution, _registers: &Registers) {}
+
+    /// Every read by the processor, including fetching instructions.
+    fn read(&mut self, _address: u16, _value: u8) {}
+
+    /// Every write by the processor.
+    fn write(&mut self, _address: u16, _value: u8) {}
+
+    /// An interrupt has been taken. `return_address` is the address pushed onto the stack, and
+    /// `handler` the address read from the vector.
+    fn interrupt(&mut self, _interrupt: Interrupt, _return_address: u16, _handler: u16) {}
+
+    /// A byte is pushed onto the stack at `address`.
+    fn push(&mut self, _address: u16, _value: u8) {}
+
+    /// A byte is pulled from the stack at `address`.
+    fn pull(&mut self, _address: u16, _value: u8) {}
+}
+
+/// The observer that observes nothing.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoObserver;
+
+impl Observer for NoObserver {
+    const ENABLED: bool = false;
+}
+
+/// Two observers can be combined by pairing them up. Each callback goes to the first, then the
+/// second.
+impl<A: Observer, B: Observer> Observer for (A, B) {
+    const ENABLED: bool = A::ENABLED || B::ENABLED;
+
+    fn before_instruction(&mut self, address: u16, opcode: u8, registers: &Registers) {
+        self.0.before_instruction(address, opcode, registers);
+        self.1.before_instruction(address, opcode, registers);
+    }
+
+    fn after_instruction(&mut self, execution: &Execution, registers: &Registers) {
+        self.0.after_instruction(execution, registers);
+        self.1.after_instruction(execution, registers);
+    }
+
+    fn read(&mut self, address: u16, value: u8) {
+        self.0.read(address, value);
+        self.1.read(address, value);
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.0.write(address, value);
+        self.1.write(address, value);
+    }
+
+    fn interrupt(&mut self, interrupt: Interrupt, return_address: u16, handler: u16) {
+        self.0.interrupt(interrupt, return_address, handler);
+        self.1.interrupt(interrupt, return_address, handler);
+    }
+
+    fn push(&mut self, address: u16, value: u8) {
+        self.0.push(address, value);
+        self.1.push(address, value);
+    }
+
+    fn pull(&mut self, address: u16, value: u8) {
+        self.0.pull(address, value);
+        self.1.pull(address, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::addressing::AddressMode;
+    use crate::emulator::instructions::opcodes::*;
+    use crate::emulator::observer::{Execution, Interrupt, Observer};
+    use crate::emulator::registers::Registers;
+    use crate::emulator::Emulator;
+    use crate::memory::default::DefaultMemory;
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<String>,
+        executions: Vec<Execution>,
+    }
+
+    impl Observer for Recorder {
+        fn before_instruction(&mut self, address: u16, opcode: u8, _registers: &Registers) {
+            self.events
+                .push(format!("before {:04x} {:02x}", address, opcode));
+        }
+
+        fn after_instruction(&mut self, execution: &Execution, _registers: &Registers) {
+            self.executions.push(*execution);
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.events
+                .push(format!("write {:04x} {:02x}", address, value));
+        }
+
+        fn interrupt(&mut self, interrupt: Interrupt, return_address: u16, handler: u16) {
+            self.events.push(format!(
+                "{:?} {:04x} {:04x}",
+                interrupt, return_address, handler
+            ));
+        }
+
+        fn push(&mut self, address: u16, value: u8) {
+            self.events
+                .push(format!("push {:04x} {:02x}", address, value));
+        }
+
+        fn pull(&mut self, address: u16, value: u8) {
+            self.events
+                .push(format!("pull {:04x} {:02x}", address, value));
+        }
+    }
+
+    fn emulator(program: Vec<Vec<u8>>) -> Emulator<DefaultMemory, Recorder> {
+        let mut memory = DefaultMemory::empty();
+        memory.load(program.concat(), 0x600);
+        memory.set_program_counter(0x600);
+
+        Emulator::with_observer(memory, Recorder::default())
+    }
+
+    #[test]
+    fn test_instructions() {
+        let mut e = emulator(vec![
+            LDX::immediate(2),
+            STA::absolute_x(0x200),
+            INX::implied(),
+        ]);
+        for _ in 0..3 {
+            e.execute_next();
+        }
+
+        let executions = &e.observer.executions;
+        assert_eq!(
+            Execution {
+                address: 0x600,
+                opcode: LDX::IMMEDIATE,
+                mode: Some(AddressMode::Immediate),
+                effective_address: Some(0x601),
+                cycles: 2,
+            },
+            executions[0]
+        );
+        assert_eq!(Some(AddressMode::AbsoluteX), executions[1].mode);
+        assert_eq!(Some(0x202), executions[1].effective_address);
+        assert_eq!(None, executions[2].mode);
+        assert_eq!(
+            vec![
+                "before 0600 a2",
+                "before 0602 9d",
+                "write 0202 00",
+                "before 0605 e8"
+            ],
+            e.observer.events
+        );
+    }
+
+    #[test]
+    fn test_branch_target() {
+        let mut e = emulator(vec![BNE::relative(-2)]);
+        e.registers
+            .status
+            .set(crate::emulator::registers::Flag::Zero);
+        e.execute_next();
+
+        assert_eq!(Some(0x600), e.observer.executions[0].effective_address);
+    }
+
+    #[test]
+    fn test_stack_and_interrupts() {
+        let mut e = emulator(vec![PHA::implied(), PLA::implied()]);
+        e.memory.load(vec![0x00, 0x07], 0xfffa);
+        e.memory.load(NOP::implied(), 0x700);
+        e.registers.accumulator = 0x42;
+
+        e.execute_next();
+        e.execute_next();
+        e.nmi = true;
+        e.execute_next();
+
+        assert_eq!(
+            vec![
+                "before 0600 48",
+                "push 01ff 42",
+                "write 01ff 42",
+                "before 0601 68",
+                "pull 01ff 42",
+                "push 01ff 06",
+                "write 01ff 06",
+                "push 01fe 02",
+                "write 01fe 02",
+                "push 01fd 24",
+                "write 01fd 24",
+                "Nmi 0602 0700",
+                "before 0700 ea",
+            ],
+            e.observer.events
+        );
+    }
+
+    #[test]
+    fn test_pair() {
+        let mut e = Emulator::with_observer(
+            DefaultMemory::empty(),
+            (Recorder::default(), Recorder::default()),
+        );
+        e.memory.load(INX::implied(), 0);
+        e.execute_next();
+
+        assert_eq!(1, e.observer.0.executions.len());
+        assert_eq!(1, e.observer.1.executions.len());
+    }
+}
@@ -1,7 +1,8 @@
+use crate::emulator::observer::Observer;
 use crate::emulator::Emulator;
 use crate::memory::Memory;
 
-impl<C: Memory> Emulator<C> {
+impl<C: Memory, O: Observer> Emulator<C, O> {
     pub(crate) fn read_two(
         &mut self,
         address_least_significant: u16,
@@ -12,10 +13,13 @@ impl<C: Memory> Emulator<C> {
     }
 
     pub(crate) fn read(&mut self, address: u16) -> u8 {
-        self.memory.read(address)
+        let value = self.memory.read(address);
+        self.observer.read(address, value);
+        value
     }
 
     pub(crate) fn write(&mut self, address: u16, value: u8) {
+        self.observer.write(address, value);
         self.memory.write(address, value)
     }
 }
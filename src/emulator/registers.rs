@@ -1,3 +1,5 @@
+use std::fmt;
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Flag {
     Carry = 0,
@@ -10,6 +12,18 @@ pub enum Flag {
     Negative,
 }
 
+/// Flags in the order in which they appear in the status register, most significant first.
+pub const FLAGS: [Flag; 8] = [
+    Flag::Negative,
+    Flag::Overflow,
+    Flag::Reserved,
+    Flag::Break,
+    Flag::Decimal,
+    Flag::Interrupt,
+    Flag::Zero,
+    Flag::Carry,
+];
+
 #[derive(Debug, Clone)]
 pub struct Status {
     pub(crate) flags: u8,
@@ -28,7 +42,7 @@ impl Status {
         self.flags = contents | Self::DEFAULT_STATUS
     }
 
-    pub(crate) fn set_to(&mut self, flag: Flag, v: bool) {
+    pub fn set_to(&mut self, flag: Flag, v: bool) {
         if v {
             self.set(flag)
         } else {
@@ -104,9 +118,26 @@ impl Registers {
     }
 }
 
+/// A heading with the names of the registers and flags, and a line with their values below it.
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flags: String = FLAGS
+            .iter()
+            .map(|flag| if self.status.get(*flag) { '1' } else { '0' })
+            .collect();
+
+        writeln!(f, "  pc  a  x  y  sp nv-bdizc")?;
+        write!(
+            f,
+            "{:04x}  {:02x} {:02x} {:02x} {:02x} {}",
+            self.program_counter, self.accumulator, self.x, self.y, self.stack_pointer, flags
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::emulator::registers::{Flag, Status};
+    use crate::emulator::registers::{Flag, Registers, Status};
 
     #[test]
     fn test_reserved_always_on() {
@@ -138,6 +169,19 @@ mod tests {
         test(Flag::Negative);
     }
 
+    #[test]
+    fn test_display() {
+        let mut registers = Registers::new();
+        registers.program_counter = 0x0604;
+        registers.accumulator = 0x42;
+        registers.status.set(Flag::Carry);
+
+        assert_eq!(
+            "  pc  a  x  y  sp nv-bdizc\n0604  42 00 00 ff 00100001",
+            registers.to_string()
+        );
+    }
+
     #[test]
     fn test_clear_retains_others() {
         let mut status = Status::new();
@@ -0,0 +1,152 @@
+use crate::emulator::instructions::opcodes::*;
+use crate::emulator::observer::Observer;
+use crate::emulator::Emulator;
+use crate::memory::Memory;
+
+/// Reason for `Emulator::run` to hand control back to the caller.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+    /// The instruction at this address jumps or branches to itself, and nothing can interrupt it.
+    Trap(u16),
+    /// The maximum number of instructions has been executed.
+    InstructionLimit,
+    /// The cycle to run until has been reached.
+    CycleLimit,
+}
+
+/// Whether the instruction is a jump or branch, which lands on itself without touching any other
+/// state when its target is its own address.
+pub(crate) fn is_self_loop(instruction: u8) -> bool {
+    matches!(
+        instruction,
+        JMP::ABSOLUTE
+            | JMP::INDIRECT
+            | BCC::RELATIVE
+            | BCS::RELATIVE
+            | BEQ::RELATIVE
+            | BMI::RELATIVE
+            | BNE::RELATIVE
+            | BPL::RELATIVE
+            | BVC::RELATIVE
+            | BVS::RELATIVE
+    )
+}
+
+impl<C: Memory, O: Observer> Emulator<C, O> {
+    /// Execute instructions until a trap is detected or `max_instructions` have been executed.
+    pub fn run(&mut self, max_instructions: u64) -> StopReason {
+        for _ in 0..max_instructions {
+            self.execute_next();
+
+            if self.is_trapped() {
+                return StopReason::Trap(self.registers.program_counter);
+            }
+        }
+
+        StopReason::InstructionLimit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::instructions::opcodes::*;
+    use crate::emulator::registers::Flag;
+    use crate::emulator::registers::Flag::{Interrupt, Zero};
+    use crate::emulator::run::StopReason;
+    use crate::emulator::tests::setup;
+    use crate::emulator::Emulator;
+    use crate::memory::default::DefaultMemory;
+
+    fn setup_program(flags: Vec<Flag>, program: Vec<Vec<u8>>) -> Emulator<DefaultMemory> {
+        let mut e = setup(flags);
+        let start = e.registers.program_counter as usize;
+        e.memory
+            .load(program.into_iter().flatten().collect(), start);
+        e
+    }
+
+    #[test]
+    fn test_jmp_to_self() {
+        let mut e = setup_program(vec![Interrupt], vec![NOP::implied(), JMP::absolute(0x601)]);
+
+        assert_eq!(StopReason::Trap(0x601), e.run(10));
+    }
+
+    #[test]
+    fn test_jmp_indirect_to_self() {
+        let mut e = setup_program(vec![Interrupt], vec![JMP::indirect(0x700)]);
+        e.memory.memory[0x700] = 0x00;
+        e.memory.memory[0x701] = 0x06;
+
+        assert_eq!(StopReason::Trap(0x600), e.run(10));
+    }
+
+    #[test]
+    fn test_branch_to_self() {
+        let mut e = setup_program(vec![Interrupt], vec![LDA::immediate(0), BEQ::relative(-2)]);
+
+        assert_eq!(StopReason::Trap(0x602), e.run(10));
+    }
+
+    #[test]
+    fn test_branch_not_taken() {
+        let mut e = setup_program(vec![Zero], vec![BNE::relative(-2), NOP::implied()]);
+
+        e.execute_next();
+        assert!(!e.is_trapped());
+    }
+
+    #[test]
+    fn test_loop_is_not_trap() {
+        // Decrements X until it reaches zero, then jumps to itself.
+        let mut e = setup_program(
+            vec![Interrupt],
+            vec![
+                LDX::immediate(3),
+                DEX::implied(),
+                BNE::relative(-3),
+                JMP::absolute(0x605),
+            ],
+        );
+
+        assert_eq!(StopReason::Trap(0x605), e.run(100));
+    }
+
+    #[test]
+    fn test_trap_in_interrupt_handler() {
+        let mut e = setup_program(vec![], vec![JMP::absolute(0x600)]);
+        e.irq = true;
+        e.memory.memory[0xfffe] = 0x00;
+        e.memory.memory[0xffff] = 0x07;
+        e.memory.load(JMP::absolute(0x700), 0x700);
+
+        // The IRQ is serviced first, after which the handler traps with interrupts disabled.
+        e.execute_next();
+        assert_eq!(0x700, e.registers.program_counter);
+        assert!(e.is_trapped());
+    }
+
+    #[test]
+    fn test_held_irq_is_not_trap() {
+        let mut e = setup_program(vec![], vec![JMP::absolute(0x600)]);
+
+        e.execute_next();
+        assert!(e.is_trapped());
+
+        // An IRQ with interrupts enabled will take execution out of the loop.
+        e.irq = true;
+        assert!(!e.is_trapped());
+
+        // But not with interrupts disabled.
+        e.registers.status.set(Interrupt);
+        assert!(e.is_trapped());
+    }
+
+    #[test]
+    fn test_instruction_limit() {
+        let mut e = setup_program(vec![], vec![NOP::implied(), NOP::implied()]);
+
+        assert_eq!(StopReason::InstructionLimit, e.run(2));
+        assert_eq!(0x602, e.registers.program_counter);
+    }
+}
@@ -0,0 +1,305 @@
+extern crate crossterm;
+extern crate emulator;
+
+use std::collections::VecDeque;
+use std::env;
+use std::io::{stdout, Stdout, Write};
+use std::process;
+
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{KeyCode, KeyModifiers},
+    execute, queue,
+    style::Print,
+    terminal::{Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+
+use cli::{fail, parse_address, read_file};
+use emulator::machines::kim1::{Config, Key, Kim1, CLOCK, DEFAULT_BAUD, DIGITS};
+use terminal::{Input, Terminal};
+
+#[path = "../cli/mod.rs"]
+mod cli;
+#[path = "../cli/terminal.rs"]
+mod terminal;
+
+const USAGE: &str = "\
+Usage: kim1 [options] monitor
+
+Options:
+  --tty                 Fit the TTY jumper, and talk to the monitor in the terminal.
+  --baud rate           Bits per second of the teletype, 1200 by default.
+  --load address:file   Load a file into RAM. Can be repeated.
+  --cycles count        Run this many cycles without a terminal, then print the display.
+
+The monitor file holds the 2K of ROM at 1800. Addresses are hexadecimal.
+
+On the keypad, 0-9 and a-f are the hex keys, Ctrl-A is AD, Ctrl-D is DA, + is +, Ctrl-G is GO
+and Ctrl-P is PC. Esc presses ST and Ctrl-T flips the SST switch. In both modes Ctrl-R presses
+RS, and Ctrl-C quits.";
+
+/// Instructions to execute between looking at the keyboard and the clock.
+const BATCH: usize = 1000;
+
+/// How long a key stays pressed, as the terminal does not tell when it is let go.
+const PRESS_CYCLES: u64 = CLOCK as u64 / 10;
+
+/// The character that the monitor measures the speed of the teletype with after a reset.
+const RUB_OUT: u8 = 0x7f;
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    monitor: String,
+    tty: bool,
+    baud: u32,
+    load: Vec<(u16, String)>,
+    cycles: Option<u64>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        monitor: String::new(),
+        tty: false,
+        baud: DEFAULT_BAUD,
+        load: Vec::new(),
+        cycles: None,
+    };
+    let mut monitor = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}.", arg))
+        };
+
+        match arg.as_str() {
+            "--tty" => options.tty = true,
+            "--baud" => {
+                let baud = value()?;
+                options.baud = baud
+                    .parse()
+                    .map_err(|_| format!("Invalid baud rate '{}'.", baud))?;
+            }
+            "--load" => {
+                let load = value()?;
+                let (address, file) = load
+                    .split_once(':')
+                    .ok_or_else(|| format!("Invalid load '{}'.", load))?;
+                options
+                    .load
+                    .push((parse_address(address)?, file.to_string()));
+            }
+            "--cycles" => {
+                let cycles = value()?;
+                options.cycles = Some(
+                    cycles
+                        .parse()
+                        .map_err(|_| format!("Invalid number of cycles '{}'.", cycles))?,
+                );
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option '{}'.", arg)),
+            _ if monitor.is_none() => monitor = Some(arg.clone()),
+            _ => return Err(format!("Unexpected argument '{}'.", arg)),
+        }
+    }
+
+    options.monitor = monitor.ok_or_else(|| "No monitor ROM given.".to_string())?;
+    Ok(options)
+}
+
+enum Action {
+    Key(Key),
+    Character(u8),
+    Stop,
+    SingleStep,
+}
+
+/// What a key on the keyboard of the host does, with the keypad or with the teletype.
+fn action(code: KeyCode, modifiers: KeyModifiers, tty: bool) -> Option<Action> {
+    let control = modifiers.contains(KeyModifiers::CONTROL);
+    let action = match code {
+        KeyCode::Esc => Action::Stop,
+        KeyCode::Char('t') if control => Action::SingleStep,
+        KeyCode::Char('a') if control => Action::Key(Key::Address),
+        KeyCode::Char('d') if control => Action::Key(Key::Data),
+        KeyCode::Char('g') if control => Action::Key(Key::Go),
+        KeyCode::Char('p') if control => Action::Key(Key::Pc),
+        KeyCode::Char(c) if tty && c.is_ascii() => Action::Character(c.to_ascii_uppercase() as u8),
+        KeyCode::Enter if tty => Action::Character(b'\r'),
+        KeyCode::Backspace if tty => Action::Character(RUB_OUT),
+        KeyCode::Char('+') => Action::Key(Key::Plus),
+        KeyCode::Char(c) if !tty => Action::Key(Key::Digit(c.to_digit(16)? as u8)),
+        _ => return None,
+    };
+    Some(action)
+}
+
+/// Stdout as the printer of the teletype, which does not print the fill characters.
+struct Printer(Stdout);
+
+impl Write for Printer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for byte in buf {
+            if *byte != 0x00 && *byte != RUB_OUT {
+                self.0.write_all(&[*byte])?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// The three lines of text that draw the seven segment digits, with a gap between the address
+/// and the data.
+fn render(display: &[u8; DIGITS]) -> [String; 3] {
+    let mut lines = [String::new(), String::new(), String::new()];
+    for (digit, segments) in display.iter().enumerate() {
+        let on = |segment: u8, c: char| if segments & segment != 0 { c } else { ' ' };
+        if digit == 4 {
+            for line in lines.iter_mut() {
+                line.push_str("  ");
+            }
+        }
+
+        lines[0].extend([' ', on(0x01, '_'), ' ', ' ']);
+        lines[1].extend([on(0x20, '|'), on(0x40, '_'), on(0x02, '|'), ' ']);
+        lines[2].extend([on(0x10, '|'), on(0x08, '_'), on(0x04, '|'), ' ']);
+    }
+    lines
+}
+
+fn draw<W: Write>(out: &mut W, kim1: &Kim1<VecDeque<u8>, Printer>) {
+    for (i, line) in render(&kim1.display()).iter().enumerate() {
+        queue!(out, MoveTo(0, i as u16), Print(line)).unwrap();
+    }
+    let sst = if kim1.single_step {
+        "SST on "
+    } else {
+        "SST off"
+    };
+    queue!(out, MoveTo(0, 4), Print(sst)).unwrap();
+    out.flush().unwrap();
+}
+
+fn run_headless(mut kim1: Kim1<VecDeque<u8>, Printer>, cycles: u64, tty: bool) {
+    while kim1.emulator.cycles < cycles && !kim1.emulator.is_trapped() {
+        kim1.step();
+    }
+    if !tty {
+        println!("{}", kim1.digits());
+    }
+}
+
+fn run_terminal(mut kim1: Kim1<VecDeque<u8>, Printer>, tty: bool) {
+    let terminal = Terminal::start(CLOCK, move |code, modifiers| action(code, modifiers, tty));
+    let mut out = stdout();
+    if !tty {
+        execute!(out, EnterAlternateScreen, Clear(ClearType::All), Hide).unwrap();
+    }
+
+    let mut release = 0;
+    'run: loop {
+        while let Some(input) = terminal.poll() {
+            match input {
+                Input::Key(Action::Key(key)) => {
+                    kim1.set_key(Some(key));
+                    release = kim1.emulator.cycles + PRESS_CYCLES;
+                }
+                Input::Key(Action::Character(c)) => kim1.tty.input().push_back(c),
+                Input::Key(Action::Stop) => kim1.stop(),
+                Input::Key(Action::SingleStep) => kim1.single_step = !kim1.single_step,
+                Input::Reset => kim1.reset(),
+                Input::Quit => break 'run,
+            }
+        }
+
+        for _ in 0..BATCH {
+            kim1.step();
+        }
+        if release != 0 && kim1.emulator.cycles >= release {
+            kim1.set_key(None);
+            release = 0;
+        }
+        if !tty {
+            draw(&mut out, &kim1);
+        }
+
+        terminal.wait(kim1.emulator.cycles);
+    }
+
+    if !tty {
+        execute!(out, Show, LeaveAlternateScreen).unwrap();
+    }
+    drop(terminal);
+    println!();
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = parse_options(&args).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(1)
+    });
+
+    let config = Config {
+        monitor: read_file(&options.monitor),
+        tty: options.tty,
+        baud: options.baud,
+    };
+    let mut kim1 =
+        Kim1::new(config, VecDeque::new(), Printer(stdout())).unwrap_or_else(|e| fail(e));
+    for (address, file) in options.load.iter() {
+        kim1.load(&read_file(file), *address)
+            .unwrap_or_else(|e| fail(e));
+    }
+    if options.tty {
+        kim1.tty.input().push_back(RUB_OUT);
+    }
+
+    match options.cycles {
+        Some(cycles) => run_headless(kim1, cycles, options.tty),
+        None => run_terminal(kim1, options.tty),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {parse_options, render, Options};
+
+    fn options(args: &str) -> Result<Options, String> {
+        let args: Vec<String> = args.split_whitespace().map(|s| s.to_string()).collect();
+        parse_options(&args)
+    }
+
+    #[test]
+    fn test_options() {
+        let o = options("--tty --baud 300 --load 200:game.bin --cycles 1000 kim.bin").unwrap();
+
+        assert_eq!("kim.bin", o.monitor);
+        assert!(o.tty);
+        assert_eq!(300, o.baud);
+        assert_eq!(vec![(0x200, "game.bin".to_string())], o.load);
+        assert_eq!(Some(1000), o.cycles);
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        assert!(options("").is_err());
+        assert!(options("--baud fast kim.bin").is_err());
+        assert!(options("--load 200 kim.bin").is_err());
+        assert!(options("kim.bin other.bin").is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let lines = render(&[0x3f, 0x06, 0x00, 0x00, 0x00, 0x7f]);
+
+        assert_eq!(" _                     _  ", lines[0]);
+        assert_eq!("| |   |               |_| ", lines[1]);
+        assert_eq!("|_|   |               |_| ", lines[2]);
+    }
+}
@@ -1,2 +1,7 @@
+extern crate rand;
+
+pub mod debug;
+pub mod devices;
 pub mod emulator;
+pub mod machines;
 pub mod memory;
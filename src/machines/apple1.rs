@@ -0,0 +1,322 @@
+//! The Apple-1.
+//!
+//! | Address | Purpose |
+//! |---------|---------|
+//! | $0000-$0FFF | 4K of RAM |
+//! | $C000-$C1FF | The cassette interface and its ROM, when there is one |
+//! | $D010-$D013 | The PIA with the keyboard and the display |
+//! | $E000-$EFFF | 4K more RAM on 8K machines, where Apple-1 BASIC goes |
+//! | $FF00-$FFFF | WozMon, or another 256 byte monitor |
+//!
+//! Nothing else is connected: reading it gives 0 and writing it does nothing.
+
+use std::cell::RefCell;
+use std::io::{Read, Write};
+use std::rc::Rc;
+
+use crate::devices::apple1::{Cassette, Terminal, ADDRESS, CASSETTE_ADDRESS};
+use crate::devices::bus;
+use crate::devices::bus::{Bus, Line};
+use crate::emulator::Emulator;
+use crate::memory::Memory;
+
+/// The clock of the processor, in cycles per second.
+pub const CLOCK: u32 = 1_022_727;
+
+/// Where the monitor ROM starts.
+pub const WOZMON_ADDRESS: u16 = 0xff00;
+
+/// Characters per second the display of the real machine shows.
+pub const DISPLAY_RATE: u32 = 60;
+
+const RAM_SIZE: usize = 0x1000;
+const UPPER_RAM_ADDRESS: u16 = 0xe000;
+
+/// How much RAM the machine has.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ram {
+    FourK,
+    EightK,
+}
+
+pub struct Config {
+    pub ram: Ram,
+    /// The 256 bytes at $FF00, usually WozMon.
+    pub wozmon: Vec<u8>,
+    /// The 256 bytes of the ROM of the cassette interface, to add the interface.
+    pub cassette: Option<Vec<u8>>,
+    /// Whether the display shows 60 characters per second like the real one, rather than as
+    /// many as the program writes.
+    pub slow_display: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            ram: Ram::EightK,
+            wozmon: Vec::new(),
+            cassette: None,
+            slow_display: false,
+        }
+    }
+}
+
+/// The RAM and the monitor ROM.
+pub struct Apple1Memory {
+    ram: Vec<u8>,
+    wozmon: [u8; 256],
+}
+
+impl Apple1Memory {
+    fn index(&self, address: u16) -> Option<usize> {
+        match address {
+            0x0000..=0x0fff => Some(address as usize),
+            0xe000..=0xefff if self.ram.len() > RAM_SIZE => {
+                Some(RAM_SIZE + (address - UPPER_RAM_ADDRESS) as usize)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Memory for Apple1Memory {
+    fn read(&self, address: u16) -> u8 {
+        if address >= WOZMON_ADDRESS {
+            return self.wozmon[(address - WOZMON_ADDRESS) as usize];
+        }
+        self.index(address).map_or(0, |i| self.ram[i])
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if let Some(i) = self.index(address) {
+            self.ram[i] = value;
+        }
+    }
+}
+
+/// A 256 byte ROM image, or an error naming it.
+fn rom(image: &[u8], name: &str) -> Result<[u8; 256], String> {
+    let mut rom = [0; 256];
+    if image.len() != rom.len() {
+        return Err(format!(
+            "The {} ROM is {} bytes rather than 256.",
+            name,
+            image.len()
+        ));
+    }
+    rom.copy_from_slice(image);
+    Ok(rom)
+}
+
+pub struct Apple1<R: Read, W: Write> {
+    pub emulator: Emulator<Bus<Apple1Memory>>,
+    pub terminal: Rc<RefCell<Terminal<R, W>>>,
+    pub cassette: Option<Rc<RefCell<Cassette>>>,
+}
+
+impl<R: Read + 'static, W: Write + 'static> Apple1<R, W> {
+    /// An Apple-1 with `input` as its keyboard and `output` as its display, starting at the
+    /// reset vector of the monitor.
+    pub fn new(config: Config, input: R, output: W) -> Result<Apple1<R, W>, String> {
+        let ram_size = match config.ram {
+            Ram::FourK => RAM_SIZE,
+            Ram::EightK => 2 * RAM_SIZE,
+        };
+        let memory = Apple1Memory {
+            ram: vec![0; ram_size],
+            wozmon: rom(&config.wozmon, "monitor")?,
+        };
+        let mut bus = Bus::new(memory);
+
+        let display_cycles = if config.slow_display {
+            (CLOCK / DISPLAY_RATE) as u64
+        } else {
+            0
+        };
+        let terminal = Rc::new(RefCell::new(Terminal::with_display_cycles(
+            input,
+            output,
+            display_cycles,
+        )));
+        // IRQA and IRQB are not connected, while WozMon enables the interrupts of both ports.
+        bus.attach("terminal", ADDRESS, 4, terminal.clone(), Line::Unconnected);
+
+        let cassette = match config.cassette {
+            Some(image) => {
+                let cassette = Rc::new(RefCell::new(Cassette::new(rom(&image, "cassette")?)));
+                bus.attach(
+                    "cassette",
+                    CASSETTE_ADDRESS,
+                    0x200,
+                    cassette.clone(),
+                    Line::Irq,
+                );
+                Some(cassette)
+            }
+            None => None,
+        };
+
+        Ok(Apple1 {
+            emulator: Emulator::new(bus),
+            terminal,
+            cassette,
+        })
+    }
+}
+
+impl<R: Read, W: Write> Apple1<R, W> {
+    /// Put `data` in RAM from `address`, such as Apple-1 BASIC at $E000.
+    pub fn load(&mut self, data: &[u8], address: u16) -> Result<(), String> {
+        let memory = &mut self.emulator.memory.memory;
+        let end = address as usize + data.len();
+        let fits = data.is_empty()
+            || (end <= 0x10000
+                && memory.index(address).is_some()
+                && memory.index((end - 1) as u16).is_some());
+        if !fits {
+            return Err(format!(
+                "{} bytes at ${:04x} do not fit in RAM.",
+                data.len(),
+                address
+            ));
+        }
+
+        for (i, value) in data.iter().enumerate() {
+            memory.write(address + i as u16, *value);
+        }
+        Ok(())
+    }
+
+    /// Execute the next instruction.
+    pub fn step(&mut self) {
+        bus::step(&mut self.emulator);
+    }
+
+    /// Run until `cycle`, only clocking the devices when one is due or accessed. A program that
+    /// traps keeps running its loop, as nothing but a reset gets the real machine out of it.
+    pub fn run_until(&mut self, cycle: u64) {
+        while self.emulator.cycles < cycle {
+            bus::run_until(&mut self.emulator, cycle);
+        }
+    }
+
+    /// Press the reset button, which restarts the monitor.
+    pub fn reset(&mut self) {
+        self.emulator.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use crate::emulator::instructions::opcodes::{
+        Absolute, Immediate, Implied, Relative, BPL, CLI, JMP, LDA, LDY, STA, STY,
+    };
+    use crate::machines::apple1::{Apple1, Config, Ram};
+    use crate::memory::Memory;
+
+    /// A monitor that prints "OK" and stops.
+    fn monitor() -> Vec<u8> {
+        let mut rom = [
+            LDY::immediate(0x7f),
+            STY::absolute(0xd012),
+            LDA::immediate(0xa7),
+            STA::absolute(0xd013),
+            LDA::immediate(b'O' | 0x80),
+            STA::absolute(0xd012),
+            LDA::immediate(b'K' | 0x80),
+            STA::absolute(0xd012),
+            JMP::absolute(0xff14),
+        ]
+        .concat();
+        rom.resize(256, 0);
+        rom[0xfc] = 0x00;
+        rom[0xfd] = 0xff;
+        rom
+    }
+
+    fn apple1(ram: Ram) -> Apple1<VecDeque<u8>, Vec<u8>> {
+        let config = Config {
+            ram,
+            wozmon: monitor(),
+            ..Config::default()
+        };
+        Apple1::new(config, VecDeque::new(), Vec::new()).unwrap()
+    }
+
+    #[test]
+    fn test_boot() {
+        let mut apple1 = apple1(Ram::EightK);
+        for _ in 0..10 {
+            apple1.step();
+        }
+
+        assert_eq!(b"OK", apple1.terminal.borrow_mut().output().as_slice());
+        assert!(apple1.emulator.is_trapped());
+
+        apple1.reset();
+        assert_eq!(0xff00, apple1.emulator.registers.program_counter);
+    }
+
+    #[test]
+    fn test_memory_map() {
+        let mut apple1 = apple1(Ram::FourK);
+        let memory = &mut apple1.emulator.memory;
+        memory.write(0x0fff, 0x42);
+        memory.write(0x1000, 0x42);
+        memory.write(0xe000, 0x42);
+        memory.write(0xff00, 0x42);
+
+        assert_eq!(0x42, memory.read(0x0fff));
+        assert_eq!(0x00, memory.read(0x1000));
+        assert_eq!(0x00, memory.read(0xe000));
+        assert_eq!(0xa0, memory.read(0xff00));
+        assert!(apple1.load(&[1, 2], 0xe000).is_err());
+
+        let mut apple1 = self::apple1(Ram::EightK);
+        apple1.load(&[1, 2], 0xe000).unwrap();
+        assert_eq!(0x02, apple1.emulator.memory.read(0xe001));
+        assert!(apple1.load(&[1, 2], 0xefff).is_err());
+    }
+
+    #[test]
+    fn test_keyboard_interrupt() {
+        // Enable the interrupts of the PIA like WozMon, allow them, then wait for a key.
+        let mut rom = [
+            LDA::immediate(0xa7),
+            STA::absolute(0xd011),
+            CLI::implied(),
+            LDA::absolute(0xd011),
+            BPL::relative(-5),
+            LDA::absolute(0xd010),
+            STA::absolute(0x0000),
+            JMP::absolute(0xff11),
+        ]
+        .concat();
+        rom.resize(256, 0);
+        rom[0xfd] = 0xff;
+        let config = Config {
+            wozmon: rom,
+            ..Config::default()
+        };
+        let mut apple1 = Apple1::new(config, VecDeque::new(), Vec::new()).unwrap();
+
+        apple1.run_until(1000);
+        apple1.terminal.borrow_mut().input().push_back(b'a');
+        apple1.run_until(2000);
+
+        // The key was read by polling, as the IRQ vector at $0000 was never taken.
+        assert_eq!(b'A' | 0x80, apple1.emulator.memory.read(0x0000));
+        assert_eq!(0xff11, apple1.emulator.registers.program_counter);
+    }
+
+    #[test]
+    fn test_invalid_rom() {
+        let config = Config {
+            wozmon: vec![0; 255],
+            ..Config::default()
+        };
+        assert!(Apple1::new(config, VecDeque::new(), Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,311 @@
+//! Ben Eater's breadboard computer: a 6502 with RAM, ROM and a 6522 VIA driving an HD44780
+//! character LCD and a row of buttons.
+//!
+//! | Address | Purpose |
+//! |---------|---------|
+//! | $0000-$3FFF | 16K of RAM |
+//! | $6000-$7FFF | The VIA, its 16 registers repeating |
+//! | $8000-$FFFF | 32K of ROM |
+//!
+//! The LCD has its data bus on port B, and its enable, read/write and register select lines on
+//! PA7, PA6 and PA5. The buttons pull PA0 to PA4 low while they are pressed, and one more button
+//! pulls CA1 low, as in Ben's interrupt video, where the VIA interrupts on its falling edge.
+//! Nothing else is connected, so reading it gives 0 and writing it does nothing.
+//!
+//! The original has a 65C02, which has a few instructions more than the 6502 emulated here, so
+//! programs for it have to stick to the instructions they share.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::devices::bus;
+use crate::devices::bus::{Bus, Line};
+use crate::devices::hd44780::Hd44780;
+use crate::devices::via::Via;
+use crate::devices::Device;
+use crate::emulator::run::StopReason;
+use crate::emulator::Emulator;
+use crate::memory::Memory;
+
+/// The clock of the processor, in cycles per second.
+pub const CLOCK: u32 = 1_000_000;
+
+pub const VIA_ADDRESS: u16 = 0x6000;
+pub const ROM_ADDRESS: u16 = 0x8000;
+pub const ROM_SIZE: usize = 0x8000;
+const RAM_SIZE: usize = 0x4000;
+
+// Port A.
+const ENABLE: u8 = 0x80;
+const READ: u8 = 0x40;
+const REGISTER_SELECT: u8 = 0x20;
+/// The pins the buttons are connected to.
+pub const BUTTONS: u8 = 0x1f;
+
+/// The RAM and the ROM.
+pub struct BenEaterMemory {
+    ram: Vec<u8>,
+    rom: Vec<u8>,
+}
+
+impl Memory for BenEaterMemory {
+    fn read(&self, address: u16) -> u8 {
+        match address as usize {
+            a if a < RAM_SIZE => self.ram[a],
+            a if a >= ROM_ADDRESS as usize => self.rom[a - ROM_ADDRESS as usize],
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if (address as usize) < RAM_SIZE {
+            self.ram[address as usize] = value;
+        }
+    }
+}
+
+pub struct BenEater {
+    pub emulator: Emulator<Bus<BenEaterMemory>>,
+    pub via: Rc<RefCell<Via>>,
+    /// A 16x2 display.
+    pub lcd: Hd44780,
+    /// The buttons that are pressed, one bit per pin of port A.
+    buttons: u8,
+    /// Whether the button on CA1 is pressed.
+    interrupt_button: bool,
+}
+
+impl BenEater {
+    /// The computer with a 32K ROM image, starting at its reset vector.
+    pub fn new(rom: Vec<u8>) -> Result<BenEater, String> {
+        if rom.len() != ROM_SIZE {
+            return Err(format!(
+                "The ROM is {} bytes rather than {}.",
+                rom.len(),
+                ROM_SIZE
+            ));
+        }
+
+        let memory = BenEaterMemory {
+            ram: vec![0; RAM_SIZE],
+            rom,
+        };
+        let via = Rc::new(RefCell::new(Via::new()));
+        let mut bus = Bus::new(memory);
+        bus.attach("via", VIA_ADDRESS, 0x2000, via.clone(), Line::Irq);
+
+        let mut computer = BenEater {
+            emulator: Emulator::new(bus),
+            via,
+            lcd: Hd44780::with_clock(16, 2, CLOCK),
+            buttons: 0,
+            interrupt_button: false,
+        };
+        computer.connect();
+        Ok(computer)
+    }
+
+    /// Press the buttons set in `buttons`, and release the others.
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.buttons = buttons & BUTTONS;
+        self.connect();
+    }
+
+    /// Press or release the button on CA1.
+    pub fn set_interrupt_button(&mut self, pressed: bool) {
+        self.interrupt_button = pressed;
+        self.connect();
+    }
+
+    /// Execute the next instruction, and let the LCD see what it did to the ports.
+    pub fn step(&mut self) {
+        let cycles = self.emulator.cycles;
+        bus::step(&mut self.emulator);
+        self.lcd.tick(self.emulator.cycles - cycles);
+        self.connect();
+    }
+
+    /// Like `Emulator::run`, but with `step`.
+    pub fn run(&mut self, max_instructions: u64) -> StopReason {
+        for _ in 0..max_instructions {
+            self.step();
+            if self.emulator.is_trapped() {
+                return StopReason::Trap(self.emulator.registers.program_counter);
+            }
+        }
+
+        StopReason::InstructionLimit
+    }
+
+    /// What the LCD shows, one string per line.
+    pub fn text(&self) -> Vec<String> {
+        self.lcd.text()
+    }
+
+    /// Carry the levels of the pins between the VIA, the LCD and the buttons.
+    fn connect(&mut self) {
+        let mut via = self.via.borrow_mut();
+        let control = via.port_a();
+        self.lcd.set_pins(
+            control & REGISTER_SELECT != 0,
+            control & READ != 0,
+            control & ENABLE != 0,
+            via.port_b(),
+        );
+
+        // Pins nobody drives read as high.
+        via.set_port_b(self.lcd.data().unwrap_or(0xff));
+        via.set_port_a(!self.buttons);
+        via.set_ca1(!self.interrupt_button);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::instructions::opcodes::{
+        Absolute, AbsoluteX, Immediate, Implied, Relative, AND, BEQ, BNE, INX, JMP, JSR, LDA, LDX,
+        PHA, PLA, RTS, STA,
+    };
+    use crate::emulator::run::StopReason;
+    use crate::machines::ben_eater::{BenEater, ROM_ADDRESS, ROM_SIZE};
+    use crate::memory::Memory;
+
+    const PORTB: u16 = 0x6000;
+    const PORTA: u16 = 0x6001;
+    const DDRB: u16 = 0x6002;
+    const DDRA: u16 = 0x6003;
+    const PCR: u16 = 0x600c;
+    const IFR: u16 = 0x600d;
+    const IER: u16 = 0x600e;
+
+    /// Ben's "hello, world": set up the LCD, print a message, waiting for the LCD every time.
+    fn hello_world() -> Vec<u8> {
+        let program = [
+            // Reset, at $8000.
+            LDA::immediate(0xff),
+            STA::absolute(DDRB),
+            LDA::immediate(0xe0),
+            STA::absolute(DDRA),
+            LDA::immediate(0x38),
+            JSR::absolute(0x8040),
+            LDA::immediate(0x0e),
+            JSR::absolute(0x8040),
+            LDA::immediate(0x06),
+            JSR::absolute(0x8040),
+            LDA::immediate(0x01),
+            JSR::absolute(0x8040),
+            LDX::immediate(0),
+            // Print, at $8020.
+            LDA::absolute_x(0x8080),
+            BEQ::relative(7),
+            JSR::absolute(0x8060),
+            INX::implied(),
+            JMP::absolute(0x8020),
+            // Loop, at $802c.
+            JMP::absolute(0x802c),
+        ]
+        .concat();
+
+        let instruction = [
+            // Send an instruction, at $8040.
+            JSR::absolute(0x80a0),
+            STA::absolute(PORTB),
+            LDA::immediate(0x00),
+            STA::absolute(PORTA),
+            LDA::immediate(0x80),
+            STA::absolute(PORTA),
+            LDA::immediate(0x00),
+            STA::absolute(PORTA),
+            RTS::implied(),
+        ]
+        .concat();
+
+        let data = [
+            // Send data, at $8060.
+            JSR::absolute(0x80a0),
+            STA::absolute(PORTB),
+            LDA::immediate(0x20),
+            STA::absolute(PORTA),
+            LDA::immediate(0xa0),
+            STA::absolute(PORTA),
+            LDA::immediate(0x20),
+            STA::absolute(PORTA),
+            RTS::implied(),
+        ]
+        .concat();
+
+        let wait = [
+            // Wait until the LCD is not busy, at $80a0, keeping A.
+            PHA::implied(),
+            LDA::immediate(0x00),
+            STA::absolute(DDRB),
+            // Busy, at $80a6.
+            LDA::immediate(0x40),
+            STA::absolute(PORTA),
+            LDA::immediate(0xc0),
+            STA::absolute(PORTA),
+            LDA::absolute(PORTB),
+            AND::immediate(0x80),
+            BNE::relative(-17),
+            LDA::immediate(0x40),
+            STA::absolute(PORTA),
+            LDA::immediate(0xff),
+            STA::absolute(DDRB),
+            PLA::implied(),
+            RTS::implied(),
+        ]
+        .concat();
+
+        let mut rom = vec![0; ROM_SIZE];
+        rom[..program.len()].copy_from_slice(&program);
+        rom[0x40..0x40 + instruction.len()].copy_from_slice(&instruction);
+        rom[0x60..0x60 + data.len()].copy_from_slice(&data);
+        rom[0x80..0x8e].copy_from_slice(b"Hello, world!\0");
+        rom[0xa0..0xa0 + wait.len()].copy_from_slice(&wait);
+        rom[0x7ffc] = (ROM_ADDRESS & 0xff) as u8;
+        rom[0x7ffd] = (ROM_ADDRESS >> 8) as u8;
+        rom
+    }
+
+    #[test]
+    fn test_hello_world() {
+        let mut computer = BenEater::new(hello_world()).unwrap();
+        let reason = computer.run(5000);
+
+        assert_eq!(
+            vec!["Hello, world!   ", "                "],
+            computer.text()
+        );
+        assert!(matches!(reason, StopReason::Trap(_)));
+    }
+
+    #[test]
+    fn test_buttons() {
+        let mut computer = BenEater::new(hello_world()).unwrap();
+        computer.set_buttons(0x05);
+        assert_eq!(0xfa, computer.via.borrow().port_a());
+    }
+
+    #[test]
+    fn test_interrupt_button() {
+        let mut computer = BenEater::new(hello_world()).unwrap();
+        // Interrupt on the falling edge of CA1.
+        computer.emulator.memory.write(PCR, 0x00);
+        computer.emulator.memory.write(IER, 0x82);
+
+        computer.set_interrupt_button(true);
+        assert_eq!(0x82, computer.emulator.memory.read(IFR));
+
+        // Reading port A clears the flag, and letting go of the button does not set it again.
+        computer.emulator.memory.read(PORTA);
+        computer.set_interrupt_button(false);
+        assert_eq!(0x00, computer.emulator.memory.read(IFR));
+        computer.set_interrupt_button(true);
+        assert_eq!(0x82, computer.emulator.memory.read(IFR));
+    }
+
+    #[test]
+    fn test_invalid_rom() {
+        assert!(BenEater::new(vec![0; 0x100]).is_err());
+    }
+}
@@ -0,0 +1,629 @@
+//! The KIM-1.
+//!
+//! | Address | Purpose |
+//! |---------|---------|
+//! | $0000-$03FF | 1K of RAM |
+//! | $1700-$173F | The registers of the 6530-003, its ports free for the user |
+//! | $1740-$177F | The registers of the 6530-002, with the keypad, the display and the TTY |
+//! | $1780-$17BF | The RAM of the 6530-003 |
+//! | $17C0-$17FF | The RAM of the 6530-002 |
+//! | $1800-$1FFF | The monitor, in the ROMs of the 6530-003 and the 6530-002 |
+//!
+//! Only A0 to A12 are decoded, so the RAM and the ROMs repeat every 8K, and the vectors at $FFFA
+//! come from the monitor at $1FFA. The RIOTs are only mapped at $1700. Nothing else is connected:
+//! reading it gives 0 and writing it does nothing.
+//!
+//! The monitor drives the display and scans the keypad through port A of the 6530-002. PB1 to
+//! PB4 go to a decoder: its outputs 0 to 2 select a row of the keypad, whose keys pull PA0 to PA6
+//! low, and 4 to 9 light one of the six digits with the segments on PA0 to PA6. The digits are
+//! lit one at a time, so a digit that is no longer scanned goes dark soon after.
+//!
+//! With the TTY jumper fitted, decoder output 3 pulls PA0 low, which tells the monitor to talk to
+//! a teletype instead: it receives on PA7 and sends on PB0, both 1 when idle, timing the bits
+//! with delay loops. The teletype is in the same current loop, so what is typed is printed too.
+
+use std::cell::RefCell;
+use std::io::{Read, Write};
+use std::rc::Rc;
+
+use crate::devices::bus;
+use crate::devices::bus::{Bus, Line};
+use crate::devices::riot::{Model, Riot, RiotRam};
+use crate::devices::send;
+use crate::emulator::Emulator;
+use crate::memory::Memory;
+
+/// The clock of the processor, in cycles per second.
+pub const CLOCK: u32 = 1_000_000;
+
+pub const MONITOR_ADDRESS: u16 = 0x1800;
+pub const MONITOR_SIZE: usize = 0x800;
+pub const RIOT_003_ADDRESS: u16 = 0x1700;
+pub const RIOT_002_ADDRESS: u16 = 0x1740;
+const RIOT_003_RAM_ADDRESS: u16 = 0x1780;
+const RIOT_002_RAM_ADDRESS: u16 = 0x17c0;
+/// Where the ROM of the 6530-002 starts, which the SST switch does not stop in.
+const RIOT_002_ROM_ADDRESS: u16 = 0x1c00;
+const RAM_SIZE: usize = 0x400;
+const ADDRESS_MASK: u16 = 0x1fff;
+
+/// The speed of the teletype unless told otherwise, in bits per second.
+pub const DEFAULT_BAUD: u32 = 1200;
+
+// Port A of the 6530-002.
+const SEGMENTS: u8 = 0x7f;
+const TTY_IN: u8 = 0x80;
+const TTY_JUMPER: u8 = 0x01;
+
+// Port B of the 6530-002.
+const TTY_OUT: u8 = 0x01;
+
+// Outputs of the decoder on PB1 to PB4.
+const TTY_ROW: u8 = 3;
+const FIRST_DIGIT: u8 = 4;
+
+pub const DIGITS: usize = 6;
+
+/// Cycles a digit stays lit after the program last scanned it.
+const PERSISTENCE: u64 = CLOCK as u64 / 50;
+
+/// The segments of the hex digits the monitor shows, with segment a in bit 0 to g in bit 6.
+const HEX_DIGITS: [u8; 16] = [
+    0x3f, 0x06, 0x5b, 0x4f, 0x66, 0x6d, 0x7d, 0x07, 0x7f, 0x6f, 0x77, 0x7c, 0x39, 0x5e, 0x79, 0x71,
+];
+
+/// A key of the keypad. ST and RS are not on the keypad matrix: see `Kim1::stop` and
+/// `Kim1::reset`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    /// 0 to F.
+    Digit(u8),
+    Address,
+    Data,
+    Plus,
+    Go,
+    Pc,
+}
+
+impl Key {
+    /// The row of the keypad and the pin of port A the key connects. The rows hold the keys in
+    /// this order, seven at a time from PA6 down to PA0.
+    fn position(self) -> (u8, u8) {
+        let index = match self {
+            Key::Digit(digit) => digit & 0x0f,
+            Key::Address => 16,
+            Key::Data => 17,
+            Key::Plus => 18,
+            Key::Go => 19,
+            Key::Pc => 20,
+        };
+        (index / 7, 0x40 >> (index % 7))
+    }
+}
+
+pub struct Config {
+    /// The 2K of ROM at $1800, the 6530-003 and then the 6530-002.
+    pub monitor: Vec<u8>,
+    /// Whether the TTY jumper is fitted.
+    pub tty: bool,
+    /// The speed of the teletype, in bits per second.
+    pub baud: u32,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            monitor: Vec::new(),
+            tty: false,
+            baud: DEFAULT_BAUD,
+        }
+    }
+}
+
+/// The RAM and the monitor ROM.
+pub struct Kim1Memory {
+    ram: Vec<u8>,
+    monitor: Vec<u8>,
+}
+
+impl Memory for Kim1Memory {
+    fn read(&self, address: u16) -> u8 {
+        match (address & ADDRESS_MASK) as usize {
+            a if a < RAM_SIZE => self.ram[a],
+            a if a >= MONITOR_ADDRESS as usize => self.monitor[a - MONITOR_ADDRESS as usize],
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        let address = (address & ADDRESS_MASK) as usize;
+        if address < RAM_SIZE {
+            self.ram[address] = value;
+        }
+    }
+}
+
+/// A teletype on the serial lines: the characters of `input` are sent to the KIM-1, and the
+/// characters it sends go to `output`. Every character has a start bit, 8 data bits and a stop
+/// bit.
+pub struct Tty<R: Read, W: Write> {
+    input: R,
+    output: W,
+    cycles_per_bit: u64,
+    /// The bits of the character being sent, from the start bit, and the cycle it started.
+    sending: Option<(u16, u64)>,
+    /// The next character is not sent before this cycle.
+    idle_until: u64,
+    /// The cycle the start bit of the character being received began, the bits received so
+    /// far, and how many.
+    receiving: Option<(u64, u8, u64)>,
+}
+
+impl<R: Read, W: Write> Tty<R, W> {
+    /// A teletype sending and receiving a bit every `cycles_per_bit` cycles. It waits for a
+    /// character time before sending anything, to give the program time to start listening.
+    pub fn new(input: R, output: W, cycles_per_bit: u64) -> Tty<R, W> {
+        Tty {
+            input,
+            output,
+            cycles_per_bit,
+            sending: None,
+            idle_until: 10 * cycles_per_bit,
+            receiving: None,
+        }
+    }
+
+    pub fn input(&mut self) -> &mut R {
+        &mut self.input
+    }
+
+    pub fn output(&mut self) -> &mut W {
+        &mut self.output
+    }
+
+    /// The level of the line to the KIM-1 at `cycle`.
+    pub fn line(&self, cycle: u64) -> bool {
+        match self.sending {
+            Some((bits, start)) => match (cycle - start) / self.cycles_per_bit {
+                bit if bit < 10 => bits >> bit & 1 != 0,
+                _ => true,
+            },
+            None => true,
+        }
+    }
+
+    /// Move on to `cycle`, with the line from the KIM-1 at `level`.
+    pub fn update(&mut self, cycle: u64, level: bool) {
+        self.send(cycle);
+
+        // What is typed goes round the loop to the printer as well.
+        let level = level && self.line(cycle);
+        self.receive(cycle, level);
+    }
+
+    fn send(&mut self, cycle: u64) {
+        if let Some((_, start)) = self.sending {
+            if cycle < start + 10 * self.cycles_per_bit {
+                return;
+            }
+            self.sending = None;
+            self.idle_until = start + 11 * self.cycles_per_bit;
+        }
+        if cycle < self.idle_until {
+            return;
+        }
+
+        let mut byte = [0];
+        if let Ok(1) = self.input.read(&mut byte) {
+            // A start bit of 0, the character from bit 0, and a stop bit of 1.
+            self.sending = Some(((byte[0] as u16) << 1 | 0x200, cycle));
+        }
+    }
+
+    /// Sample the bits in their middle, counting from the start of the start bit.
+    fn receive(&mut self, cycle: u64, level: bool) {
+        let (start, bits, count) = match self.receiving {
+            Some(receiving) => receiving,
+            None => {
+                if !level {
+                    self.receiving = Some((cycle, 0, 0));
+                }
+                return;
+            }
+        };
+
+        let middle = start + self.cycles_per_bit * (count + 1) + self.cycles_per_bit / 2;
+        if cycle < middle {
+            return;
+        }
+        if count < 8 {
+            self.receiving = Some((start, bits | (level as u8) << count, count + 1));
+            return;
+        }
+
+        self.receiving = None;
+        send(&mut self.output, &[bits & 0x7f]);
+    }
+}
+
+pub struct Kim1<R: Read, W: Write> {
+    pub emulator: Emulator<Bus<Kim1Memory>>,
+    /// The 6530-002, with the keypad, the display and the TTY on its ports.
+    pub riot_002: Rc<RefCell<Riot>>,
+    /// The 6530-003, with its ports on the application connector.
+    pub riot_003: Rc<RefCell<Riot>>,
+    pub tty: Tty<R, W>,
+    /// The SST switch, which stops after every instruction outside the ROM of the 6530-002.
+    pub single_step: bool,
+    tty_jumper: bool,
+    key: Option<Key>,
+    /// The segments each digit showed when it was last lit, and the cycle it was.
+    segments: [u8; DIGITS],
+    lit_at: [Option<u64>; DIGITS],
+}
+
+impl<R: Read, W: Write> Kim1<R, W> {
+    /// A KIM-1 with `input` and `output` as the teletype, starting at the reset vector of the
+    /// monitor.
+    pub fn new(config: Config, input: R, output: W) -> Result<Kim1<R, W>, String> {
+        if config.monitor.len() != MONITOR_SIZE {
+            return Err(format!(
+                "The monitor ROM is {} bytes rather than {}.",
+                config.monitor.len(),
+                MONITOR_SIZE
+            ));
+        }
+        if config.baud == 0 || config.baud > CLOCK {
+            return Err(format!("Invalid baud rate {}.", config.baud));
+        }
+
+        let memory = Kim1Memory {
+            ram: vec![0; RAM_SIZE],
+            monitor: config.monitor,
+        };
+        let mut bus = Bus::new(memory);
+
+        // The timer interrupts are only wired to IRQ with a jumper, which is usually there.
+        let riot_003 = Rc::new(RefCell::new(Riot::with_model(Model::Mos6530)));
+        let riot_002 = Rc::new(RefCell::new(Riot::with_model(Model::Mos6530)));
+        for (name, riot, address, ram_address) in [
+            (
+                "6530-003",
+                &riot_003,
+                RIOT_003_ADDRESS,
+                RIOT_003_RAM_ADDRESS,
+            ),
+            (
+                "6530-002",
+                &riot_002,
+                RIOT_002_ADDRESS,
+                RIOT_002_RAM_ADDRESS,
+            ),
+        ] {
+            let ram = Rc::new(RefCell::new(RiotRam::new(riot.clone())));
+            bus.attach(&format!("{} RAM", name), ram_address, 0x40, ram, Line::Irq);
+            bus.attach(name, address, 0x40, riot.clone(), Line::Irq);
+        }
+
+        let mut kim1 = Kim1 {
+            emulator: Emulator::new(bus),
+            riot_002,
+            riot_003,
+            tty: Tty::new(input, output, (CLOCK / config.baud) as u64),
+            single_step: false,
+            tty_jumper: config.tty,
+            key: None,
+            segments: [0; DIGITS],
+            lit_at: [None; DIGITS],
+        };
+        kim1.connect();
+        Ok(kim1)
+    }
+
+    /// Put `data` in RAM from `address`.
+    pub fn load(&mut self, data: &[u8], address: u16) -> Result<(), String> {
+        if address as usize + data.len() > RAM_SIZE {
+            return Err(format!(
+                "{} bytes at ${:04x} do not fit in RAM.",
+                data.len(),
+                address
+            ));
+        }
+
+        for (i, value) in data.iter().enumerate() {
+            self.emulator
+                .memory
+                .memory
+                .write(address + i as u16, *value);
+        }
+        Ok(())
+    }
+
+    /// Hold down `key`, or no key.
+    pub fn set_key(&mut self, key: Option<Key>) {
+        self.key = key;
+        self.connect();
+    }
+
+    /// Press the ST key, which interrupts the processor with NMI.
+    pub fn stop(&mut self) {
+        self.emulator.nmi = true;
+    }
+
+    /// Press the RS key, which restarts the monitor.
+    pub fn reset(&mut self) {
+        self.emulator.reset();
+    }
+
+    /// Execute the next instruction, and let the keypad, the display and the teletype see what
+    /// it did to the ports.
+    pub fn step(&mut self) {
+        let address = self.emulator.next_instruction_address();
+        bus::step(&mut self.emulator);
+        if self.single_step && address & ADDRESS_MASK < RIOT_002_ROM_ADDRESS {
+            self.emulator.nmi = true;
+        }
+        self.connect();
+    }
+
+    /// The segments of the six digits from the left, with segment a in bit 0 to g in bit 6.
+    pub fn display(&self) -> [u8; DIGITS] {
+        let mut display = [0; DIGITS];
+        for (digit, segments) in display.iter_mut().enumerate() {
+            if let Some(cycle) = self.lit_at[digit] {
+                if self.emulator.cycles - cycle <= PERSISTENCE {
+                    *segments = self.segments[digit];
+                }
+            }
+        }
+        display
+    }
+
+    /// The display as text: a hex digit for each digit that shows one, a space for a dark one,
+    /// and '?' for anything else.
+    pub fn digits(&self) -> String {
+        self.display()
+            .iter()
+            .map(
+                |segments| match HEX_DIGITS.iter().position(|s| s == segments) {
+                    Some(digit) => std::char::from_digit(digit as u32, 16)
+                        .unwrap()
+                        .to_ascii_uppercase(),
+                    None if *segments == 0 => ' ',
+                    None => '?',
+                },
+            )
+            .collect()
+    }
+
+    /// Carry the levels of the pins between the 6530-002, the keypad, the display and the
+    /// teletype.
+    fn connect(&mut self) {
+        let cycle = self.emulator.cycles;
+        let mut riot = self.riot_002.borrow_mut();
+        let port_b = riot.port_b();
+        let output = (port_b >> 1) & 0x0f;
+
+        if (FIRST_DIGIT..FIRST_DIGIT + DIGITS as u8).contains(&output) {
+            // The program blanks the segments while it selects the next digit.
+            let segments = riot.port_a() & SEGMENTS;
+            if segments != 0 {
+                let digit = (output - FIRST_DIGIT) as usize;
+                self.segments[digit] = segments;
+                self.lit_at[digit] = Some(cycle);
+            }
+        }
+
+        self.tty.update(cycle, port_b & TTY_OUT != 0);
+
+        // Pins nobody pulls low read as high.
+        let mut pins = SEGMENTS;
+        if let Some((row, pin)) = self.key.map(Key::position) {
+            if row == output {
+                pins &= !pin;
+            }
+        }
+        if self.tty_jumper && output == TTY_ROW {
+            pins &= !TTY_JUMPER;
+        }
+        if self.tty.line(cycle) {
+            pins |= TTY_IN;
+        }
+        riot.set_port_a(pins);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use crate::emulator::instructions::opcodes::{
+        Absolute, AbsoluteX, Immediate, Implied, Relative, BNE, CPX, INX, JMP, LDA, LDX, NOP, STA,
+    };
+    use crate::machines::kim1::{Config, Key, Kim1, Tty, MONITOR_SIZE};
+    use crate::memory::Memory;
+
+    const SAD: u16 = 0x1740;
+    const PADD: u16 = 0x1741;
+    const SBD: u16 = 0x1742;
+    const PBDD: u16 = 0x1743;
+
+    /// A monitor that sets up the ports like the real one and runs `program` from $1C00.
+    fn monitor(program: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut rom = vec![0; MONITOR_SIZE];
+        rom[0x400..0x400 + program.len()].copy_from_slice(program);
+        rom[0x480..0x480 + data.len()].copy_from_slice(data);
+        // NMI at $1F00, RESET at $1C00.
+        rom[0x7fa..].copy_from_slice(&[0x00, 0x1f, 0x00, 0x1c, 0x00, 0x1f]);
+        rom[0x700..0x703].copy_from_slice(&JMP::absolute(0x1f00));
+        rom
+    }
+
+    fn kim1(monitor: Vec<u8>, tty: bool) -> Kim1<VecDeque<u8>, Vec<u8>> {
+        let config = Config {
+            monitor,
+            tty,
+            ..Config::default()
+        };
+        Kim1::new(config, VecDeque::new(), Vec::new()).unwrap()
+    }
+
+    #[test]
+    fn test_display() {
+        // Scan the digits like the monitor: blank the segments, select a digit, then light it.
+        let program = [
+            LDA::immediate(0x7f),
+            STA::absolute(PADD),
+            LDA::immediate(0x1e),
+            STA::absolute(PBDD),
+            // Scan, at $1C0A.
+            LDX::immediate(0),
+            // Digit, at $1C0C.
+            LDA::immediate(0),
+            STA::absolute(SAD),
+            LDA::absolute_x(0x1c90),
+            STA::absolute(SBD),
+            LDA::absolute_x(0x1c80),
+            STA::absolute(SAD),
+            INX::implied(),
+            CPX::immediate(6),
+            BNE::relative(-22),
+            JMP::absolute(0x1c0a),
+        ]
+        .concat();
+        let mut data = vec![0x86, 0xdb, 0xcf, 0xe6, 0x80, 0x77];
+        data.resize(0x10, 0);
+        data.extend_from_slice(&[0x08, 0x0a, 0x0c, 0x0e, 0x10, 0x12]);
+
+        let mut kim1 = kim1(monitor(&program, &data), false);
+        for _ in 0..200 {
+            kim1.step();
+        }
+        assert_eq!([0x06, 0x5b, 0x4f, 0x66, 0x00, 0x77], kim1.display());
+        assert_eq!("1234 A", kim1.digits());
+
+        // The digits go dark once they are no longer scanned.
+        let stop = [
+            LDA::immediate(0x06),
+            STA::absolute(SBD),
+            JMP::absolute(0x0005),
+        ]
+        .concat();
+        kim1.load(&stop, 0x0000).unwrap();
+        kim1.emulator.registers.program_counter = 0x0000;
+        for _ in 0..10000 {
+            kim1.step();
+        }
+        assert_eq!("      ", kim1.digits());
+    }
+
+    #[test]
+    fn test_keypad() {
+        // Read the three rows of the keypad and the TTY jumper to $00-$03.
+        let mut program = [LDA::immediate(0x1e), STA::absolute(PBDD)].concat();
+        for row in 0..4 {
+            program.extend(
+                [
+                    LDA::immediate(row << 1),
+                    STA::absolute(SBD),
+                    LDA::absolute(SAD),
+                    STA::absolute(row as u16),
+                ]
+                .concat(),
+            );
+        }
+        program.extend(JMP::absolute(0x1c00 + program.len() as u16));
+
+        let mut kim1 = kim1(monitor(&program, &[]), false);
+        kim1.set_key(Some(Key::Go));
+        for _ in 0..20 {
+            kim1.step();
+        }
+        let memory = &kim1.emulator.memory;
+        assert_eq!(0xff, memory.read(0x00));
+        assert_eq!(0xff, memory.read(0x01));
+        assert_eq!(0xfd, memory.read(0x02));
+        assert_eq!(0xff, memory.read(0x03));
+
+        let mut kim1 = self::kim1(monitor(&program, &[]), true);
+        kim1.set_key(Some(Key::Digit(0x8)));
+        for _ in 0..20 {
+            kim1.step();
+        }
+        let memory = &kim1.emulator.memory;
+        assert_eq!(0xff, memory.read(0x00));
+        assert_eq!(0xdf, memory.read(0x01));
+        assert_eq!(0xfe, memory.read(0x03));
+    }
+
+    #[test]
+    fn test_tty() {
+        let mut tty = Tty::new(VecDeque::from(b"K".to_vec()), Vec::new(), 100);
+        assert!(tty.line(0));
+
+        // 'K' is sent after waiting for a character time, and echoed.
+        let mut levels = Vec::new();
+        for cycle in (1000..2200).step_by(10) {
+            tty.update(cycle, true);
+            levels.push(tty.line(cycle));
+        }
+        assert_eq!(levels[0..10], [false; 10]);
+        assert_eq!(levels[10..20], [true; 10]);
+        assert_eq!(b"K", tty.output().as_slice());
+
+        // 'O' from the KIM-1.
+        let bits = (b'O' as u16) << 1 | 0x200;
+        for cycle in (3000..4200).step_by(10) {
+            let bit = (cycle - 3000) / 100;
+            tty.update(cycle, bit >= 10 || bits >> bit & 1 != 0);
+        }
+        assert_eq!(b"KO", tty.output().as_slice());
+    }
+
+    #[test]
+    fn test_stop() {
+        let mut kim1 = kim1(monitor(&[], &[]), false);
+        kim1.load(&NOP::implied(), 0x0200).unwrap();
+        kim1.emulator.registers.program_counter = 0x0200;
+
+        kim1.stop();
+        kim1.step();
+        assert_eq!(0x1f00, kim1.emulator.registers.program_counter);
+
+        // With SST, every instruction outside the monitor ROM stops.
+        kim1.single_step = true;
+        kim1.emulator.registers.program_counter = 0x0200;
+        kim1.step();
+        assert_eq!(0x0201, kim1.emulator.registers.program_counter);
+        kim1.step();
+        assert_eq!(0x1f00, kim1.emulator.registers.program_counter);
+
+        kim1.reset();
+        assert_eq!(0x1c00, kim1.emulator.registers.program_counter);
+    }
+
+    #[test]
+    fn test_memory_map() {
+        let mut kim1 = kim1(monitor(&[], &[]), false);
+        let memory = &mut kim1.emulator.memory;
+        memory.write(0x2000, 0x42);
+        memory.write(0x17ff, 0x43);
+        memory.write(0x0400, 0x44);
+
+        assert_eq!(0x42, memory.read(0x0000));
+        assert_eq!(0x43, memory.read(0x17ff));
+        assert_eq!(0x43, kim1.riot_002.borrow().ram()[0x3f]);
+        assert_eq!(0x00, memory.read(0x0400));
+        assert_eq!(0x1c, memory.read(0xfffd));
+        assert!(kim1.load(&[1, 2], 0x03ff).is_err());
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let config = Config {
+            monitor: vec![0; 0x400],
+            ..Config::default()
+        };
+        assert!(Kim1::new(config, VecDeque::new(), Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,6 @@
+//! Ready-made computers: a processor, memory and devices wired together the way a real machine
+//! does it.
+
+pub mod apple1;
+pub mod ben_eater;
+pub mod kim1;
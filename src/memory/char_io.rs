@@ -0,0 +1,101 @@
+//! A character I/O port, for programs that only need to read and write text.
+//!
+//! Reading the port takes the next byte of input, or 0 once the input is exhausted. Writing the
+//! port writes the byte to the output. There is no status register: reading blocks until input
+//! is available, just like `getchar`.
+
+use std::cell::RefCell;
+use std::io::{Read, Write};
+
+use crate::devices::send;
+use crate::memory::Memory;
+
+/// Memory with the port at a single address, and everything else passed through.
+pub struct CharIo<M: Memory, R: Read, W: Write> {
+    pub memory: M,
+    port: u16,
+    // Reading memory does not take `&mut self`, but reading input does.
+    input: RefCell<R>,
+    output: W,
+}
+
+impl<M: Memory, R: Read, W: Write> CharIo<M, R, W> {
+    pub fn new(memory: M, port: u16, input: R, output: W) -> CharIo<M, R, W> {
+        CharIo {
+            memory,
+            port,
+            input: RefCell::new(input),
+            output,
+        }
+    }
+
+    pub fn output(&mut self) -> &mut W {
+        &mut self.output
+    }
+}
+
+impl<M: Memory, R: Read, W: Write> Memory for CharIo<M, R, W> {
+    fn read(&self, address: u16) -> u8 {
+        if address != self.port {
+            return self.memory.read(address);
+        }
+
+        let mut byte = [0];
+        match self.input.borrow_mut().read(&mut byte) {
+            Ok(1) => byte[0],
+            _ => 0,
+        }
+    }
+
+    /// The port reads as 0, rather than taking input.
+    fn peek(&self, address: u16) -> u8 {
+        if address != self.port {
+            return self.memory.peek(address);
+        }
+        0
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address != self.port {
+            return self.memory.write(address, value);
+        }
+
+        send(&mut self.output, &[value]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::memory::char_io::CharIo;
+    use crate::memory::default::DefaultMemory;
+    use crate::memory::Memory;
+
+    #[test]
+    fn test_input() {
+        let memory = CharIo::new(
+            DefaultMemory::empty(),
+            0xf001,
+            Cursor::new("hi"),
+            Vec::new(),
+        );
+
+        assert_eq!(0, memory.peek(0xf001));
+        assert_eq!(b'h', memory.read(0xf001));
+        assert_eq!(b'i', memory.read(0xf001));
+        assert_eq!(0, memory.read(0xf001));
+    }
+
+    #[test]
+    fn test_output() {
+        let mut memory = CharIo::new(DefaultMemory::empty(), 0xf001, Cursor::new(""), Vec::new());
+        memory.write(0xf001, b'o');
+        memory.write(0xf001, b'k');
+        memory.write(0x200, 0x42);
+
+        assert_eq!(b"ok", memory.output().as_slice());
+        assert_eq!(0x42, memory.read(0x200));
+        assert_eq!(0x00, memory.memory.read(0xf001));
+    }
+}
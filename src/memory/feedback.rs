@@ -0,0 +1,155 @@
+//! The interrupt feedback port of Klaus Dormann's interrupt test.
+//!
+//! The test triggers interrupts by writing to an output port that is wired back to the IRQ and
+//! NMI inputs of the processor: by default bit 0 to IRQ and bit 1 to NMI, with a set bit
+//! asserting the interrupt. IRQ is level triggered, so it stays asserted as long as its bit is
+//! set. NMI is edge triggered, so only setting its bit triggers an interrupt.
+
+use crate::emulator::observer::Observer;
+use crate::emulator::Emulator;
+use crate::memory::Memory;
+
+/// The port address the interrupt test uses unless configured otherwise.
+pub const DEFAULT_PORT: u16 = 0xbffc;
+
+const IRQ_BIT: u8 = 0b01;
+const NMI_BIT: u8 = 0b10;
+
+/// Memory with the feedback port at a single address, and everything else passed through.
+pub struct InterruptFeedback<M: Memory> {
+    pub memory: M,
+    port: u16,
+    value: u8,
+    /// Whether the NMI bit was set since the last `update`.
+    nmi_edge: bool,
+}
+
+impl<M: Memory> InterruptFeedback<M> {
+    pub fn new(memory: M, port: u16) -> InterruptFeedback<M> {
+        InterruptFeedback {
+            memory,
+            port,
+            value: 0,
+            nmi_edge: false,
+        }
+    }
+
+    /// Whether the IRQ line is asserted.
+    pub fn irq(&self) -> bool {
+        self.value & IRQ_BIT != 0
+    }
+
+    /// Whether the NMI line is asserted.
+    pub fn nmi(&self) -> bool {
+        self.value & NMI_BIT != 0
+    }
+}
+
+impl<M: Memory> Memory for InterruptFeedback<M> {
+    fn read(&self, address: u16) -> u8 {
+        if address == self.port {
+            self.value
+        } else {
+            self.memory.read(address)
+        }
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        if address == self.port {
+            self.value
+        } else {
+            self.memory.peek(address)
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address != self.port {
+            return self.memory.write(address, value);
+        }
+
+        if value & NMI_BIT != 0 && self.value & NMI_BIT == 0 {
+            self.nmi_edge = true;
+        }
+        self.value = value;
+    }
+}
+
+/// Hand the state of the port to the processor. Call this before every instruction, so that an
+/// interrupt is taken right after the write that triggers it.
+pub fn update<M: Memory, O: Observer>(emulator: &mut Emulator<InterruptFeedback<M>, O>) {
+    emulator.irq = emulator.memory.irq();
+    if emulator.memory.nmi_edge {
+        emulator.memory.nmi_edge = false;
+        emulator.nmi = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::emulator::instructions::opcodes::*;
+    use crate::emulator::Emulator;
+    use crate::memory::default::DefaultMemory;
+    use crate::memory::feedback::{update, InterruptFeedback, DEFAULT_PORT};
+    use crate::memory::Memory;
+
+    fn emulator(program: Vec<Vec<u8>>) -> Emulator<InterruptFeedback<DefaultMemory>> {
+        let mut memory = DefaultMemory::empty();
+        memory.load(program.concat(), 0x600);
+        memory.set_program_counter(0x600);
+        memory.load(vec![0x00, 0x07, 0x00, 0x06, 0x00, 0x08], 0xfffa);
+        memory.load(NOP::implied(), 0x700);
+        memory.load(NOP::implied(), 0x800);
+
+        Emulator::new(InterruptFeedback::new(memory, DEFAULT_PORT))
+    }
+
+    fn step(e: &mut Emulator<InterruptFeedback<DefaultMemory>>) {
+        update(e);
+        e.execute_next();
+    }
+
+    #[test]
+    fn test_port() {
+        let mut memory = InterruptFeedback::new(DefaultMemory::empty(), DEFAULT_PORT);
+        memory.write(DEFAULT_PORT, 0x03);
+        memory.write(0x200, 0x42);
+
+        assert_eq!(0x03, memory.read(DEFAULT_PORT));
+        assert_eq!(0x00, memory.memory.read(DEFAULT_PORT));
+        assert_eq!(0x42, memory.read(0x200));
+        assert!(memory.irq());
+        assert!(memory.nmi());
+    }
+
+    #[test]
+    fn test_irq() {
+        let mut e = emulator(vec![
+            CLI::implied(),
+            LDA::immediate(0x01),
+            STA::absolute(DEFAULT_PORT),
+        ]);
+        for _ in 0..3 {
+            step(&mut e);
+        }
+
+        step(&mut e);
+        assert_eq!(0x801, e.registers.program_counter);
+    }
+
+    #[test]
+    fn test_nmi_edge() {
+        let mut e = emulator(vec![
+            LDA::immediate(0x02),
+            STA::absolute(DEFAULT_PORT),
+            STA::absolute(DEFAULT_PORT),
+        ]);
+        step(&mut e);
+        step(&mut e);
+
+        // The line stays asserted, but the edge has been taken.
+        step(&mut e);
+        assert_eq!(0x701, e.registers.program_counter);
+        assert!(!e.nmi);
+        assert!(e.memory.nmi());
+    }
+}
@@ -0,0 +1,63 @@
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
+
+use crate::memory::Memory;
+
+/// Ways to fill RAM before a program starts, instead of the zeroes a program may silently
+/// depend on. Real RAM comes up with arbitrary contents.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fill {
+    /// Repeat the bytes, starting at the first address of the range.
+    Pattern(Vec<u8>),
+    /// Random bytes. The same seed always gives the same contents, so failures can be
+    /// reproduced.
+    Random(u64),
+}
+
+impl Fill {
+    /// Fill memory from `start` to `end`, both inclusive.
+    pub fn apply<M: Memory>(&self, memory: &mut M, start: u16, end: u16) {
+        match self {
+            Fill::Pattern(pattern) if pattern.is_empty() => {}
+            Fill::Pattern(pattern) => {
+                for (address, value) in (start..=end).zip(pattern.iter().cycle()) {
+                    memory.write(address, *value);
+                }
+            }
+            Fill::Random(seed) => {
+                let mut rng = StdRng::seed_from_u64(*seed);
+                for address in start..=end {
+                    memory.write(address, rng.next_u32() as u8);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::default::DefaultMemory;
+    use crate::memory::fill::Fill;
+
+    #[test]
+    fn test_pattern() {
+        let mut memory = DefaultMemory::empty();
+        Fill::Pattern(vec![0xde, 0xad]).apply(&mut memory, 0x200, 0x204);
+
+        assert_eq!(
+            [0, 0xde, 0xad, 0xde, 0xad, 0xde, 0],
+            memory.memory[0x1ff..0x206]
+        );
+    }
+
+    #[test]
+    fn test_random_is_reproducible() {
+        let mut first = DefaultMemory::empty();
+        let mut second = DefaultMemory::empty();
+        Fill::Random(42).apply(&mut first, 0x0000, 0xffff);
+        Fill::Random(42).apply(&mut second, 0x0000, 0xffff);
+
+        assert_eq!(first.memory[..], second.memory[..]);
+        assert!(first.memory.iter().any(|b| *b != 0));
+    }
+}
@@ -1,6 +1,16 @@
 pub trait Memory: Sized {
     fn read(&self, address: u16) -> u8;
     fn write(&mut self, address: u16, value: u8);
+
+    /// Read without side effects, for tools that look at memory without taking part in the
+    /// program. Memory whose reads change something, such as a device register that clears a
+    /// flag or takes input, gives what it can see without doing so.
+    fn peek(&self, address: u16) -> u8 {
+        self.read(address)
+    }
 }
 
+pub mod char_io;
 pub mod default;
+pub mod feedback;
+pub mod fill;
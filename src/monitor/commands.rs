@@ -0,0 +1,808 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::io::Write;
+
+use cli;
+use emulator::debug::callstack::CallStack;
+use emulator::debug::disassembler::disassemble;
+use emulator::debug::symbols::SymbolTable;
+use emulator::debug::trace::trace;
+use emulator::emulator::instructions::opcodes::{Absolute, JSR};
+use emulator::emulator::registers::{Flag, FLAGS};
+use emulator::emulator::Emulator;
+use emulator::memory::default::DefaultMemory;
+use emulator::memory::Memory;
+
+const MEMORY_LINES: u16 = 8;
+const BYTES_PER_LINE: u16 = 16;
+const DISASSEMBLY_LINES: usize = 16;
+
+/// Instructions that `g` and `n` execute at most, so that a program that never reaches a
+/// breakpoint or trap gives the prompt back. A few seconds' worth.
+const MAX_INSTRUCTIONS: u64 = 100_000_000;
+
+const HELP: &str = "\
+m [start [end]]        show memory
+> address byte...      modify memory
+d [start [end]]        disassemble
+t [count]              trace instructions as they execute
+r [reg=value...]       show or modify registers (a, x, y, sp, pc, p)
+bt                     show the call stack
+z [count]              step into
+n [count]              step over subroutine calls
+g [address]            go until a breakpoint, trap or instruction limit
+b [address]            set a breakpoint, or list breakpoints
+del [address]          delete a breakpoint, or all breakpoints
+l file address         load a file into memory
+s file start end       save a memory range to a file
+f start end byte...    fill a memory range
+c start end address    compare a memory range against another
+h start end byte...    hunt for bytes in a memory range
+ll file                load symbols
+x                      exit
+
+Addresses can be given as symbols, optionally prefixed with '.'.";
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Outcome {
+    Continue,
+    Exit,
+}
+
+type CommandResult = Result<(), String>;
+
+pub(crate) struct Monitor {
+    pub(crate) emulator: Emulator<DefaultMemory, CallStack>,
+    pub(crate) symbols: SymbolTable,
+    breakpoints: BTreeSet<u16>,
+    /// Instructions that `g` and `n` execute at most.
+    pub(crate) max_instructions: u64,
+    next_memory: u16,
+    next_disassembly: u16,
+}
+
+impl Monitor {
+    pub(crate) fn new(emulator: Emulator<DefaultMemory, CallStack>) -> Monitor {
+        let pc = emulator.registers.program_counter;
+
+        Monitor {
+            emulator,
+            symbols: SymbolTable::new(),
+            breakpoints: BTreeSet::new(),
+            max_instructions: MAX_INSTRUCTIONS,
+            next_memory: pc,
+            next_disassembly: pc,
+        }
+    }
+
+    pub(crate) fn prompt(&self) -> String {
+        format!("(C:${:04x}) ", self.emulator.registers.program_counter)
+    }
+
+    /// Execute a single command line, writing any output.
+    pub(crate) fn execute<W: Write>(&mut self, line: &str, out: &mut W) -> io::Result<Outcome> {
+        let words = split_words(line);
+        let (command, arguments) = match words.split_first() {
+            Some((command, arguments)) => (command.as_str(), arguments),
+            None => return Ok(Outcome::Continue),
+        };
+
+        let result = match command {
+            "m" => self.memory(arguments, out),
+            ">" => self.modify(arguments),
+            "d" => self.disassemble(arguments, out),
+            "t" => self.trace(arguments, out),
+            "r" => self.registers(arguments, out),
+            "bt" => self.backtrace(out),
+            "z" => self.step(arguments, false, out),
+            "n" => self.step(arguments, true, out),
+            "g" => self.go(arguments, out),
+            "b" => self.set_breakpoint(arguments, out),
+            "del" => self.delete_breakpoint(arguments),
+            "l" => self.load(arguments, out),
+            "s" => self.save(arguments),
+            "f" => self.fill(arguments),
+            "c" => self.compare(arguments, out),
+            "h" => self.hunt(arguments, out),
+            "ll" => self.load_symbols(arguments, out),
+            "?" | "help" => writeln!(out, "{}", HELP).map_err(|e| e.to_string()),
+            "x" | "q" => return Ok(Outcome::Exit),
+            _ => Err(format!("Unknown command '{}', type ? for help.", command)),
+        };
+
+        if let Err(message) = result {
+            writeln!(out, "Error: {}", message)?;
+        }
+
+        Ok(Outcome::Continue)
+    }
+
+    fn memory<W: Write>(&mut self, arguments: &[String], out: &mut W) -> CommandResult {
+        let start = optional_address(&self.symbols, arguments, 0)?.unwrap_or(self.next_memory);
+        let end = optional_address(&self.symbols, arguments, 1)?
+            .unwrap_or_else(|| start.wrapping_add(MEMORY_LINES * BYTES_PER_LINE - 1));
+
+        let mut line_start = start as u32;
+        while line_start <= end as u32 {
+            let line_end = (line_start + BYTES_PER_LINE as u32 - 1).min(end as u32);
+            let bytes: Vec<u8> = (line_start..=line_end)
+                .map(|a| self.emulator.memory.peek(a as u16))
+                .collect();
+
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            let text: String = bytes.iter().map(|b| printable(*b)).collect();
+
+            writeln!(out, "{:04x}  {:<48} {}", line_start, hex.join(" "), text)
+                .map_err(|e| e.to_string())?;
+
+            line_start = line_end + 1;
+        }
+
+        self.next_memory = (line_start & 0xFFFF) as u16;
+        Ok(())
+    }
+
+    fn modify(&mut self, arguments: &[String]) -> CommandResult {
+        let address = required_address(&self.symbols, arguments, 0)?;
+        let bytes = parse_bytes(&arguments[1..])?;
+
+        for (i, b) in bytes.into_iter().enumerate() {
+            self.emulator
+                .memory
+                .write(address.wrapping_add(i as u16), b);
+        }
+
+        Ok(())
+    }
+
+    fn disassemble<W: Write>(&mut self, arguments: &[String], out: &mut W) -> CommandResult {
+        let start = optional_address(&self.symbols, arguments, 0)?.unwrap_or(self.next_disassembly);
+        let end = optional_address(&self.symbols, arguments, 1)?;
+
+        let mut address = start;
+        let mut lines = 0;
+        loop {
+            let done = match end {
+                Some(end) => address > end || address < start,
+                None => lines == DISASSEMBLY_LINES,
+            };
+            if done {
+                break;
+            }
+
+            if let Some(name) = self.symbols.name(address) {
+                writeln!(out, "{}:", name).map_err(|e| e.to_string())?;
+            }
+
+            let instruction = disassemble(&self.emulator.memory, address);
+            self.print_instruction(address, out)?;
+
+            address = address.wrapping_add(instruction.length());
+            lines += 1;
+        }
+
+        self.next_disassembly = address;
+        Ok(())
+    }
+
+    fn print_instruction<W: Write>(&self, address: u16, out: &mut W) -> CommandResult {
+        let instruction = disassemble(&self.emulator.memory, address);
+        let bytes: Vec<String> = instruction
+            .bytes()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        writeln!(
+            out,
+            "{:04x}  {:<9} {}",
+            address,
+            bytes.join(" "),
+            instruction.format(|a| self.symbols.label(a))
+        )
+        .map_err(|e| e.to_string())
+    }
+
+    fn backtrace<W: Write>(&mut self, out: &mut W) -> CommandResult {
+        let pc = self.emulator.registers.program_counter;
+        let backtrace = self.emulator.observer.backtrace(pc, &self.symbols);
+
+        write!(out, "{}", backtrace).map_err(|e| e.to_string())
+    }
+
+    fn registers<W: Write>(&mut self, arguments: &[String], out: &mut W) -> CommandResult {
+        for assignment in arguments {
+            let (register, value) = assignment
+                .split_once('=')
+                .ok_or("Expected register=value.")?;
+            let value = parse_number(value)?;
+
+            let r = &mut self.emulator.registers;
+            match (register, value) {
+                ("pc", v) => r.program_counter = v,
+                ("a", v) if v <= 0xFF => r.accumulator = v as u8,
+                ("x", v) if v <= 0xFF => r.x = v as u8,
+                ("y", v) if v <= 0xFF => r.y = v as u8,
+                ("sp", v) if v <= 0xFF => r.stack_pointer = v as u8,
+                ("p", v) if v <= 0xFF => {
+                    for (bit, flag) in FLAGS.iter().enumerate() {
+                        if *flag != Flag::Reserved {
+                            r.status.set_to(*flag, v & (0x80 >> bit) != 0);
+                        }
+                    }
+                }
+                _ => return Err(format!("Cannot assign {}.", assignment)),
+            }
+        }
+
+        self.print_registers(out)
+    }
+
+    fn print_registers<W: Write>(&self, out: &mut W) -> CommandResult {
+        writeln!(out, "{}", self.emulator.registers).map_err(|e| e.to_string())
+    }
+
+    fn step<W: Write>(&mut self, arguments: &[String], over: bool, out: &mut W) -> CommandResult {
+        let count = optional_number(arguments, 0)?.unwrap_or(1);
+
+        for _ in 0..count {
+            self.print_instruction(self.emulator.registers.program_counter, out)?;
+
+            let pc = self.emulator.registers.program_counter;
+            if over && self.emulator.memory.peek(pc) == JSR::ABSOLUTE {
+                let stack_pointer = self.emulator.registers.stack_pointer;
+                let reason = self.run_until(|e| {
+                    e.registers.program_counter == pc.wrapping_add(3)
+                        && e.registers.stack_pointer == stack_pointer
+                });
+
+                if let Some(reason) = reason {
+                    writeln!(out, "{}", reason).map_err(|e| e.to_string())?;
+                    break;
+                }
+            } else {
+                self.emulator.execute_next();
+            }
+        }
+
+        self.after_execution(out)
+    }
+
+    fn trace<W: Write>(&mut self, arguments: &[String], out: &mut W) -> CommandResult {
+        let count = optional_number(arguments, 0)?.unwrap_or(1);
+
+        for _ in 0..count {
+            writeln!(out, "{}", trace(&self.emulator, &self.symbols)).map_err(|e| e.to_string())?;
+            self.emulator.execute_next();
+
+            if self.emulator.is_trapped() {
+                let pc = self.emulator.registers.program_counter;
+                writeln!(out, "Trapped at ${:04x}.", pc).map_err(|e| e.to_string())?;
+                break;
+            }
+        }
+
+        self.next_disassembly = self.emulator.registers.program_counter;
+        Ok(())
+    }
+
+    fn go<W: Write>(&mut self, arguments: &[String], out: &mut W) -> CommandResult {
+        if let Some(address) = optional_address(&self.symbols, arguments, 0)? {
+            self.emulator.registers.program_counter = address;
+        }
+
+        if let Some(reason) = self.run_until(|_| false) {
+            writeln!(out, "{}", reason).map_err(|e| e.to_string())?;
+        }
+
+        self.after_execution(out)
+    }
+
+    /// Run until the condition holds, a breakpoint is hit, execution traps or the instruction
+    /// limit is reached. Returns why execution stopped unless it was the condition.
+    fn run_until<F: Fn(&Emulator<DefaultMemory, CallStack>) -> bool>(
+        &mut self,
+        condition: F,
+    ) -> Option<String> {
+        for _ in 0..self.max_instructions {
+            self.emulator.execute_next();
+            let pc = self.emulator.registers.program_counter;
+
+            if condition(&self.emulator) {
+                return None;
+            } else if self.breakpoints.contains(&pc) {
+                return Some(format!("Breakpoint at ${:04x}.", pc));
+            } else if self.emulator.is_trapped() {
+                return Some(format!("Trapped at ${:04x}.", pc));
+            }
+        }
+
+        Some(format!(
+            "Stopped after {} instructions.",
+            self.max_instructions
+        ))
+    }
+
+    fn after_execution<W: Write>(&mut self, out: &mut W) -> CommandResult {
+        let pc = self.emulator.registers.program_counter;
+        self.next_disassembly = pc;
+
+        self.print_registers(out)?;
+        self.print_instruction(pc, out)
+    }
+
+    fn set_breakpoint<W: Write>(&mut self, arguments: &[String], out: &mut W) -> CommandResult {
+        match optional_address(&self.symbols, arguments, 0)? {
+            Some(address) => {
+                self.breakpoints.insert(address);
+            }
+            None => {
+                for address in &self.breakpoints {
+                    writeln!(out, "${:04x}", address).map_err(|e| e.to_string())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn delete_breakpoint(&mut self, arguments: &[String]) -> CommandResult {
+        match optional_address(&self.symbols, arguments, 0)? {
+            Some(address) => {
+                if !self.breakpoints.remove(&address) {
+                    return Err(format!("No breakpoint at ${:04x}.", address));
+                }
+            }
+            None => self.breakpoints.clear(),
+        }
+
+        Ok(())
+    }
+
+    fn load<W: Write>(&mut self, arguments: &[String], out: &mut W) -> CommandResult {
+        let file = arguments.first().ok_or("Expected a file name.")?;
+        let address = required_address(&self.symbols, arguments, 1)?;
+
+        let bytes = fs::read(file).map_err(|e| e.to_string())?;
+        if address as usize + bytes.len() > 0x10000 {
+            return Err(format!(
+                "{} does not fit in memory at ${:04x}.",
+                file, address
+            ));
+        }
+
+        for (i, b) in bytes.iter().enumerate() {
+            self.emulator.memory.write(address + i as u16, *b);
+        }
+
+        writeln!(
+            out,
+            "Loaded ${:04x} bytes at ${:04x}.",
+            bytes.len(),
+            address
+        )
+        .map_err(|e| e.to_string())
+    }
+
+    fn load_symbols<W: Write>(&mut self, arguments: &[String], out: &mut W) -> CommandResult {
+        let file = arguments.first().ok_or("Expected a file name.")?;
+        let contents = fs::read_to_string(file).map_err(|e| e.to_string())?;
+
+        let loaded = SymbolTable::parse(&contents).len();
+        self.symbols.extend(&contents);
+
+        writeln!(out, "Loaded {} symbols.", loaded).map_err(|e| e.to_string())
+    }
+
+    fn save(&mut self, arguments: &[String]) -> CommandResult {
+        let file = arguments.first().ok_or("Expected a file name.")?;
+        let (start, end) = required_range(&self.symbols, arguments, 1)?;
+
+        let bytes: Vec<u8> = (start..=end)
+            .map(|a| self.emulator.memory.peek(a))
+            .collect();
+        fs::write(file, bytes).map_err(|e| e.to_string())
+    }
+
+    fn fill(&mut self, arguments: &[String]) -> CommandResult {
+        let (start, end) = required_range(&self.symbols, arguments, 0)?;
+        let pattern = parse_bytes(&arguments[2..])?;
+        if pattern.is_empty() {
+            return Err("Expected bytes to fill with.".to_string());
+        }
+
+        for (i, address) in (start..=end).enumerate() {
+            self.emulator
+                .memory
+                .write(address, pattern[i % pattern.len()]);
+        }
+
+        Ok(())
+    }
+
+    fn compare<W: Write>(&mut self, arguments: &[String], out: &mut W) -> CommandResult {
+        let (start, end) = required_range(&self.symbols, arguments, 0)?;
+        let destination = required_address(&self.symbols, arguments, 2)?;
+
+        for (i, address) in (start..=end).enumerate() {
+            let other = destination.wrapping_add(i as u16);
+            let (a, b) = (
+                self.emulator.memory.peek(address),
+                self.emulator.memory.peek(other),
+            );
+
+            if a != b {
+                writeln!(out, "${:04x} {:02x}  ${:04x} {:02x}", address, a, other, b)
+                    .map_err(|e| e.to_string())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn hunt<W: Write>(&mut self, arguments: &[String], out: &mut W) -> CommandResult {
+        let (start, end) = required_range(&self.symbols, arguments, 0)?;
+        let pattern = parse_bytes(&arguments[2..])?;
+        if pattern.is_empty() {
+            return Err("Expected bytes to hunt for.".to_string());
+        }
+
+        let memory: Vec<u8> = (start..=end)
+            .map(|a| self.emulator.memory.peek(a))
+            .collect();
+        for (i, window) in memory.windows(pattern.len()).enumerate() {
+            if window == pattern.as_slice() {
+                writeln!(out, "${:04x}", start as usize + i).map_err(|e| e.to_string())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn printable(b: u8) -> char {
+    if b.is_ascii_graphic() || b == b' ' {
+        b as char
+    } else {
+        '.'
+    }
+}
+
+/// Split a line into words, keeping quoted words such as file names together.
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(word.clone());
+                    word.clear();
+                }
+            }
+            c => word.push(c),
+        }
+    }
+
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
+
+/// Parse a hexadecimal number, optionally prefixed with `$`.
+fn parse_number(s: &str) -> Result<u16, String> {
+    cli::parse_address(s).map_err(|_| format!("Invalid number '{}'.", s))
+}
+
+fn parse_bytes(words: &[String]) -> Result<Vec<u8>, String> {
+    words
+        .iter()
+        .map(|w| match parse_number(w)? {
+            b if b <= 0xFF => Ok(b as u8),
+            _ => Err(format!("Invalid byte '{}'.", w)),
+        })
+        .collect()
+}
+
+/// Parse an address, which is either a number or a symbol. Symbols may be prefixed with `.` to
+/// tell them apart from numbers.
+fn parse_address(symbols: &SymbolTable, s: &str) -> Result<u16, String> {
+    if let Some(name) = s.strip_prefix('.') {
+        return symbols
+            .address(name)
+            .ok_or_else(|| format!("Unknown symbol '{}'.", name));
+    }
+
+    parse_number(s).or_else(|e| symbols.address(s).ok_or(e))
+}
+
+fn optional_number(arguments: &[String], index: usize) -> Result<Option<u16>, String> {
+    arguments.get(index).map(|a| parse_number(a)).transpose()
+}
+
+fn optional_address(
+    symbols: &SymbolTable,
+    arguments: &[String],
+    index: usize,
+) -> Result<Option<u16>, String> {
+    arguments
+        .get(index)
+        .map(|a| parse_address(symbols, a))
+        .transpose()
+}
+
+fn required_address(
+    symbols: &SymbolTable,
+    arguments: &[String],
+    index: usize,
+) -> Result<u16, String> {
+    optional_address(symbols, arguments, index)?.ok_or_else(|| "Expected an address.".to_string())
+}
+
+fn required_range(
+    symbols: &SymbolTable,
+    arguments: &[String],
+    index: usize,
+) -> Result<(u16, u16), String> {
+    let start = required_address(symbols, arguments, index)?;
+    let end = required_address(symbols, arguments, index + 1)?;
+
+    if end < start {
+        return Err("The end of a range cannot precede its start.".to_string());
+    }
+
+    Ok((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use commands::{split_words, Monitor, Outcome};
+    use emulator::debug::callstack::CallStack;
+    use emulator::emulator::instructions::opcodes::*;
+    use emulator::emulator::Emulator;
+    use emulator::memory::default::DefaultMemory;
+
+    fn monitor(program: Vec<Vec<u8>>) -> Monitor {
+        let mut memory = DefaultMemory::empty();
+        memory.load(program.into_iter().flatten().collect(), 0x600);
+        memory.set_program_counter(0x600);
+
+        Monitor::new(Emulator::with_observer(memory, CallStack::new()))
+    }
+
+    fn execute(monitor: &mut Monitor, line: &str) -> String {
+        let mut out = Vec::new();
+        assert_eq!(Outcome::Continue, monitor.execute(line, &mut out).unwrap());
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_split_words() {
+        assert_eq!(
+            vec!["l", "my file.bin", "0600"],
+            split_words("  l \"my file.bin\"   0600 ")
+        );
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut m = monitor(vec![]);
+
+        execute(&mut m, "> 0600 48 49 $ff");
+        let output = execute(&mut m, "m 0600 0602");
+
+        assert_eq!(
+            "0600  48 49 ff                                         HI.\n",
+            output
+        );
+    }
+
+    #[test]
+    fn test_memory_continues() {
+        let mut m = monitor(vec![]);
+
+        let first = execute(&mut m, "m 0600");
+        let second = execute(&mut m, "m");
+
+        assert_eq!(8, first.lines().count());
+        assert!(second.starts_with("0680"));
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let mut m = monitor(vec![LDA::immediate(0x42), STA::absolute(0x0200)]);
+
+        let output = execute(&mut m, "d 0600 0602");
+
+        assert_eq!(
+            "0600  a9 42     lda #$42\n0602  8d 00 02  sta $0200\n",
+            output
+        );
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut m = monitor(vec![]);
+
+        let output = execute(&mut m, "r a=12 x=34 pc=0700 p=81");
+
+        assert_eq!(
+            "  pc  a  x  y  sp nv-bdizc\n0700  12 34 00 ff 10100001\n",
+            output
+        );
+        assert!(execute(&mut m, "r a=100").starts_with("Error"));
+    }
+
+    #[test]
+    fn test_step() {
+        let mut m = monitor(vec![LDX::immediate(5), INX::implied()]);
+
+        execute(&mut m, "z 2");
+
+        assert_eq!(6, m.emulator.registers.x);
+        assert_eq!(0x603, m.emulator.registers.program_counter);
+    }
+
+    #[test]
+    fn test_next_steps_over_subroutine() {
+        let mut m = monitor(vec![JSR::absolute(0x610), NOP::implied(), NOP::implied()]);
+        m.emulator
+            .memory
+            .load([INX::implied(), RTS::implied()].concat(), 0x610);
+
+        execute(&mut m, "n");
+
+        assert_eq!(1, m.emulator.registers.x);
+        assert_eq!(0x603, m.emulator.registers.program_counter);
+    }
+
+    #[test]
+    fn test_go_until_breakpoint() {
+        let mut m = monitor(vec![INX::implied(), INX::implied(), JMP::absolute(0x600)]);
+
+        execute(&mut m, "b 0601");
+        assert_eq!("$0601\n", execute(&mut m, "b"));
+
+        let output = execute(&mut m, "g");
+        assert!(output.starts_with("Breakpoint at $0601."));
+        assert_eq!(1, m.emulator.registers.x);
+
+        execute(&mut m, "del 0601");
+        assert!(execute(&mut m, "del 0601").starts_with("Error"));
+    }
+
+    #[test]
+    fn test_go_until_trap() {
+        let mut m = monitor(vec![JMP::absolute(0x600)]);
+        m.emulator
+            .registers
+            .status
+            .set_to(emulator::emulator::registers::Flag::Interrupt, true);
+
+        assert!(execute(&mut m, "g").starts_with("Trapped at $0600."));
+    }
+
+    #[test]
+    fn test_go_until_limit() {
+        let mut m = monitor(vec![INX::implied(), JMP::absolute(0x600)]);
+        m.max_instructions = 10;
+
+        assert!(execute(&mut m, "g").starts_with("Stopped after 10 instructions."));
+        assert_eq!(5, m.emulator.registers.x);
+    }
+
+    #[test]
+    fn test_symbols() {
+        let mut m = monitor(vec![JSR::absolute(0x610), JMP::absolute(0x603)]);
+        m.emulator.memory.load(RTS::implied(), 0x610);
+        m.symbols.insert("main", 0x600);
+        m.symbols.insert("done", 0x603);
+        m.symbols.insert("seed", 0x610);
+
+        assert_eq!(
+            "main:\n0600  20 10 06  jsr seed\ndone:\n0603  4c 03 06  jmp done\n",
+            execute(&mut m, "d main 0603")
+        );
+
+        execute(&mut m, "b .seed");
+        assert!(execute(&mut m, "g").starts_with("Breakpoint at $0610."));
+        assert!(execute(&mut m, "b .missing").starts_with("Error: Unknown symbol"));
+    }
+
+    #[test]
+    fn test_load_symbols() {
+        let path = env::temp_dir().join("monitor_test_load_symbols.lbl");
+        fs::write(&path, "al C:0610 .seed\nal C:0620 .loop\n").unwrap();
+
+        let mut m = monitor(vec![]);
+        let output = execute(&mut m, &format!("ll \"{}\"", path.to_str().unwrap()));
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!("Loaded 2 symbols.\n", output);
+        assert_eq!(Some(0x620), m.symbols.address("loop"));
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut m = monitor(vec![LDX::immediate(5), INX::implied()]);
+
+        let output = execute(&mut m, "t 2");
+
+        assert_eq!(2, output.lines().count());
+        assert!(output.lines().nth(1).unwrap().starts_with("0602  e8"));
+        assert_eq!(6, m.emulator.registers.x);
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let mut m = monitor(vec![JSR::absolute(0x610)]);
+        m.emulator.memory.load(NOP::implied(), 0x610);
+        m.symbols.insert("main", 0x600);
+        m.symbols.insert("seed", 0x610);
+
+        execute(&mut m, "z");
+
+        assert_eq!(
+            "#0  0610  seed\n#1  0600  main (jsr seed)\n",
+            execute(&mut m, "bt")
+        );
+    }
+
+    #[test]
+    fn test_fill_compare_hunt() {
+        let mut m = monitor(vec![]);
+
+        execute(&mut m, "f 1000 1007 01 02");
+        execute(&mut m, "f 2000 2007 01 02");
+        execute(&mut m, "> 2003 ff");
+
+        assert_eq!("$1003 02  $2003 ff\n", execute(&mut m, "c 1000 1007 2000"));
+        assert_eq!(
+            "$1001\n$1003\n$1005\n",
+            execute(&mut m, "h 1000 1007 02 01")
+        );
+    }
+
+    #[test]
+    fn test_save_load() {
+        let path = env::temp_dir().join("monitor_test_save_load.bin");
+        let file = path.to_str().unwrap();
+
+        let mut m = monitor(vec![]);
+        execute(&mut m, "f 1000 100f aa");
+        execute(&mut m, &format!("s \"{}\" 1000 100f", file));
+        execute(&mut m, &format!("l \"{}\" 3000", file));
+        let beyond = execute(&mut m, &format!("l \"{}\" fff8", file));
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!("", execute(&mut m, "c 1000 100f 3000"));
+        assert!(beyond.starts_with("Error:") && beyond.contains("does not fit in memory"));
+        assert_eq!(0x00, m.emulator.memory.memory[0xfff8]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut m = monitor(vec![]);
+
+        assert!(execute(&mut m, "nonsense").starts_with("Error: Unknown command"));
+        assert!(execute(&mut m, "m zz").starts_with("Error: Invalid number"));
+        assert!(execute(&mut m, "f 2000 1000 00").starts_with("Error"));
+    }
+
+    #[test]
+    fn test_exit() {
+        let mut m = monitor(vec![]);
+        let mut out = Vec::new();
+
+        assert_eq!(Outcome::Exit, m.execute("x", &mut out).unwrap());
+    }
+}
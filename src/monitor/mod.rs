@@ -0,0 +1,92 @@
+extern crate emulator;
+
+use std::env;
+use std::fs;
+use std::io::{stdin, stdout, BufRead, Write};
+use std::process;
+
+use commands::{Monitor, Outcome};
+use emulator::debug::callstack::CallStack;
+use emulator::debug::symbols::SymbolTable;
+use emulator::emulator::Emulator;
+use emulator::memory::default::DefaultMemory;
+
+#[path = "../cli/mod.rs"]
+mod cli;
+mod commands;
+
+const USAGE: &str = "Usage: monitor [--labels file] [file [load address [start address]]]";
+
+fn parse_address(s: &str) -> u16 {
+    cli::parse_address(s).unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        process::exit(1)
+    })
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut symbols = SymbolTable::new();
+
+    // Symbols can be given with `--labels`, in any of the formats SymbolTable understands.
+    while let Some(index) = args.iter().position(|a| a == "--labels") {
+        let file = args.get(index + 1).cloned().unwrap_or_else(|| {
+            eprintln!("{}", USAGE);
+            process::exit(1)
+        });
+
+        let contents = fs::read_to_string(&file).unwrap_or_else(|e| {
+            eprintln!("Cannot read {}: {}", file, e);
+            process::exit(1)
+        });
+
+        symbols.extend(&contents);
+        args.drain(index..index + 2);
+    }
+
+    let mut memory = DefaultMemory::empty();
+    let mut start = None;
+
+    // Load the program, and start executing where it was loaded unless told otherwise.
+    if let Some(file) = args.first() {
+        let program = cli::read_file(file);
+
+        let load_address = args.get(1).map_or(0, |a| parse_address(a));
+        let start_address = args.get(2).map_or(load_address, |a| parse_address(a));
+
+        if load_address as usize + program.len() > memory.memory.len() {
+            eprintln!("{} does not fit in memory at ${:04x}.", file, load_address);
+            process::exit(1)
+        }
+
+        memory.load(program, load_address as usize);
+        start = Some(start_address);
+    }
+
+    let mut emulator = Emulator::with_observer(memory, CallStack::new());
+    if let Some(start) = start {
+        emulator.registers.program_counter = start;
+    }
+
+    let mut monitor = Monitor::new(emulator);
+    monitor.symbols = symbols;
+    let stdin = stdin();
+    let mut stdout = stdout();
+
+    print!("{}", monitor.prompt());
+    stdout.flush().unwrap();
+
+    for line in stdin.lock().lines() {
+        match monitor.execute(&line.unwrap(), &mut stdout) {
+            Ok(Outcome::Continue) => {}
+            Ok(Outcome::Exit) => break,
+            Err(e) => {
+                eprintln!("{}", e);
+                break;
+            }
+        }
+
+        print!("{}", monitor.prompt());
+        stdout.flush().unwrap();
+    }
+}
@@ -0,0 +1,293 @@
+extern crate emulator;
+
+use std::env;
+use std::io::{stdin, stdout, Write};
+use std::process;
+
+use cli::{parse_address, read_file};
+use emulator::emulator::Emulator;
+use emulator::memory::char_io::CharIo;
+use emulator::memory::default::DefaultMemory;
+use emulator::memory::Memory;
+
+#[path = "../cli/mod.rs"]
+mod cli;
+
+const USAGE: &str = "\
+Usage: run6502 [options] file
+
+Options:
+  --load address        Load the file at this address, 0 by default.
+  --start address       Start executing here, rather than at the reset vector when the file
+                        covers it, or the load address otherwise.
+  --cycles count        Stop after this many cycles.
+  --stop-at address     Stop when the program counter reaches this address. Can be repeated.
+  --stop-on-brk         Stop at a BRK instruction.
+  --io address          Read stdin and write stdout through a character port at this address.
+  --dump start:end      Print memory from start to end (inclusive) when done.
+  --quiet               Do not print the registers when done.
+
+Execution always stops at a trap: a jump or branch to itself. Addresses are hexadecimal.";
+
+/// Exit code when the cycle limit is reached, telling it apart from a program that stopped.
+const EXIT_CYCLE_LIMIT: i32 = 2;
+
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    file: String,
+    load: u16,
+    start: Option<u16>,
+    cycles: Option<u64>,
+    stop_at: Vec<u16>,
+    stop_on_brk: bool,
+    io: Option<u16>,
+    dump: Vec<(u16, u16)>,
+    quiet: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stop {
+    Trap(u16),
+    Address(u16),
+    Brk(u16),
+    CycleLimit,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut file = None;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}.", arg))
+        };
+
+        match arg.as_str() {
+            "--load" => options.load = parse_address(value()?)?,
+            "--start" => options.start = Some(parse_address(value()?)?),
+            "--cycles" => {
+                let count = value()?;
+                let count = count
+                    .parse()
+                    .map_err(|_| format!("Invalid cycle count '{}'.", count))?;
+                options.cycles = Some(count);
+            }
+            "--stop-at" => options.stop_at.push(parse_address(value()?)?),
+            "--stop-on-brk" => options.stop_on_brk = true,
+            "--io" => options.io = Some(parse_address(value()?)?),
+            "--dump" => {
+                let range = value()?;
+                let (start, end) = range.split_once(':').unwrap_or((range, ""));
+                let (start, end) = (parse_address(start)?, parse_address(end)?);
+                if end < start {
+                    return Err(format!("Invalid range '{}'.", range));
+                }
+                options.dump.push((start, end));
+            }
+            "--quiet" => options.quiet = true,
+            _ if arg.starts_with("--") => return Err(format!("Unknown option '{}'.", arg)),
+            _ if file.is_none() => file = Some(arg.clone()),
+            _ => return Err(format!("Unexpected argument '{}'.", arg)),
+        }
+    }
+
+    options.file = file.ok_or_else(|| "No file given.".to_string())?;
+    Ok(options)
+}
+
+/// Execute until one of the stop conditions is met.
+fn run<M: Memory>(emulator: &mut Emulator<M>, options: &Options) -> Stop {
+    loop {
+        let pc = emulator.next_instruction_address();
+        if options.stop_at.contains(&pc) {
+            return Stop::Address(pc);
+        }
+        if options.stop_on_brk && emulator.memory.peek(pc) == 0x00 {
+            return Stop::Brk(pc);
+        }
+        if options.cycles.is_some_and(|c| emulator.cycles >= c) {
+            return Stop::CycleLimit;
+        }
+
+        emulator.execute_next();
+        if emulator.is_trapped() {
+            return Stop::Trap(emulator.registers.program_counter);
+        }
+    }
+}
+
+/// Describe how the program stopped, followed by the registers and memory asked for.
+fn report<M: Memory>(emulator: &Emulator<M>, options: &Options, stop: &Stop) -> String {
+    let mut report = match stop {
+        Stop::Trap(address) => format!("Trapped at ${:04x}", address),
+        Stop::Address(address) => format!("Reached ${:04x}", address),
+        Stop::Brk(address) => format!("BRK at ${:04x}", address),
+        Stop::CycleLimit => "Cycle limit reached".to_string(),
+    };
+    report += &format!(" after {} cycles.\n", emulator.cycles);
+
+    if !options.quiet {
+        report += &format!("{}\n", emulator.registers);
+    }
+
+    for (start, end) in options.dump.iter() {
+        let mut line_start = *start as u32;
+        while line_start <= *end as u32 {
+            let line_end = (line_start + 15).min(*end as u32);
+            let bytes: Vec<String> = (line_start..=line_end)
+                .map(|a| format!("{:02x}", emulator.memory.peek(a as u16)))
+                .collect();
+
+            report += &format!("{:04x}  {}\n", line_start, bytes.join(" "));
+            line_start = line_end + 1;
+        }
+    }
+
+    report
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = parse_options(&args).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(1)
+    });
+
+    let program = read_file(&options.file);
+
+    let end = options.load as usize + program.len();
+    let mut memory = DefaultMemory::empty();
+    if end > memory.memory.len() {
+        eprintln!(
+            "{} does not fit in memory at ${:04x}.",
+            options.file, options.load
+        );
+        process::exit(1)
+    }
+    memory.load(program, options.load as usize);
+
+    // Use the reset vector if the file sets it.
+    let covers_reset_vector = options.load <= 0xfffc && end > 0xfffd;
+    let start = match options.start {
+        Some(start) => Some(start),
+        None if !covers_reset_vector => Some(options.load),
+        None => None,
+    };
+
+    // The report goes to stderr, so that stdout only holds what the program writes.
+    let (stop, report) = match options.io {
+        Some(port) => {
+            let mut emulator = Emulator::new(CharIo::new(memory, port, stdin(), stdout()));
+            if let Some(start) = start {
+                emulator.registers.program_counter = start;
+            }
+            let stop = run(&mut emulator, &options);
+            emulator.memory.output().flush().unwrap();
+            (stop, report(&emulator, &options, &stop))
+        }
+        None => {
+            let mut emulator = Emulator::new(memory);
+            if let Some(start) = start {
+                emulator.registers.program_counter = start;
+            }
+            let stop = run(&mut emulator, &options);
+            (stop, report(&emulator, &options, &stop))
+        }
+    };
+    eprint!("{}", report);
+
+    if stop == Stop::CycleLimit {
+        process::exit(EXIT_CYCLE_LIMIT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use emulator::emulator::instructions::opcodes::*;
+    use emulator::emulator::Emulator;
+    use emulator::memory::default::DefaultMemory;
+    use {parse_options, report, run, Options, Stop};
+
+    fn options(args: &str) -> Result<Options, String> {
+        let args: Vec<String> = args.split_whitespace().map(|s| s.to_string()).collect();
+        parse_options(&args)
+    }
+
+    fn emulator(program: Vec<Vec<u8>>) -> Emulator<DefaultMemory> {
+        let mut memory = DefaultMemory::empty();
+        memory.load(program.concat(), 0x600);
+        memory.set_program_counter(0x600);
+
+        Emulator::new(memory)
+    }
+
+    #[test]
+    fn test_options() {
+        let o = options("--load 600 --stop-at $700 --stop-at 800 --dump 0:f --stop-on-brk a.bin")
+            .unwrap();
+
+        assert_eq!("a.bin", o.file);
+        assert_eq!(0x600, o.load);
+        assert_eq!(None, o.start);
+        assert_eq!(vec![0x700, 0x800], o.stop_at);
+        assert_eq!(vec![(0, 0xf)], o.dump);
+        assert!(o.stop_on_brk);
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        assert!(options("").is_err());
+        assert!(options("--load").is_err());
+        assert!(options("--load xyz a.bin").is_err());
+        assert!(options("--dump 10:5 a.bin").is_err());
+        assert!(options("--frobnicate a.bin").is_err());
+        assert!(options("a.bin b.bin").is_err());
+    }
+
+    #[test]
+    fn test_stop_conditions() {
+        let program = vec![INX::implied(), BRK::immediate(0), JMP::absolute(0x604)];
+
+        let mut e = emulator(program.clone());
+        assert_eq!(
+            Stop::Address(0x601),
+            run(&mut e, &options("--stop-at 601 a.bin").unwrap())
+        );
+
+        let mut e = emulator(program.clone());
+        assert_eq!(
+            Stop::Brk(0x601),
+            run(&mut e, &options("--stop-on-brk a.bin").unwrap())
+        );
+
+        let mut e = emulator(program.clone());
+        assert_eq!(
+            Stop::CycleLimit,
+            run(&mut e, &options("--cycles 1 a.bin").unwrap())
+        );
+        assert_eq!(1, e.registers.x);
+
+        let mut e = emulator(vec![INX::implied(), JMP::absolute(0x601)]);
+        assert_eq!(Stop::Trap(0x601), run(&mut e, &options("a.bin").unwrap()));
+    }
+
+    #[test]
+    fn test_report() {
+        let mut e = emulator(vec![LDA::immediate(0x42), STA::zero_page(0x11)]);
+        e.run(2);
+
+        assert_eq!(
+            "Reached $0604 after 5 cycles.\n  \
+             pc  a  x  y  sp nv-bdizc\n\
+             0604  42 00 00 ff 00100100\n\
+             0010  00 42\n",
+            report(
+                &e,
+                &options("--dump 10:11 a.bin").unwrap(),
+                &Stop::Address(0x604)
+            )
+        );
+    }
+}
@@ -0,0 +1,42 @@
+extern crate emulator;
+
+use std::fs;
+
+use emulator::emulator::Emulator;
+use emulator::memory::default::DefaultMemory;
+use emulator::memory::Memory;
+
+/// Where the test stores its result: 0 when it passed, 1 when it failed.
+const ERROR: u16 = 0x0b;
+
+/// The 65C02 STP instruction, which the test executes once done. See readme for details.
+const STP: u8 = 0xdb;
+
+// Klaus Dormann's decimal test, which checks ADC and SBC in decimal mode for every pair of
+// operands, both with and without carry, including invalid BCD.
+#[test]
+#[ignore = "needs tests/decimal.bin assembled with as65"]
+fn decimal() {
+    let program = fs::read("tests/decimal.bin").expect("tests/decimal.bin not found");
+
+    let mut memory = DefaultMemory::empty();
+    memory.load(program, 0);
+    memory.set_program_counter(0x200);
+
+    let mut emulator = Emulator::new(memory);
+    for _ in 0..100_000_000 {
+        let pc = emulator.registers.program_counter;
+        if emulator.memory.read(pc) == STP || emulator.is_trapped() {
+            assert_eq!(
+                0,
+                emulator.memory.read(ERROR),
+                "failed, stopped at {:04x}",
+                pc
+            );
+            return;
+        }
+        emulator.execute_next();
+    }
+
+    panic!("did not finish");
+}
@@ -1,5 +1,6 @@
 extern crate emulator;
 
+use emulator::emulator::run::StopReason;
 use emulator::emulator::Emulator;
 use emulator::memory::default::DefaultMemory;
 
@@ -13,11 +14,5 @@ fn functional() {
 
     let mut emulator = Emulator::new(memory);
 
-    let mut program_counter = 0x0u16;
-    while program_counter != emulator.registers.program_counter {
-        program_counter = emulator.registers.program_counter;
-        emulator.execute_next();
-    }
-
-    assert_eq!(0x3469, program_counter)
+    assert_eq!(StopReason::Trap(0x3469), emulator.run(100_000_000))
 }
@@ -0,0 +1,42 @@
+extern crate emulator;
+
+use std::fs;
+
+use emulator::emulator::instructions::opcodes::{Absolute, JMP};
+use emulator::emulator::Emulator;
+use emulator::memory::default::DefaultMemory;
+use emulator::memory::feedback::{update, InterruptFeedback, DEFAULT_PORT};
+use emulator::memory::Memory;
+
+const START: u16 = 0x400;
+
+// Klaus Dormann's interrupt test, which triggers IRQ and NMI by writing to a feedback port.
+#[test]
+#[ignore = "needs tests/interrupt.bin assembled with as65"]
+fn interrupt() {
+    let program = fs::read("tests/interrupt.bin").expect("tests/interrupt.bin not found");
+
+    let mut memory = DefaultMemory::empty();
+    memory.load(program, 0);
+    memory.set_program_counter(START);
+
+    let mut emulator = Emulator::new(InterruptFeedback::new(memory, DEFAULT_PORT));
+    for _ in 0..10_000_000 {
+        update(&mut emulator);
+        emulator.execute_next();
+        if emulator.is_trapped() {
+            break;
+        }
+    }
+    assert!(emulator.is_trapped(), "did not finish");
+
+    // Every failure traps, and so does success, but only success is followed by a jump back to
+    // the start.
+    let pc = emulator.registers.program_counter;
+    let next = [
+        emulator.memory.read(pc + 3),
+        emulator.memory.read(pc + 4),
+        emulator.memory.read(pc + 5),
+    ];
+    assert_eq!(JMP::absolute(START), next.to_vec(), "failed at {:04x}", pc);
+}
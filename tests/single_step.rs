@@ -0,0 +1,342 @@
+extern crate emulator;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::panic;
+use std::path::Path;
+
+use emulator::debug::disassembler::decode;
+use emulator::emulator::registers::Flag;
+use emulator::emulator::Emulator;
+use emulator::memory::default::DefaultMemory;
+
+// Run Tom Harte's SingleStepTests (https://github.com/SingleStepTests/65x02), which give the
+// state before and after a single instruction for ten thousand random cases per opcode.
+//
+// The tests are too large to keep in the repository. Point SINGLE_STEP_TESTS at a directory
+// holding the 6502 files (00.json to ff.json) to run them, for example:
+//
+//   SINGLE_STEP_TESTS=../65x02/6502/v1 cargo test --test single_step -- --ignored --nocapture
+//
+// Only documented opcodes are tested, and the cycle count is compared rather than each bus
+// access.
+#[test]
+#[ignore = "needs SINGLE_STEP_TESTS pointing at the 6502 test files"]
+fn single_step() {
+    let directory = env::var("SINGLE_STEP_TESTS").expect("SINGLE_STEP_TESTS is not set");
+
+    let mut failed = Vec::new();
+    for opcode in 0..=0xffu8 {
+        if decode(opcode).is_none() {
+            continue;
+        }
+
+        let path = Path::new(&directory).join(format!("{:02x}.json", opcode));
+        let text = fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("cannot read {}: {}", path.display(), e));
+
+        let cases = Json::parse(&text).expect("invalid JSON");
+        let cases = cases.array();
+        let mut failures = Vec::new();
+        for case in cases {
+            let failure = run_case(case);
+            // Every panic prints its message, and an opcode that panics once tends to panic for
+            // every case, so the rest of its cases are skipped.
+            let panicked = matches!(&failure, Some(f) if f.ends_with("panicked"));
+            failures.extend(failure);
+            if panicked {
+                break;
+            }
+        }
+
+        if !failures.is_empty() {
+            println!(
+                "{:02x}: {} of {} failed, first: {}",
+                opcode,
+                failures.len(),
+                cases.len(),
+                failures[0]
+            );
+            failed.push(opcode);
+        }
+    }
+
+    let failed: Vec<_> = failed.iter().map(|o| format!("{:02x}", o)).collect();
+    assert!(failed.is_empty(), "failed opcodes: {}", failed.join(" "));
+}
+
+#[test]
+fn single_step_sample() {
+    // Cases in the format of the test files, including one that wraps around the end of memory.
+    let text = r#"[
+        {"name": "bd ff ff", "initial": {"pc": 512, "s": 253, "a": 0, "x": 1, "y": 0, "p": 36,
+            "ram": [[512, 189], [513, 255], [514, 255], [0, 66]]},
+         "final": {"pc": 515, "s": 253, "a": 66, "x": 1, "y": 0, "p": 36,
+            "ram": [[512, 189], [513, 255], [514, 255], [0, 66]]},
+         "cycles": [[512, 189, "read"], [513, 255, "read"], [514, 255, "read"],
+            [65280, 0, "read"], [0, 66, "read"]]},
+        {"name": "48", "initial": {"pc": 65535, "s": 0, "a": 7, "x": 0, "y": 0, "p": 164,
+            "ram": [[65535, 72], [0, 1]]},
+         "final": {"pc": 0, "s": 255, "a": 7, "x": 0, "y": 0, "p": 164,
+            "ram": [[65535, 72], [0, 1], [256, 7]]},
+         "cycles": [[65535, 72, "read"], [0, 1, "read"], [256, 7, "write"]]}
+    ]"#;
+
+    let cases = Json::parse(text).unwrap();
+    for case in cases.array() {
+        assert_eq!(None, run_case(case));
+    }
+}
+
+/// Run a single case, and describe how it failed.
+fn run_case(case: &Json) -> Option<String> {
+    let name = case.get("name").string();
+    let initial = case.get("initial");
+    let expected = case.get("final");
+    let cycles = case.get("cycles").array().len() as u64;
+
+    let result = panic::catch_unwind(|| {
+        let mut memory = DefaultMemory::empty();
+        for entry in initial.get("ram").array() {
+            let entry = entry.array();
+            memory.memory[entry[0].number() as usize] = entry[1].number() as u8;
+        }
+
+        let mut emulator = Emulator::new(memory);
+        emulator.registers.program_counter = initial.get("pc").number() as u16;
+        emulator.registers.stack_pointer = initial.get("s").number() as u8;
+        emulator.registers.accumulator = initial.get("a").number() as u8;
+        emulator.registers.x = initial.get("x").number() as u8;
+        emulator.registers.y = initial.get("y").number() as u8;
+        set_status(&mut emulator, initial.get("p").number() as u8);
+
+        emulator.execute_next();
+        emulator
+    });
+
+    let emulator = match result {
+        Ok(emulator) => emulator,
+        Err(_) => return Some(format!("{}: panicked", name)),
+    };
+
+    let registers = &emulator.registers;
+    let actual = [
+        ("pc", registers.program_counter as u64),
+        ("s", registers.stack_pointer as u64),
+        ("a", registers.accumulator as u64),
+        ("x", registers.x as u64),
+        ("y", registers.y as u64),
+        // The break and unused bits only exist once pushed onto the stack.
+        ("p", (status(&emulator) & 0xcf) as u64),
+    ];
+    for (register, value) in actual.iter() {
+        let mut wanted = expected.get(register).number();
+        if *register == "p" {
+            wanted &= 0xcf;
+        }
+        if wanted != *value {
+            return Some(format!(
+                "{}: {} is {:02x}, expected {:02x}",
+                name, register, value, wanted
+            ));
+        }
+    }
+
+    for entry in expected.get("ram").array() {
+        let entry = entry.array();
+        let address = entry[0].number() as usize;
+        let value = emulator.memory.memory[address] as u64;
+        if value != entry[1].number() {
+            return Some(format!(
+                "{}: ${:04x} is {:02x}, expected {:02x}",
+                name,
+                address,
+                value,
+                entry[1].number()
+            ));
+        }
+    }
+
+    if emulator.cycles != cycles {
+        return Some(format!(
+            "{}: took {} cycles, expected {}",
+            name, emulator.cycles, cycles
+        ));
+    }
+
+    None
+}
+
+const FLAGS: [Flag; 8] = [
+    Flag::Carry,
+    Flag::Zero,
+    Flag::Interrupt,
+    Flag::Decimal,
+    Flag::Break,
+    Flag::Reserved,
+    Flag::Overflow,
+    Flag::Negative,
+];
+
+fn set_status(emulator: &mut Emulator<DefaultMemory>, p: u8) {
+    for (bit, flag) in FLAGS.iter().enumerate() {
+        emulator.registers.status.set_to(*flag, p & (1 << bit) != 0);
+    }
+}
+
+fn status(emulator: &Emulator<DefaultMemory>) -> u8 {
+    FLAGS
+        .iter()
+        .enumerate()
+        .filter(|(_, flag)| emulator.registers.status.get(**flag))
+        .map(|(bit, _)| 1 << bit)
+        .sum()
+}
+
+/// Just enough JSON for the test files.
+#[derive(Debug)]
+enum Json {
+    Number(u64),
+    String(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
+}
+
+impl Json {
+    fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            position: 0,
+        };
+        let value = parser.value()?;
+        parser.whitespace();
+        if parser.position != parser.bytes.len() {
+            return Err(format!("trailing characters at {}", parser.position));
+        }
+        Ok(value)
+    }
+
+    fn number(&self) -> u64 {
+        match self {
+            Json::Number(number) => *number,
+            _ => panic!("expected a number, found {:?}", self),
+        }
+    }
+
+    fn string(&self) -> &str {
+        match self {
+            Json::String(string) => string,
+            _ => panic!("expected a string, found {:?}", self),
+        }
+    }
+
+    fn array(&self) -> &[Json] {
+        match self {
+            Json::Array(array) => array,
+            _ => panic!("expected an array, found {:?}", self),
+        }
+    }
+
+    fn get(&self, key: &str) -> &Json {
+        match self {
+            Json::Object(object) => &object[key],
+            _ => panic!("expected an object, found {:?}", self),
+        }
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn whitespace(&mut self) {
+        while self.position < self.bytes.len() && self.bytes[self.position].is_ascii_whitespace() {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Result<u8, String> {
+        self.whitespace();
+        self.bytes
+            .get(self.position)
+            .cloned()
+            .ok_or_else(|| "unexpected end".to_string())
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        if self.peek()? != byte {
+            return Err(format!("expected '{}' at {}", byte as char, self.position));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        match self.peek()? {
+            b'[' => {
+                self.position += 1;
+                let mut array = Vec::new();
+                if self.peek()? == b']' {
+                    self.position += 1;
+                    return Ok(Json::Array(array));
+                }
+                loop {
+                    array.push(self.value()?);
+                    if self.peek()? == b']' {
+                        self.position += 1;
+                        return Ok(Json::Array(array));
+                    }
+                    self.expect(b',')?;
+                }
+            }
+            b'{' => {
+                self.position += 1;
+                let mut object = BTreeMap::new();
+                if self.peek()? == b'}' {
+                    self.position += 1;
+                    return Ok(Json::Object(object));
+                }
+                loop {
+                    let key = match self.value()? {
+                        Json::String(key) => key,
+                        _ => return Err(format!("expected a key at {}", self.position)),
+                    };
+                    self.expect(b':')?;
+                    object.insert(key, self.value()?);
+                    if self.peek()? == b'}' {
+                        self.position += 1;
+                        return Ok(Json::Object(object));
+                    }
+                    self.expect(b',')?;
+                }
+            }
+            b'"' => {
+                // The test files have no escapes in their strings.
+                self.position += 1;
+                let start = self.position;
+                while self.position < self.bytes.len() && self.bytes[self.position] != b'"' {
+                    self.position += 1;
+                }
+                let string = String::from_utf8_lossy(&self.bytes[start..self.position]);
+                self.expect(b'"')?;
+                Ok(Json::String(string.into_owned()))
+            }
+            b'0'..=b'9' => {
+                let mut number = 0;
+                while self.position < self.bytes.len() && self.bytes[self.position].is_ascii_digit()
+                {
+                    number = number * 10 + (self.bytes[self.position] - b'0') as u64;
+                    self.position += 1;
+                }
+                Ok(Json::Number(number))
+            }
+            byte => Err(format!(
+                "unexpected '{}' at {}",
+                byte as char, self.position
+            )),
+        }
+    }
+}
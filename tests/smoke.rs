@@ -1,6 +1,7 @@
 extern crate emulator;
 
 use emulator::emulator::instructions::opcodes::*;
+use emulator::emulator::run::StopReason;
 use emulator::emulator::Emulator;
 use emulator::memory::default::DefaultMemory;
 use emulator::memory::Memory;
@@ -43,11 +44,7 @@ fn smoke() {
 
     let mut emulator = Emulator::new(memory);
 
-    let mut program_counter = 0x0u16;
-    while program_counter != emulator.registers.program_counter {
-        program_counter = emulator.registers.program_counter;
-        emulator.execute_next();
-    }
+    assert_eq!(StopReason::Trap(0x405), emulator.run(1000));
 
     let r = ((emulator.memory.read(0x1) as u16) << 8) | (emulator.memory.read(0x0) as u16);
     assert_eq!(r, number * 2)